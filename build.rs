@@ -14,6 +14,9 @@ fn main() {
     // shared library.
     println!("cargo:rustc-link-lib=usb-1.0");
 
+    // HE-AAC v2 decoder for DAB+
+    println!("cargo:rustc-link-lib=fdk-aac");
+
     // The bindgen::Builder is the main entry point
     // to bindgen, and lets you build up options for
     // the resulting bindings.
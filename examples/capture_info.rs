@@ -0,0 +1,42 @@
+//! Describe a capture written with --file, or an older raw one.
+//!
+//!     cargo run --example capture_info -- capture.wf
+
+use std::env;
+use std::fs::File;
+use std::io::BufReader;
+use std::time::UNIX_EPOCH;
+
+use dab::source::capture::new_capture_reader;
+
+fn main() -> std::io::Result<()> {
+    let Some(path) = env::args().nth(1) else {
+        eprintln!("usage: capture_info FILE");
+        std::process::exit(1);
+    };
+
+    let mut reader = new_capture_reader(BufReader::new(File::open(&path)?))?;
+    match reader.header() {
+        Some(header) => {
+            let start = header.start.duration_since(UNIX_EPOCH).unwrap_or_default();
+            println!("version {}", header.version);
+            println!("source {:?}", header.source);
+            println!("frequency {:.3}MHz", header.frequency);
+            println!("started {}s after the epoch", start.as_secs());
+        }
+        None => println!("raw buffers, no header"),
+    }
+
+    let mut buffers = 0;
+    let mut last = None;
+    while let Ok((time, _)) = reader.read_buffer() {
+        buffers += 1;
+        last = time.or(last);
+    }
+    print!("{} buffers", buffers);
+    if let Some(last) = last {
+        print!(" over {:.1}s", last.as_secs_f64());
+    }
+    println!();
+    Ok(())
+}
@@ -0,0 +1,49 @@
+//! A stand-in rtl_tcp server, for trying the rtl-tcp source without a
+//! dongle: serves an unsigned 8 bit I/Q recording at 2.048 Msps to one
+//! client, printing the commands it sends.
+//!
+//!     cargo run --example rtl_tcp_replay -- recording.cu8 [127.0.0.1:1234]
+//!     dab-cli rtl-tcp --address 127.0.0.1:1234 -s c221
+
+use std::env;
+use std::fs::File;
+use std::io::BufReader;
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::thread;
+
+use dab::source::rtltcp::{SET_FREQUENCY, SET_GAIN, SET_GAIN_MODE, SET_SAMPLE_RATE, replay};
+
+fn main() -> std::io::Result<()> {
+    let mut args = env::args().skip(1);
+    let Some(path) = args.next() else {
+        eprintln!("usage: rtl_tcp_replay FILE [ADDRESS]");
+        std::process::exit(1);
+    };
+    let address = args.next().unwrap_or("127.0.0.1:1234".to_owned());
+    let file = BufReader::new(File::open(&path)?);
+
+    let listener = TcpListener::bind(&address)?;
+    println!("listening on {}", listener.local_addr()?);
+    let (stream, peer) = listener.accept()?;
+    println!("{} connected", peer);
+
+    let (commands_tx, commands_rx) = mpsc::channel();
+    thread::spawn(move || {
+        for (cmd, param) in commands_rx {
+            match cmd {
+                SET_FREQUENCY => println!("frequency {}Hz", param),
+                SET_SAMPLE_RATE => println!("sample rate {}", param),
+                SET_GAIN_MODE => {
+                    println!("gain {}", if param == 0 { "automatic" } else { "manual" })
+                }
+                SET_GAIN => println!("gain {:.1}dB", param as f64 / 10.0),
+                c => println!("command {:#04x} {}", c, param),
+            }
+        }
+    });
+
+    let sent = replay(stream, file, commands_tx)?;
+    println!("sent {} samples", sent);
+    Ok(())
+}
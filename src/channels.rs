@@ -0,0 +1,154 @@
+use core::fmt;
+
+use crate::wavefinder::tunable;
+
+/// A DAB channel by its standard label, and its centre frequency in MHz
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Channel {
+    pub name: &'static str,
+    pub frequency: f64,
+}
+
+const fn channel(name: &'static str, frequency: f64) -> Channel {
+    Channel { name, frequency }
+}
+
+/* Band III channels 5A to 13F, with the additional 10N, 11N and 12N */
+pub const BAND_III: [Channel; 41] = [
+    channel("5A", 174.928),
+    channel("5B", 176.640),
+    channel("5C", 178.352),
+    channel("5D", 180.064),
+    channel("6A", 181.936),
+    channel("6B", 183.648),
+    channel("6C", 185.360),
+    channel("6D", 187.072),
+    channel("7A", 188.928),
+    channel("7B", 190.640),
+    channel("7C", 192.352),
+    channel("7D", 194.064),
+    channel("8A", 195.936),
+    channel("8B", 197.648),
+    channel("8C", 199.360),
+    channel("8D", 201.072),
+    channel("9A", 202.928),
+    channel("9B", 204.640),
+    channel("9C", 206.352),
+    channel("9D", 208.064),
+    channel("10A", 209.936),
+    channel("10N", 210.096),
+    channel("10B", 211.648),
+    channel("10C", 213.360),
+    channel("10D", 215.072),
+    channel("11A", 216.928),
+    channel("11N", 217.088),
+    channel("11B", 218.640),
+    channel("11C", 220.352),
+    channel("11D", 222.064),
+    channel("12A", 223.936),
+    channel("12N", 224.096),
+    channel("12B", 225.648),
+    channel("12C", 227.360),
+    channel("12D", 229.072),
+    channel("13A", 230.784),
+    channel("13B", 232.496),
+    channel("13C", 234.208),
+    channel("13D", 235.776),
+    channel("13E", 237.488),
+    channel("13F", 239.200),
+];
+
+/* L-Band channels LA to LW */
+pub const L_BAND: [Channel; 23] = [
+    channel("LA", 1452.960),
+    channel("LB", 1454.672),
+    channel("LC", 1456.384),
+    channel("LD", 1458.096),
+    channel("LE", 1459.808),
+    channel("LF", 1461.520),
+    channel("LG", 1463.232),
+    channel("LH", 1464.944),
+    channel("LI", 1466.656),
+    channel("LJ", 1468.368),
+    channel("LK", 1470.080),
+    channel("LL", 1471.792),
+    channel("LM", 1473.504),
+    channel("LN", 1475.216),
+    channel("LO", 1476.928),
+    channel("LP", 1478.640),
+    channel("LQ", 1480.352),
+    channel("LR", 1482.064),
+    channel("LS", 1483.776),
+    channel("LT", 1485.488),
+    channel("LU", 1487.200),
+    channel("LV", 1488.912),
+    channel("LW", 1490.624),
+];
+
+// Every channel in the tables has to be within reach of Wavefinder::tune
+const _: () = {
+    let mut i = 0;
+    while i < BAND_III.len() {
+        assert!(tunable(BAND_III[i].frequency));
+        i += 1;
+    }
+    let mut i = 0;
+    while i < L_BAND.len() {
+        assert!(tunable(L_BAND[i].frequency));
+        i += 1;
+    }
+};
+
+/// Why a --frequency couldn't be used
+#[derive(Debug, Clone, PartialEq)]
+pub enum FrequencyError {
+    // neither a channel name nor a number of MHz
+    Unknown(String),
+    // a number of MHz the Wavefinder can't tune to
+    OutOfRange(f64),
+}
+
+impl fmt::Display for FrequencyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrequencyError::Unknown(s) => {
+                write!(
+                    f,
+                    "{} is not a channel (5A-13F, LA-LW) or a frequency in MHz",
+                    s
+                )
+            }
+            FrequencyError::OutOfRange(freq) => {
+                write!(f, "{}MHz is outside Band III and L-Band", freq)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FrequencyError {}
+
+/// The channel with this name, in either band, ignoring case
+pub fn find_channel(name: &str) -> Option<Channel> {
+    BAND_III
+        .iter()
+        .chain(L_BAND.iter())
+        .find(|c| c.name.eq_ignore_ascii_case(name))
+        .copied()
+}
+
+/// The frequency in MHz of a channel name or a number of MHz, checked to be
+/// one the Wavefinder can tune to
+pub fn parse_frequency(s: &str) -> Result<f64, FrequencyError> {
+    let s = s.trim();
+    if let Some(channel) = find_channel(s) {
+        return Ok(channel.frequency);
+    }
+    let freq: f64 = s
+        .parse()
+        .map_err(|_| FrequencyError::Unknown(s.to_owned()))?;
+    if tunable(freq) {
+        Ok(freq)
+    } else {
+        Err(FrequencyError::OutOfRange(freq))
+    }
+}
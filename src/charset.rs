@@ -0,0 +1,116 @@
+/* ETSI TS 101 756 V2.2.1 (2017-01), 5.2 Table 19: character set codes */
+pub const EBU_LATIN: u8 = 0;
+pub const UCS2: u8 = 6;
+pub const UTF8: u8 = 15;
+
+/* Annex C: Complete EBU Latin based repertoire. 0x20 to 0x7a are as ASCII
+ * apart from 0x24, 0x5c, 0x5e and 0x60; the control codes and everything
+ * above differ. */
+const EBU_LATIN_00_1F: [Option<char>; 32] = [
+    None,
+    Some('\u{0118}'),
+    Some('\u{012E}'),
+    Some('\u{0172}'),
+    Some('\u{0102}'),
+    Some('\u{0116}'),
+    Some('\u{010E}'),
+    Some('\u{0218}'),
+    Some('\u{021A}'),
+    Some('\u{010A}'),
+    // preferred line break, end of headline
+    None,
+    None,
+    Some('\u{0120}'),
+    Some('\u{0139}'),
+    Some('\u{017B}'),
+    Some('\u{0143}'),
+    Some('\u{0105}'),
+    Some('\u{0119}'),
+    Some('\u{012F}'),
+    Some('\u{0173}'),
+    Some('\u{0103}'),
+    Some('\u{0117}'),
+    Some('\u{010F}'),
+    Some('\u{0219}'),
+    Some('\u{021B}'),
+    Some('\u{010B}'),
+    Some('\u{0147}'),
+    Some('\u{011A}'),
+    Some('\u{0121}'),
+    Some('\u{013A}'),
+    Some('\u{017C}'),
+    // soft hyphen
+    None,
+];
+
+const EBU_LATIN_7B_FF: [char; 133] = [
+    '«', 'ů', '»', 'Ľ', 'Ħ', //
+    'á', 'à', 'é', 'è', 'í', 'ì', 'ó', 'ò', 'ú', 'ù', 'Ñ', 'Ç', 'Ş', 'ß', '¡', 'Ÿ', //
+    'â', 'ä', 'ê', 'ë', 'î', 'ï', 'ô', 'ö', 'û', 'ü', 'ñ', 'ç', 'ş', 'ğ', 'ı', 'ÿ', //
+    'Ķ', 'Ņ', '©', 'Ģ', 'Ğ', 'ě', 'ň', 'ő', 'Ő', '€', '£', '$', 'Ā', 'Ē', 'Ī', 'Ū', //
+    'ķ', 'ņ', 'Ļ', 'ģ', 'ļ', 'İ', 'ń', 'ű', 'Ű', '¿', 'ľ', '°', 'ā', 'ē', 'ī', 'ū', //
+    'Á', 'À', 'É', 'È', 'Í', 'Ì', 'Ó', 'Ò', 'Ú', 'Ù', 'Ř', 'Č', 'Š', 'Ž', 'Ð', 'Ŀ', //
+    'Â', 'Ä', 'Ê', 'Ë', 'Î', 'Ï', 'Ô', 'Ö', 'Û', 'Ü', 'ř', 'č', 'š', 'ž', 'đ', 'ŀ', //
+    'Ã', 'Å', 'Æ', 'Œ', 'ŷ', 'Ý', 'Õ', 'Ø', 'Þ', 'Ŋ', 'Ŕ', 'Ć', 'Ś', 'Ź', 'Ť', 'ð', //
+    'ã', 'å', 'æ', 'œ', 'ŵ', 'ý', 'õ', 'ø', 'þ', 'ŋ', 'ŕ', 'ć', 'ś', 'ź', 'ť', 'ħ', //
+];
+
+fn ebu_latin(byte: u8) -> Option<char> {
+    match byte {
+        0x00..=0x1f => EBU_LATIN_00_1F[byte as usize],
+        0x24 => Some('ł'),
+        0x5c => Some('Ů'),
+        0x5e => Some('Ł'),
+        0x60 => Some('Ą'),
+        0x20..=0x7a => Some(byte as char),
+        _ => Some(EBU_LATIN_7B_FF[(byte - 0x7b) as usize]),
+    }
+}
+
+/// Convert text broadcast in the given character set. Anything not
+/// understood is treated as EBU Latin, which the other Latin based
+/// sets are subsets of.
+pub fn decode(charset: u8, bytes: &[u8]) -> String {
+    match charset {
+        UCS2 => char::decode_utf16(
+            bytes
+                .chunks_exact(2)
+                .map(|c| u16::from_be_bytes([c[0], c[1]])),
+        )
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect(),
+        UTF8 => String::from_utf8_lossy(bytes).to_string(),
+        _ => bytes.iter().filter_map(|b| ebu_latin(*b)).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ebu_latin_table() {
+        let table = [
+            (0x01, Some('Ę')),
+            (0x0a, None),
+            (0x20, Some(' ')),
+            (0x23, Some('#')),
+            (0x24, Some('ł')),
+            (0x41, Some('A')),
+            (0x5b, Some('[')),
+            (0x5c, Some('Ů')),
+            (0x5d, Some(']')),
+            (0x5e, Some('Ł')),
+            (0x5f, Some('_')),
+            (0x60, Some('Ą')),
+            (0x7a, Some('z')),
+            (0x7b, Some('«')),
+            (0xa9, Some('€')),
+            (0xff, Some('ħ')),
+        ];
+        for (byte, c) in table {
+            assert_eq!(ebu_latin(byte), c, "0x{:02x}", byte);
+        }
+        assert_eq!(decode(EBU_LATIN, b"\x5eod\x60"), "ŁodĄ");
+    }
+}
@@ -1,6 +1,8 @@
+mod reedsolomon;
 mod viterbi;
 
 use itertools::Itertools;
+pub use reedsolomon::{ReedSolomon, new_reed_solomon};
 pub use viterbi::new_viterbi;
 pub use viterbi::{Bit, Viterbi};
 
@@ -190,3 +192,83 @@ pub fn crc16(bits: &[u8; 256]) -> bool {
 
     crc == CRC_GOOD
 }
+
+// CRC-16-CCITT over bytes, x^16 + x^12 + x^5 + 1, preset to all ones and
+// inverted, as used for DAB+ access units, X-PAD and MSC data groups.
+const CCITT_POLY: u16 = 0x1021;
+
+pub fn crc16_ccitt(bytes: &[u8]) -> u16 {
+    let mut crc: u16 = 0xffff;
+
+    for byte in bytes {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            if crc & 0x8000 != 0 {
+                crc = (crc << 1) ^ CCITT_POLY;
+            } else {
+                crc <<= 1;
+            }
+        }
+    }
+
+    !crc
+}
+
+// Check bytes carrying a trailing big-endian CRC-16-CCITT.
+pub fn crc16_ccitt_check(bytes: &[u8]) -> bool {
+    if bytes.len() < 2 {
+        return false;
+    }
+    let (data, crc) = bytes.split_at(bytes.len() - 2);
+    crc16_ccitt(data) == u16::from_be_bytes([crc[0], crc[1]])
+}
+
+// ETSI TS 102 563 V2.1.1 (2017-01), 5.2: the fire code protecting the
+// first bytes of a DAB+ audio super frame,
+// x^16 + x^14 + x^13 + x^12 + x^11 + x^5 + x^3 + x^2 + x + 1
+const FIRE_POLY: u16 = 0x782f;
+
+pub fn fire_code_check(bytes: &[u8; 11]) -> bool {
+    let mut crc: u16 = 0;
+
+    for byte in &bytes[2..11] {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            if crc & 0x8000 != 0 {
+                crc = (crc << 1) ^ FIRE_POLY;
+            } else {
+                crc <<= 1;
+            }
+        }
+    }
+
+    crc == u16::from_be_bytes([bytes[0], bytes[1]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fire_code() {
+        // x^16 mod the generator is the generator's lower terms
+        let mut bytes = [0x78, 0x2f, 0, 0, 0, 0, 0, 0, 0, 0, 0x01];
+        assert!(fire_code_check(&bytes));
+        bytes[5] ^= 0x10;
+        assert!(!fire_code_check(&bytes));
+
+        // the start of a super frame
+        let header = [0x20, 0x01, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        let mut bytes = [0; 11];
+        bytes[2..].copy_from_slice(&header);
+        // the remainder of the header times x^16, by long division
+        let mut remainder = header.iter().fold(0u128, |r, b| (r << 8) | *b as u128) << 16;
+        for i in (16..16 + 72).rev() {
+            if remainder & (1 << i) != 0 {
+                remainder ^= (0x1_0000 | FIRE_POLY as u128) << (i - 16);
+            }
+        }
+        bytes[0..2].copy_from_slice(&(remainder as u16).to_be_bytes());
+        assert!(fire_code_check(&bytes));
+    }
+}
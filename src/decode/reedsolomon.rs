@@ -0,0 +1,235 @@
+// Reed-Solomon decoder over GF(2^8), as used for the DAB+ outer code.
+//
+// ETSI TS 102 563 V2.1.1 (2017-01), 6.1: RS(120, 110, t = 5), shortened from
+// RS(255, 245), field generator x^8 + x^4 + x^3 + x^2 + 1, code generator
+// (x + a^0)(x + a^1)...(x + a^9).
+
+use crate::error::{Error, Result};
+
+/* Field generator polynomial */
+const PRIM_POLY: u16 = 0x11d;
+/* Full (unshortened) code length */
+const NN: usize = 255;
+
+pub struct ReedSolomon {
+    n: usize,
+    k: usize,
+    exp: [u8; 2 * NN],
+    log: [u8; NN + 1],
+}
+
+pub fn new_reed_solomon(n: usize, k: usize) -> Result<ReedSolomon> {
+    if k >= n || n > NN {
+        return Err(Error::Output("not a shortened RS(255, k) code"));
+    }
+    let mut rs = ReedSolomon {
+        n,
+        k,
+        exp: [0; 2 * NN],
+        log: [0; NN + 1],
+    };
+    rs.gen_tables();
+    Ok(rs)
+}
+
+impl ReedSolomon {
+    fn gen_tables(&mut self) {
+        let mut x: u16 = 1;
+        for i in 0..NN {
+            self.exp[i] = x as u8;
+            self.exp[i + NN] = x as u8;
+            self.log[x as usize] = i as u8;
+            x <<= 1;
+            if x & 0x100 != 0 {
+                x ^= PRIM_POLY;
+            }
+        }
+    }
+
+    fn mul(&self, a: u8, b: u8) -> u8 {
+        if a == 0 || b == 0 {
+            return 0;
+        }
+        self.exp[self.log[a as usize] as usize + self.log[b as usize] as usize]
+    }
+
+    fn div(&self, a: u8, b: u8) -> Result<u8> {
+        if b == 0 {
+            return Err(Error::Output("RS division by zero"));
+        }
+        if a == 0 {
+            return Ok(0);
+        }
+        Ok(self.exp[self.log[a as usize] as usize + NN - self.log[b as usize] as usize])
+    }
+
+    fn pow(&self, e: usize) -> u8 {
+        self.exp[e % NN]
+    }
+
+    // Evaluate a polynomial with coefficients in ascending order of power
+    fn eval(&self, poly: &[u8], x: u8) -> u8 {
+        poly.iter().rev().fold(0, |acc, c| self.mul(acc, x) ^ c)
+    }
+
+    /// Correct a codeword of length n in place, first byte being the highest
+    /// power. Returns the number of corrected bytes.
+    pub fn decode(&self, codeword: &mut [u8]) -> Result<usize> {
+        if codeword.len() != self.n {
+            return Err(Error::Output("RS codeword length"));
+        }
+        let nroots = self.n - self.k;
+
+        // Syndromes S_j = r(a^j)
+        let mut syndromes = vec![0u8; nroots];
+        for (j, s) in syndromes.iter_mut().enumerate() {
+            let x = self.pow(j);
+            *s = codeword.iter().fold(0, |acc, c| self.mul(acc, x) ^ c);
+        }
+        if syndromes.iter().all(|s| *s == 0) {
+            return Ok(0);
+        }
+
+        // Berlekamp-Massey for the error locator polynomial
+        let mut lambda = vec![0u8; nroots + 1];
+        let mut prev = vec![0u8; nroots + 1];
+        lambda[0] = 1;
+        prev[0] = 1;
+        let mut l = 0;
+        let mut m = 1;
+        let mut b = 1u8;
+
+        for r in 0..nroots {
+            let mut delta = syndromes[r];
+            for i in 1..=l {
+                delta ^= self.mul(lambda[i], syndromes[r - i]);
+            }
+
+            if delta == 0 {
+                m += 1;
+                continue;
+            }
+
+            let coef = self.div(delta, b)?;
+            let t = lambda.clone();
+            for i in m..=nroots {
+                lambda[i] ^= self.mul(coef, prev[i - m]);
+            }
+            if 2 * l <= r {
+                l = r + 1 - l;
+                prev = t;
+                b = delta;
+                m = 1;
+            } else {
+                m += 1;
+            }
+        }
+
+        if l > nroots / 2 {
+            return Err(Error::Output("too many RS errors"));
+        }
+
+        // Error evaluator polynomial Omega = S * Lambda mod x^nroots
+        let mut omega = vec![0u8; nroots];
+        for i in 0..nroots {
+            for j in 0..=i.min(l) {
+                omega[i] ^= self.mul(syndromes[i - j], lambda[j]);
+            }
+        }
+
+        // Formal derivative of Lambda
+        let dlambda: Vec<u8> = (1..=l)
+            .map(|i| if i % 2 == 1 { lambda[i] } else { 0 })
+            .collect();
+
+        // Chien search over the positions of the shortened code, and Forney
+        let mut corrected = 0;
+        for power in 0..self.n {
+            let xinv = self.pow(NN - power);
+            if self.eval(&lambda[0..=l], xinv) != 0 {
+                continue;
+            }
+            let denom = self.eval(&dlambda, xinv);
+            if denom == 0 {
+                return Err(Error::Output("uncorrectable RS codeword"));
+            }
+            let x = self.pow(power);
+            let magnitude = self.mul(x, self.div(self.eval(&omega, xinv), denom)?);
+            codeword[self.n - 1 - power] ^= magnitude;
+            corrected += 1;
+        }
+
+        if corrected != l {
+            // roots outside the shortened codeword
+            return Err(Error::Output("uncorrectable RS codeword"));
+        }
+
+        Ok(corrected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Systematic encoding: the data, then the remainder of dividing it by
+    // the code generator
+    fn encode(rs: &ReedSolomon, data: &[u8]) -> Vec<u8> {
+        let nroots = rs.n - rs.k;
+        // generator coefficients, highest power first
+        let mut generator = vec![1u8];
+        for i in 0..nroots {
+            let mut next = vec![0; generator.len() + 1];
+            for (j, c) in generator.iter().enumerate() {
+                next[j] ^= c;
+                next[j + 1] ^= rs.mul(*c, rs.pow(i));
+            }
+            generator = next;
+        }
+
+        let mut parity = vec![0u8; nroots];
+        for d in data {
+            let feedback = d ^ parity.remove(0);
+            parity.push(0);
+            for (p, g) in parity.iter_mut().zip(&generator[1..]) {
+                *p ^= rs.mul(feedback, *g);
+            }
+        }
+        [data, &parity].concat()
+    }
+
+    #[test]
+    fn corrects_up_to_five_errors() {
+        let rs = new_reed_solomon(120, 110).unwrap();
+        let data: Vec<u8> = (0..110).map(|i| (i * 37 + 11) as u8).collect();
+        let codeword = encode(&rs, &data);
+
+        let mut received = codeword.clone();
+        assert_eq!(rs.decode(&mut received), Ok(0));
+
+        // in the data and the parity, at both ends
+        for errors in 1..=5 {
+            let mut received = codeword.clone();
+            for (e, position) in [0, 119, 57, 110, 3].iter().take(errors).enumerate() {
+                received[*position] ^= 0x5a + e as u8;
+            }
+            assert_eq!(rs.decode(&mut received), Ok(errors));
+            assert_eq!(received, codeword);
+        }
+    }
+
+    #[test]
+    fn reports_too_many_errors() {
+        let rs = new_reed_solomon(120, 110).unwrap();
+        let data: Vec<u8> = (0..110).map(|i| (i * 37 + 11) as u8).collect();
+        let mut received = encode(&rs, &data);
+        for position in [0, 20, 40, 60, 80, 100] {
+            received[position] ^= 0xff;
+        }
+        assert!(rs.decode(&mut received).is_err());
+
+        assert!(rs.decode(&mut [0; 119]).is_err());
+        assert!(new_reed_solomon(256, 246).is_err());
+        assert!(new_reed_solomon(120, 120).is_err());
+    }
+}
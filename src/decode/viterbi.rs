@@ -167,6 +167,12 @@ impl Viterbi {
 
     /// Viterbi decoder core.
     pub fn viterbi(&self, bits: &[Bit]) -> Vec<u8> {
+        self.viterbi_metric(bits).0
+    }
+
+    /// Viterbi decode, also returning the metric of the surviving path: 3
+    /// for each received bit it agrees with, -7 for each it doesn't.
+    pub fn viterbi_metric(&self, bits: &[Bit]) -> (Vec<u8>, i32) {
         let nbits = bits.len() / N - (K - 1);
 
         // output
@@ -306,6 +312,40 @@ impl Viterbi {
             endstate >>= 1;
         }
 
+        (result, nmetric[0])
+    }
+
+    /// Convolutionally encode the bits, followed by the six zero tail bits,
+    /// into the mother code of ETSI EN 300 401 V2.1.1 (2017-01), 11.1.1.
+    pub fn encode(&self, bits: &[u8]) -> Vec<u8> {
+        let mut result = Vec::with_capacity((bits.len() + K - 1) * N);
+        let mut sr = 0usize;
+        for &bit in bits.iter().chain([0u8; K - 1].iter()) {
+            sr = ((sr << 1) | bit as usize) & (SYMS_SZ - 1);
+            for &p in POLYS.iter() {
+                result.push(parity(sr & p) as u8);
+            }
+        }
         result
     }
+
+    /// Estimate the channel bit errors by re-encoding the decoded bits and
+    /// comparing them with those received, skipping the punctured ones.
+    /// Returns the number of differing bits and the number compared.
+    pub fn bit_errors(&self, received: &[Bit], decoded: &[u8]) -> (usize, usize) {
+        let mut errors = 0;
+        let mut compared = 0;
+        for (rx, tx) in received.iter().zip(self.encode(decoded)) {
+            let rx = match rx {
+                Bit::False => 0,
+                Bit::True => 1,
+                Bit::Erased => continue,
+            };
+            compared += 1;
+            if rx != tx {
+                errors += 1;
+            }
+        }
+        (errors, compared)
+    }
 }
@@ -0,0 +1,50 @@
+use core::fmt;
+
+/// Something wrong with the received data, by the stage that found it.
+/// These are expected on a weak signal: the data is skipped and counted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    Fig(&'static str),
+    Msc(&'static str),
+    Pad(&'static str),
+    Output(&'static str),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Fig(e) => write!(f, "FIG: {}", e),
+            Error::Msc(e) => write!(f, "MSC: {}", e),
+            Error::Pad(e) => write!(f, "PAD: {}", e),
+            Error::Output(e) => write!(f, "output: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Errors skipped so far, by stage
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ErrorCounts {
+    pub fig: u32,
+    pub msc: u32,
+    pub pad: u32,
+    pub output: u32,
+}
+
+impl ErrorCounts {
+    pub fn count(&mut self, error: &Error) {
+        match error {
+            Error::Fig(_) => self.fig += 1,
+            Error::Msc(_) => self.msc += 1,
+            Error::Pad(_) => self.pad += 1,
+            Error::Output(_) => self.output += 1,
+        }
+    }
+
+    pub fn total(&self) -> u32 {
+        self.fig + self.msc + self.pad + self.output
+    }
+}
@@ -0,0 +1,357 @@
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use crate::Cli;
+use crate::decode::crc16_ccitt;
+use crate::error::ErrorCounts;
+use crate::fic::decoder::FastInformationChannelDecoder;
+use crate::fic::ensemble::{Acquisition, Ensemble, SubChannelOrganisation, new_ensemble};
+use crate::fic::{FastInformationChannelBuffer, new_decoder};
+use crate::msc::multiplex::{Multiplex, new_multiplex};
+use crate::msc::tables::ProtectionProfile;
+use crate::receiver::new_source;
+use crate::stats::ReceptionStats;
+use crate::wavefinder::Buffer;
+
+/* ETSI EN 300 799 (1997-09), 5: ETI(NI, G.703), one frame of 6144 bytes
+for each 24ms CIF, padded after the TIST */
+pub const FRAME_SIZE: usize = 6144;
+const ERR_NONE: u8 = 0xff;
+// alternating from frame to frame
+const FSYNC: [[u8; 3]; 2] = [[0x07, 0x3a, 0xb6], [0xf8, 0xc5, 0x49]];
+// Transmission Mode I, 3 FIBs in 32 bit words
+const MID: u16 = 1;
+const FICL: usize = 24;
+// Transmission Mode III, 4 FIBs
+const FICL_MODE_III: usize = 32;
+const FCT_MAX: u8 = 250;
+const PADDING: u8 = 0x55;
+
+// a logical frame is time interleaved over this many CIFs
+const DEPTH: usize = 16;
+
+// How often progress is reported while recording
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+// Stream length, in 64 bit words per CIF
+fn stl(org: &SubChannelOrganisation) -> usize {
+    org.bitrate as usize * 3 / 8
+}
+
+// Type and protection level: 01 and the UEP level, or 10, the EEP option and
+// level
+fn tpl(profile: &ProtectionProfile) -> u8 {
+    match profile {
+        ProtectionProfile::UEP(uep) => 0x10 | (uep.ProtLvl - 1),
+        ProtectionProfile::EEP(eep) => 0x20 | (eep.Opt << 2) | eep.ProtLvl,
+    }
+}
+
+/// An ETI(NI) frame of the FIC, if there is one, and a logical frame for
+/// each subchannel. Streams shorter than the subchannel's bitrate are
+/// filled with zeros.
+pub fn eti_frame(
+    fct: u8,
+    fic: Option<&[u8]>,
+    streams: &[(SubChannelOrganisation, Vec<u8>)],
+) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(FRAME_SIZE);
+
+    // SYNC: ERR, FSYNC
+    frame.push(ERR_NONE);
+    frame.extend_from_slice(&FSYNC[fct as usize % 2]);
+
+    // FC: FCT, FICF, NST, FP, MID, FL
+    let ficl = if fic.is_some() { FICL } else { 0 };
+    let fl = streams.len() + 1 + ficl + streams.iter().map(|(org, _)| 2 * stl(org)).sum::<usize>();
+    frame.push(fct);
+    frame.push(((fic.is_some() as u8) << 7) | streams.len() as u8);
+    let fp = (fct % 8) as u16;
+    frame.extend_from_slice(&((fp << 13) | (MID << 11) | fl as u16).to_be_bytes());
+
+    // STC: SCID, SAD, TPL, STL for each stream
+    for (org, _) in streams {
+        let stl = stl(org) as u16;
+        frame.push((org.SubChId << 2) | (org.start >> 8) as u8);
+        frame.push(org.start as u8);
+        frame.push((tpl(&org.profile) << 2) | (stl >> 8) as u8);
+        frame.push(stl as u8);
+    }
+
+    // EOH: MNSC unused, CRC from FC
+    frame.extend_from_slice(&[0, 0]);
+    let crc = crc16_ccitt(&frame[4..]);
+    frame.extend_from_slice(&crc.to_be_bytes());
+
+    // MST: FIC then the streams
+    let mst = frame.len();
+    if let Some(fic) = fic {
+        frame.extend_from_slice(fic);
+    }
+    for (org, data) in streams {
+        let end = frame.len() + stl(org) * 8;
+        frame.extend(data.iter().take(stl(org) * 8));
+        frame.resize(end, 0);
+    }
+
+    // EOF: CRC of the MST, RFU
+    let crc = crc16_ccitt(&frame[mst..]);
+    frame.extend_from_slice(&crc.to_be_bytes());
+    frame.extend_from_slice(&[0xff, 0xff]);
+
+    // TIST: no timestamp
+    frame.extend_from_slice(&[0xff; 4]);
+
+    frame.resize(FRAME_SIZE, PADDING);
+    frame
+}
+
+/// A stream of an ETI frame: the subchannel's logical frame for one CIF
+#[derive(Debug, Clone)]
+pub struct EtiStream {
+    pub scid: u8,
+    // start address in CUs
+    pub sad: u16,
+    pub tpl: u8,
+    pub data: Vec<u8>,
+}
+
+/// An ETI frame read back, already decoded so nothing is left but to
+/// parse the FIBs and streams
+#[derive(Debug, Clone)]
+pub struct EtiFrame {
+    pub fct: u8,
+    // the FIBs with their CRCs, empty when the frame has no FIC
+    pub fic: Vec<u8>,
+    pub streams: Vec<EtiStream>,
+}
+
+impl EtiFrame {
+    pub fn stream(&self, sad: u16) -> Option<&EtiStream> {
+        self.streams.iter().find(|s| s.sad == sad)
+    }
+}
+
+/// Parse an ETI(NI) frame, None if it isn't one or either CRC fails
+pub fn parse_eti_frame(bytes: &[u8]) -> Option<EtiFrame> {
+    if bytes.len() < FRAME_SIZE || !FSYNC.iter().any(|f| bytes[1..4] == *f) {
+        return None;
+    }
+
+    let fct = bytes[4];
+    let ficf = bytes[5] >> 7;
+    let nst = (bytes[5] & 0x7f) as usize;
+    let mid = (bytes[6] >> 3) & 0x03;
+    let fl = (u16::from_be_bytes([bytes[6], bytes[7]]) & 0x07ff) as usize;
+
+    let eoh = 8 + 4 * nst;
+    let crc = u16::from_be_bytes([bytes[eoh + 2], bytes[eoh + 3]]);
+    if crc != crc16_ccitt(&bytes[4..eoh + 2]) {
+        // println!("ETI header CRC failed in frame {}", fct);
+        return None;
+    }
+
+    // FL counts the STC and EOH words too
+    let mst = eoh + 4;
+    let end = mst + fl.checked_sub(nst + 1)? * 4;
+    if end + 4 > FRAME_SIZE {
+        return None;
+    }
+    let crc = u16::from_be_bytes([bytes[end], bytes[end + 1]]);
+    if crc != crc16_ccitt(&bytes[mst..end]) {
+        // println!("ETI MST CRC failed in frame {}", fct);
+        return None;
+    }
+
+    let ficl = match (ficf, mid) {
+        (0, _) => 0,
+        (_, 3) => FICL_MODE_III,
+        _ => FICL,
+    };
+    let fic = bytes[mst..mst + ficl * 4].to_vec();
+
+    let mut offset = mst + ficl * 4;
+    let mut streams = Vec::with_capacity(nst);
+    for stc in bytes[8..eoh].chunks(4) {
+        let stl = (((stc[2] & 0x03) as usize) << 8) | stc[3] as usize;
+        if offset + stl * 8 > end {
+            return None;
+        }
+        streams.push(EtiStream {
+            scid: stc[0] >> 2,
+            sad: (((stc[0] & 0x03) as u16) << 8) | stc[1] as u16,
+            tpl: stc[2] >> 2,
+            data: bytes[offset..offset + stl * 8].to_vec(),
+        });
+        offset += stl * 8;
+    }
+
+    Some(EtiFrame { fct, fic, streams })
+}
+
+/// Builds ETI frames from symbol buffers, following the FIC to know the
+/// subchannels. The MSC takes 15 CIFs to time deinterleave, so the FIC is
+/// held back to go out with the CIF it came with.
+pub struct EtiMultiplexer {
+    fic_decoder: FastInformationChannelDecoder,
+    msc: Multiplex,
+    // the FIBs of the latest DAB frame as received, and its frame number
+    fibs: Option<(u8, Vec<[u8; 32]>)>,
+    // the FIC of each CIF held in the MSC
+    fic: VecDeque<Vec<u8>>,
+    fct: u8,
+}
+
+pub fn new_eti_multiplexer() -> EtiMultiplexer {
+    EtiMultiplexer {
+        fic_decoder: new_decoder(),
+        msc: new_multiplex(),
+        fibs: None,
+        fic: VecDeque::with_capacity(DEPTH),
+        fct: 0,
+    }
+}
+
+impl EtiMultiplexer {
+    /// Add a symbol, returning the next ETI frame once the MSC for it has
+    /// been deinterleaved
+    pub fn try_buffer(
+        &mut self,
+        buffer: &Buffer,
+        ens: &mut Ensemble,
+        stats: &mut ReceptionStats,
+        errors: &mut ErrorCounts,
+    ) -> Option<Vec<u8>> {
+        if let Ok(fic_buffer) = TryInto::<FastInformationChannelBuffer>::try_into(buffer)
+            && let Some(fic) = self.fic_decoder.try_buffer_raw(fic_buffer, stats)
+        {
+            // FIGs only from the FIBs passing their CRC, but all of them go
+            // out as received for the ETI's receiver to check
+            let frame = buffer.bytes[3];
+            for fib in self.fic_decoder.try_fibs(&fic.concat(), frame, stats) {
+                for fig in self.fic_decoder.extract_figs(&fib) {
+                    match fig {
+                        Ok(fig) => ens.add_fig(fig),
+                        Err(e) => errors.count(&e),
+                    }
+                }
+            }
+            self.fibs = Some((frame, fic));
+        }
+
+        let cif = self.msc.try_buffer(buffer)?;
+        let fic = match &self.fibs {
+            Some((frame, fibs)) if *frame == cif.frame => {
+                let first = cif.cif as usize * 3;
+                fibs[first..first + 3].concat()
+            }
+            // the FIC symbols weren't received, FIBs failing their CRC keep
+            // the frame length the same
+            _ => vec![0; FICL * 4],
+        };
+        self.fic.push_back(fic);
+        while self.fic.len() > cif.contiguous {
+            self.fic.pop_front();
+        }
+        if cif.contiguous < DEPTH {
+            return None;
+        }
+
+        let fic = self.fic.pop_front();
+        let streams = ens
+            .subchannels()
+            .into_iter()
+            .map(|org| match self.msc.decode(&org) {
+                Ok(data) => (org, data),
+                Err(e) => {
+                    errors.count(&e);
+                    (org, vec![])
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let frame = eti_frame(self.fct, fic.as_deref(), &streams);
+        self.fct = (self.fct + 1) % FCT_MAX;
+        Some(frame)
+    }
+}
+
+pub struct EtiRecorder {
+    args: Cli,
+}
+
+pub fn new_eti_recorder(args: Cli) -> EtiRecorder {
+    EtiRecorder { args }
+}
+
+impl EtiRecorder {
+    /// Write ETI frames until the source ends, from when the subchannels
+    /// are known. Progress is reported with the ensemble and the number
+    /// of frames written.
+    pub fn run<F>(&mut self, out: &mut dyn Write, mut progress: F) -> io::Result<u32>
+    where
+        F: FnMut(&Ensemble, u32),
+    {
+        let mut source = new_source(&self.args)?;
+        let (source_rx, source_t) = source.run();
+
+        let mut mux = new_eti_multiplexer();
+        let mut ens = new_ensemble();
+        let mut stats = ReceptionStats::default();
+        let mut errors = ErrorCounts::default();
+        let mut written = 0;
+        let mut last_report = Instant::now();
+
+        let result = loop {
+            let Ok(buffer) = source_rx.recv() else {
+                break Ok(());
+            };
+            if buffer.last {
+                break Ok(());
+            }
+            if let Some(frame) = mux.try_buffer(&buffer, &mut ens, &mut stats, &mut errors)
+                && ens.acquisition() >= Acquisition::Organised
+            {
+                if let Err(e) = out.write_all(&frame) {
+                    break Err(e);
+                }
+                written += 1;
+            }
+            if last_report.elapsed() >= REPORT_INTERVAL {
+                progress(&ens, written);
+                last_report = Instant::now();
+            }
+        };
+
+        source.exit();
+        let _ = source_t.join();
+        result?;
+        out.flush()?;
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fic_with_a_bad_fib() {
+        // empty FIBs, the second corrupted after its CRC was worked out
+        let mut fic = vec![];
+        for _ in 0..3 {
+            let mut fib = [0xff; 32];
+            let crc = crc16_ccitt(&fib[..30]);
+            fib[30..].copy_from_slice(&crc.to_be_bytes());
+            fic.extend(fib);
+        }
+        fic[40] ^= 0x10;
+
+        let frame = parse_eti_frame(&eti_frame(0, Some(&fic), &[])).unwrap();
+        assert_eq!(frame.fic, fic);
+        let mut stats = ReceptionStats::default();
+        let fibs = new_decoder().try_fibs(&frame.fic, frame.fct, &mut stats);
+        assert_eq!(fibs.len(), 2);
+    }
+}
@@ -4,10 +4,12 @@ use std::fmt;
 
 use crate::{
     decode::{
-        Viterbi, bit_reverse, bits_to_bytes, bytes_to_bits, crc16, depuncture, new_viterbi,
-        qpsk_symbol_demapper, scramble,
+        Viterbi, bit_reverse, bits_to_bytes, bytes_to_bits, crc16, crc16_ccitt_check, depuncture,
+        new_viterbi, qpsk_symbol_demapper, scramble,
     },
+    error::Result,
     fic::new_frame,
+    stats::ReceptionStats,
 };
 
 use super::{
@@ -50,13 +52,43 @@ impl FastInformationChannelDecoder {
     pub fn try_buffer(
         &mut self,
         buffer: FastInformationChannelBuffer,
+        stats: &mut ReceptionStats,
     ) -> Option<Vec<FastInformationBlock>> {
+        let frame = self.complete_frame(buffer)?;
+        // None if a CRC check failed
+        self.decode_and_crc(&frame, stats).ok()
+    }
+
+    /// Every FIB of the frame as received, 32 bytes each with its CRC,
+    /// whether or not that passes. The CRCs are left to `try_fibs`.
+    pub fn try_buffer_raw(
+        &mut self,
+        buffer: FastInformationChannelBuffer,
+        stats: &mut ReceptionStats,
+    ) -> Option<Vec<[u8; 32]>> {
+        let frame = self.complete_frame(buffer)?;
+        let fibs = self.decode(&frame, stats);
+        Some(
+            fibs.iter()
+                .map(|fib| bits_to_bytes(fib).try_into().unwrap())
+                .collect(),
+        )
+    }
+
+    // The frame once its last symbol is added, decoded once, whether or not
+    // the CRC passes
+    fn complete_frame(
+        &mut self,
+        buffer: FastInformationChannelBuffer,
+    ) -> Option<FastInformationChannelFrame> {
         let mut frame;
 
-        if let Some(f) = self.frames[buffer.frame as usize] {
-            frame = f;
-        } else if buffer.symbol == 2 {
+        // the first symbol always starts the frame afresh, the slot may hold
+        // one from 32 frames ago
+        if buffer.symbol == 2 {
             frame = new_frame(buffer.frame);
+        } else if let Some(f) = self.frames[buffer.frame as usize] {
+            frame = f;
         } else {
             // println!(
             //     "can't handle frame {:?} symbol {:?} right now",
@@ -71,20 +103,36 @@ impl FastInformationChannelDecoder {
         }
 
         if frame.next_symbol > 4 {
-            if let Ok(blocks) = self.decode_and_crc(&frame) {
-                return Some(blocks);
-            } else {
-                // CRC check failed
-                // println!("frame {:?} failed crc", frame.frame_number);
-                self.frames[frame.frame_number as usize] = None;
-                return None;
-            }
+            self.frames[frame.frame_number as usize] = None;
+            return Some(frame);
         }
 
         // Not enough symbols yet
         None
     }
 
+    /// FIBs already decoded elsewhere, 32 bytes each with their CRC. Only
+    /// those passing the CRC are returned.
+    pub fn try_fibs(
+        &self,
+        bytes: &[u8],
+        frame_number: u8,
+        stats: &mut ReceptionStats,
+    ) -> Vec<FastInformationBlock> {
+        bytes
+            .chunks_exact(32)
+            .filter(|fib| {
+                let ok = crc16_ccitt_check(fib);
+                stats.fib(ok);
+                ok
+            })
+            .map(|fib| FastInformationBlock {
+                bytes: fib[0..30].try_into().unwrap(),
+                num: frame_number,
+            })
+            .collect()
+    }
+
     fn append_data(
         &self,
         frame: &mut FastInformationChannelFrame,
@@ -95,10 +143,12 @@ impl FastInformationChannelDecoder {
         frame.next_symbol = buffer.symbol + 1;
     }
 
-    fn decode_and_crc(
+    // The 12 FIBs of the frame, as bits
+    fn decode(
         &self,
         frame: &FastInformationChannelFrame,
-    ) -> Result<Vec<FastInformationBlock>, &'static str> {
+        stats: &mut ReceptionStats,
+    ) -> [[u8; 256]; 12] {
         let mut merged: [u8; 9216] = [0; 9216];
 
         for (i, sym) in frame.bytes.iter().enumerate() {
@@ -116,21 +166,37 @@ impl FastInformationChannelDecoder {
             split.copy_from_slice(&merged[(i * 2304)..((i + 1) * 2304)]);
             let depunctured = depuncture(&split);
             let viterbied = self.viterbi.viterbi(&depunctured);
+            let (errors, compared) = self.viterbi.bit_errors(&depunctured, &viterbied);
+            stats.bit_errors += errors as u32;
+            stats.coded_bits += compared as u32;
             let scrambled = scramble(&viterbied);
             // Split into FIBs
             for j in 0..3 {
                 fibs[i * 3 + j].copy_from_slice(&scrambled[(j * 256)..(j * 256 + 256)]);
             }
         }
+        fibs
+    }
+
+    fn decode_and_crc(
+        &self,
+        frame: &FastInformationChannelFrame,
+        stats: &mut ReceptionStats,
+    ) -> Result<Vec<FastInformationBlock>, &'static str> {
+        let fibs = self.decode(frame, stats);
+
+        // Check CRCs, all of them so the failure rate is counted
+        let crcs = fibs.map(|fib| crc16(&fib));
+        for ok in crcs {
+            stats.fib(ok);
+        }
+        if crcs.contains(&false) {
+            return Err("crc check failed");
+        }
 
         let mut fib_bytes: [[u8; 30]; 12] = [[0_u8; 30]; 12];
 
         for i in 0..12 {
-            // Check CRC
-            if !crc16(&fibs[i]) {
-                return Err("crc check failed");
-            }
-
             // If OK, convert to bytes, first 30 only.
             fib_bytes[i].copy_from_slice(&bits_to_bytes(&fibs[i])[0..30]);
         }
@@ -144,7 +210,8 @@ impl FastInformationChannelDecoder {
         Ok(blocks)
     }
 
-    pub fn extract_figs(&self, fib: &FastInformationBlock) -> Vec<Fig> {
+    /// The FIGs in the FIB, with an error for any that can't be parsed
+    pub fn extract_figs(&self, fib: &FastInformationBlock) -> Vec<Result<Fig>> {
         // println!("fib num: {:?}\n{}", fib.num, pretty_hex(&fib.bytes));
 
         let fig_iter = fib.bytes.iter().batching(|it| {
@@ -155,8 +222,7 @@ impl FastInformationChannelDecoder {
                 }
                 if let Some(mut fig) = fig_header(*h) {
                     let body = it.take(fig.header.len);
-                    fig.push_data(body.copied().collect());
-                    return Some(fig);
+                    return Some(fig.push_data(body.copied().collect()).map(|_| fig));
                 }
             }
             None
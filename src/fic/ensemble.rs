@@ -1,38 +1,261 @@
 #![allow(non_snake_case)]
 
+use core::fmt;
 use itertools::Itertools;
 use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use super::fig::{Fig, FigType, Information, LabelPurpose, ServiceComponent};
+use super::fig::{
+    ComponentId, FI_DAB, FI_FM_RDS, Fig, FigType, Information, LabelPurpose, ServiceComponent,
+    UserApplication,
+};
+use super::pty;
 
-use crate::msc::tables::{UEPTABLE, UepProf};
+use crate::error::{Error, Result};
+use crate::msc::tables::{EepProf, ProtectionProfile, UepProf, eep_profile, uep_profile};
 
 #[derive(Clone)]
 pub struct Ensemble {
     id: u16,
     name: String,
+    short_name: String,
     services: HashMap<u32, Service>,
-    label_tries: u16,
+    acquisition: Acquisition,
+    // SId of the FIG 0/2 to see again for a full repetition cycle
+    cycle_start: Option<u32>,
+    cycle_seen: bool,
+    changed: bool,
+    next: Box<Reconfiguration>,
+    reconfigured: bool,
+    ecc: Option<u8>,
+    lto: Option<i8>,
+    inter_table_id: Option<u8>,
+    date_time: Option<DateTime>,
+    date_time_cif: u16,
+    cif_count: Option<u16>,
+    announcements: HashMap<u8, Announcement>,
+    linkage_sets: HashMap<(u16, bool, bool), LinkageSet>,
+    dab_frequencies: HashMap<u16, Vec<u32>>,
+    fm_frequencies: HashMap<u16, Vec<u32>>,
+    oe_services: HashMap<u32, Vec<u16>>,
+}
+
+/// How much of the ensemble's configuration has been received from the FIC.
+/// Each state implies the ones before it.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Acquisition {
+    Searching,
+    // FIG 0/0 and services from FIG 0/2
+    Mci,
+    // FIG 0/1 for every subchannel of the services
+    Organised,
+    // FIG 1 labels for the ensemble and every service
+    Labelled,
+    // and FIG 0/2 has been through a whole repetition cycle with no new
+    // services
+    Complete,
+}
+
+/* ETSI EN 300 401 V2.1.1 (2017-01), 6.5: the next multiplex configuration,
+from FIG 0/1 and 0/2 with the C/N flag set, staged until the CIF signalled by
+OccChg in FIG 0/0 */
+#[derive(Clone, Debug, Default)]
+struct Reconfiguration {
+    services: HashMap<u32, Vec<ServiceComponent>>,
+    subchannels: HashMap<u8, SubChannelOrganisation>,
+    // CIF count the change was first signalled at, and CIFs from then
+    change_at: Option<(u16, u16)>,
+}
+
+/* ETSI EN 300 401 V2.1.1 (2017-01), 5.3: the CIF count runs through
+20 x 250 CIFs of 24ms each */
+const CIF_COUNT_MAX: u16 = 5000;
+const CIF_MS: u64 = 24;
+
+const MS_PER_DAY: u64 = 24 * 60 * 60 * 1000;
+/* MJD of 1970-01-01 */
+const MJD_UNIX_EPOCH: u32 = 40587;
+
+/// FIG 0/10 date and time, UTC unless offset to local time
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DateTime {
+    pub mjd: u32,
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+    pub milliseconds: u16,
+}
+
+impl DateTime {
+    fn from_millis(ms: u64) -> Self {
+        let day = ms % MS_PER_DAY;
+        Self {
+            mjd: (ms / MS_PER_DAY) as u32,
+            hours: (day / 3_600_000) as u8,
+            minutes: (day / 60_000 % 60) as u8,
+            seconds: (day / 1000 % 60) as u8,
+            milliseconds: (day % 1000) as u16,
+        }
+    }
+
+    // Milliseconds since MJD 0
+    fn millis(&self) -> u64 {
+        self.mjd as u64 * MS_PER_DAY
+            + self.hours as u64 * 3_600_000
+            + self.minutes as u64 * 60_000
+            + self.seconds as u64 * 1000
+            + self.milliseconds as u64
+    }
+
+    pub fn add_millis(&self, ms: i64) -> Self {
+        DateTime::from_millis(self.millis().saturating_add_signed(ms))
+    }
+
+    /// Calendar date, from the MJD conversion of ETSI EN 300 468 Annex C
+    pub fn ymd(&self) -> (i32, u8, u8) {
+        let mjd = self.mjd as f64;
+        let y = ((mjd - 15078.2) / 365.25) as i32;
+        let m = ((mjd - 14956.1 - (y as f64 * 365.25).trunc()) / 30.6001) as i32;
+        let d = self.mjd as i32 - 14956 - (y as f64 * 365.25) as i32 - (m as f64 * 30.6001) as i32;
+        let k = if m == 14 || m == 15 { 1 } else { 0 };
+        (y + k + 1900, (m - 1 - k * 12) as u8, d as u8)
+    }
+
+    // None before 1970, which only a corrupt FIG 0/10 would give
+    pub fn system_time(&self) -> Option<SystemTime> {
+        let unix = self
+            .millis()
+            .checked_sub(MJD_UNIX_EPOCH as u64 * MS_PER_DAY)?;
+        Some(UNIX_EPOCH + Duration::from_millis(unix))
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (year, month, day) = self.ymd();
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03}",
+            year, month, day, self.hours, self.minutes, self.seconds, self.milliseconds
+        )
+    }
+}
+
+/* ETSI EN 300 401 V2.1.1 (2017-01), 8.1.6.1 Table 14: announcement types,
+by their bit in the ASu and ASw flags */
+pub const ANNOUNCEMENT_TYPES: [&str; 11] = [
+    "Alarm",
+    "Road Traffic flash",
+    "Transport flash",
+    "Warning/Service",
+    "News flash",
+    "Area weather flash",
+    "Event announcement",
+    "Special event",
+    "Programme Information",
+    "Sport report",
+    "Financial report",
+];
+
+pub fn announcement_types(flags: u16) -> Vec<&'static str> {
+    ANNOUNCEMENT_TYPES
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| flags & (1 << i) != 0)
+        .map(|(_, t)| *t)
+        .collect()
+}
+
+/// An announcement in progress in a cluster, from FIG 0/19
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Announcement {
+    pub cluster: u8,
+    pub asw: u16,
+    pub new: bool,
+    pub subchid: u8,
+    pub region: Option<u8>,
+}
+
+/* ETSI EN 300 401 V2.1.1 (2017-01), 8.1.15: Id list qualifiers */
+const IDLQ_DAB: u8 = 0b00;
+const IDLQ_RDS: u8 = 0b01;
+
+/// Services linked by FIG 0/6, collected over the FIGs sharing an LSN
+#[derive(Clone, Debug)]
+struct LinkageSet {
+    active: bool,
+    // (IdLQ, Id), the first is the key service
+    ids: Vec<(u8, u32)>,
+}
+
+/// Somewhere else to find a service, from service linking and frequency
+/// information
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Alternative {
+    Dab {
+        sid: u32,
+        eid: u16,
+        frequency: Option<u32>,
+    },
+    Fm {
+        pi: u16,
+        frequency: u32,
+    },
+}
+
+impl fmt::Display for Alternative {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Alternative::Dab {
+                sid,
+                eid,
+                frequency: Some(frequency),
+            } => write!(
+                f,
+                "DAB 0x{:04x} in 0x{:04x} at {:.3}MHz",
+                sid,
+                eid,
+                *frequency as f64 / 1000.0
+            ),
+            Alternative::Dab { sid, eid, .. } => write!(f, "DAB 0x{:04x} in 0x{:04x}", sid, eid),
+            Alternative::Fm { pi, frequency } => {
+                write!(f, "FM 0x{:04x} at {:.1}MHz", pi, *frequency as f64 / 1000.0)
+            }
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct Service {
     pub id: u32,
     pub name: String,
+    pub short_name: String,
+    component_labels: HashMap<u8, ComponentLabel>,
     audio_subchannels: HashMap<u8, AudioSubChannel>,
     data_subchannels: HashMap<u16, DataSubChannel>,
+    static_pty: Option<u8>,
+    dynamic_pty: Option<u8>,
+    asu: u16,
+    clusters: Vec<u8>,
+    user_applications: HashMap<u8, Vec<UserApplication>>,
+}
+
+// FIG 1/4 label for a service component, by SCIdS
+#[derive(Clone, Debug)]
+pub struct ComponentLabel {
+    pub label: String,
+    pub short_label: String,
 }
 
 #[derive(Clone, Debug)]
 pub struct AudioSubChannel {
     id: u8,
     primary: bool,
-    start: u16,
-    bitrate: u16,
-    size: u16,
-    protlvl: u8,
-    prot: Protection,
-    uep_index: usize,
+    codec: AudioCodec,
+    scids: Option<u8>,
+    language: Option<u8>,
+    org: Option<SubChannelOrganisation>,
 }
 
 #[derive(Clone, Debug)]
@@ -40,17 +263,32 @@ pub struct DataSubChannel {
     id: u16,
     subchid: u8,
     primary: bool,
-    start: u16,
-    size: u16,
-    protlvl: u8,
-    opt: u8,
+    scids: Option<u8>,
+    language: Option<u8>,
+    org: Option<SubChannelOrganisation>,
     scca_flag: u8,
     dg: u8,
     dscty: u8,
     packet_addr: u16,
     scca: u16,
-    prot: Protection,
-    uep_index: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AudioCodec {
+    Unknown,
+    MP2,
+    AAC,
+}
+
+impl AudioCodec {
+    // ASCTy from FIG 0/2
+    pub fn from_ascty(ascty: u8) -> Self {
+        match ascty {
+            0 => Self::MP2,
+            63 => Self::AAC,
+            _ => Self::Unknown,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -60,12 +298,107 @@ pub enum Protection {
     UEP,
 }
 
+/* ETSI EN 300 401 V2.1.1 (2017-01), 6.2.1: the CIF is 864 capacity units */
+const CIF_CUS: u16 = 864;
+
+/// Position and protection of a subchannel in the CIF, from FIG 0/1
+#[derive(Debug, Clone, Copy)]
+pub struct SubChannelOrganisation {
+    pub SubChId: u8,
+    pub start: u16,
+    pub size: u16,
+    pub profile: ProtectionProfile,
+    pub bitrate: u16,
+}
+
+/// Validate a FIG 0/1 subchannel against the protection tables.
+pub fn new_subchannel_organisation(info: &Information) -> Result<SubChannelOrganisation> {
+    let (SubChId, start, profile) = match *info {
+        Information::SubChannelShort {
+            SubChId,
+            StartAddr,
+            TableSw,
+            TabIndx,
+        } => {
+            let uep = uep_profile(TableSw, TabIndx).ok_or(Error::Fig("bad UEP table index"))?;
+            (SubChId, StartAddr, ProtectionProfile::UEP(uep))
+        }
+        Information::SubChannelLong {
+            SubChId,
+            StartAddr,
+            Opt,
+            ProtLvl,
+            SubChSz,
+        } => {
+            let eep = eep_profile(Opt, ProtLvl, SubChSz).ok_or(Error::Fig("bad EEP profile"))?;
+            (SubChId, StartAddr, ProtectionProfile::EEP(eep))
+        }
+        _ => return Err(Error::Fig("not a subchannel")),
+    };
+
+    let (size, bitrate) = match profile {
+        ProtectionProfile::UEP(uep) => (uep.SubChSz, uep.BitRate),
+        ProtectionProfile::EEP(eep) => (eep.SubChSz, eep.BitRate),
+    };
+    if start + size > CIF_CUS {
+        return Err(Error::Fig("subchannel beyond end of CIF"));
+    }
+
+    Ok(SubChannelOrganisation {
+        SubChId,
+        start,
+        size,
+        profile,
+        bitrate,
+    })
+}
+
+impl SubChannelOrganisation {
+    pub fn protection(&self) -> Protection {
+        match self.profile {
+            ProtectionProfile::UEP(_) => Protection::UEP,
+            ProtectionProfile::EEP(_) => Protection::EEP,
+        }
+    }
+
+    pub fn uep_profile(&self) -> Option<UepProf> {
+        match self.profile {
+            ProtectionProfile::UEP(uep) => Some(uep),
+            _ => None,
+        }
+    }
+
+    pub fn eep_profile(&self) -> Option<EepProf> {
+        match self.profile {
+            ProtectionProfile::EEP(eep) => Some(eep),
+            _ => None,
+        }
+    }
+}
+
 pub fn new_ensemble() -> Ensemble {
     Ensemble {
         id: 0,
         name: "Unknown".to_owned(),
+        short_name: "Unknown".to_owned(),
         services: HashMap::new(),
-        label_tries: 0,
+        acquisition: Acquisition::Searching,
+        cycle_start: None,
+        cycle_seen: false,
+        changed: false,
+        next: Box::default(),
+        reconfigured: false,
+        ecc: None,
+        lto: None,
+        inter_table_id: None,
+        date_time: None,
+        date_time_cif: 0,
+        cif_count: None,
+        announcements: HashMap::new(),
+        linkage_sets: HashMap::new(),
+        dab_frequencies: HashMap::new(),
+        fm_frequencies: HashMap::new(),
+        oe_services: HashMap::new(),
     }
 }
 
@@ -73,41 +406,42 @@ pub fn new_service(id: u32) -> Service {
     Service {
         id,
         name: "Unknown".to_owned(),
+        short_name: "Unknown".to_owned(),
+        component_labels: HashMap::new(),
         audio_subchannels: HashMap::new(),
         data_subchannels: HashMap::new(),
+        static_pty: None,
+        dynamic_pty: None,
+        asu: 0,
+        clusters: Vec::new(),
+        user_applications: HashMap::new(),
     }
 }
 
-pub fn new_subchannel(id: u8, primary: bool) -> AudioSubChannel {
+pub fn new_subchannel(id: u8, primary: bool, ascty: u8) -> AudioSubChannel {
     AudioSubChannel {
         id,
         primary,
-        start: 0,
-        bitrate: 0,
-        size: 0,
-        protlvl: 0,
-        prot: Protection::Unknown,
-        uep_index: 0,
+        codec: AudioCodec::from_ascty(ascty),
+        scids: None,
+        language: None,
+        org: None,
     }
 }
 
-#[allow(clippy::too_many_arguments)]
 pub fn new_data_subchannel(id: u16, primary: bool) -> DataSubChannel {
     DataSubChannel {
         id,
         primary,
         subchid: 0,
-        start: 0,
-        size: 0,
-        protlvl: 0,
-        opt: 0,
+        scids: None,
+        language: None,
+        org: None,
         scca_flag: 0,
         dg: 0,
         dscty: 0,
         packet_addr: 0,
         scca: 0,
-        prot: Protection::Unknown,
-        uep_index: 0,
     }
 }
 
@@ -183,12 +517,42 @@ pub fn new_data_subchannel(id: u16, primary: bool) -> DataSubChannel {
 // }
 
 impl Ensemble {
-    pub fn is_complete(&mut self) -> bool {
-        let tries = self.increment_tries();
-        let empty = self.services.is_empty();
-        let service_labels = self.services_labelled();
-        let ensemble_label = self.ensemble_labelled();
-        tries > 100 || (!empty && service_labels && ensemble_label)
+    pub fn acquisition(&self) -> Acquisition {
+        self.acquisition
+    }
+
+    /// Whether the ensemble has changed since this was last asked, so that
+    /// updates can be passed on.
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::take(&mut self.changed)
+    }
+
+    fn update_acquisition(&mut self) {
+        let acquisition = if self.cif_count.is_none() || self.services.is_empty() {
+            Acquisition::Searching
+        } else if !self.services_organised() {
+            Acquisition::Mci
+        } else if !self.services_labelled() || !self.ensemble_labelled() {
+            Acquisition::Organised
+        } else if !self.cycle_seen {
+            Acquisition::Labelled
+        } else {
+            Acquisition::Complete
+        };
+        if acquisition != self.acquisition {
+            self.acquisition = acquisition;
+            self.changed = true;
+        }
+    }
+
+    // Track the FIG 0/2 repetition: any new service restarts the cycle
+    fn service_seen(&mut self, SId: u32) {
+        if !self.services.contains_key(&SId) {
+            self.cycle_start = Some(SId);
+            self.cycle_seen = false;
+        } else if self.cycle_start == Some(SId) {
+            self.cycle_seen = true;
+        }
     }
 
     pub fn find_service_by_id_str(&self, id_str: &str) -> Option<&Service> {
@@ -203,11 +567,6 @@ impl Ensemble {
         self.services.values().find(|s| s.id == id)
     }
 
-    fn increment_tries(&mut self) -> u16 {
-        self.label_tries += 1;
-        self.label_tries
-    }
-
     fn services_labelled(&self) -> bool {
         self.services
             .values()
@@ -215,14 +574,141 @@ impl Ensemble {
             .all(|n| n != "Unknown")
     }
 
+    fn services_organised(&self) -> bool {
+        self.services.values().all(|s| {
+            s.audio_subchannels.values().all(|a| a.org.is_some())
+                && s.data_subchannels.values().all(|d| d.org.is_some())
+        })
+    }
+
     fn ensemble_labelled(&self) -> bool {
         self.name != "Unknown"
     }
 
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+
     pub fn label(&self) -> &str {
         &self.name
     }
 
+    pub fn short_label(&self) -> &str {
+        &self.short_name
+    }
+
+    pub fn ecc(&self) -> Option<u8> {
+        self.ecc
+    }
+
+    // Local time offset in half hours
+    pub fn lto(&self) -> Option<i8> {
+        self.lto
+    }
+
+    pub fn inter_table_id(&self) -> Option<u8> {
+        self.inter_table_id
+    }
+
+    // Name of the service's programme type in the ensemble's table
+    pub fn pty_name(&self, service: &Service) -> Option<&'static str> {
+        let code = service.pty()?;
+        Some(pty::name(
+            self.inter_table_id.unwrap_or(pty::RDS_EUROPE),
+            code,
+        ))
+    }
+
+    // CIF count of the most recent FIG 0/0
+    pub fn cif_count(&self) -> Option<u16> {
+        self.cif_count
+    }
+
+    /// The broadcast time, moved on from the last FIG 0/10 by the CIFs
+    /// counted since. FIG 0/10 is repeated well within the CIF count's
+    /// two minute cycle.
+    pub fn utc_time(&self) -> Option<DateTime> {
+        let date_time = self.date_time?;
+        let cifs = match self.cif_count {
+            Some(count) => (count + CIF_COUNT_MAX - self.date_time_cif) % CIF_COUNT_MAX,
+            None => 0,
+        };
+        Some(date_time.add_millis((cifs as u64 * CIF_MS) as i64))
+    }
+
+    /// The broadcast time offset by the ensemble LTO from FIG 0/9
+    pub fn local_time(&self) -> Option<DateTime> {
+        let utc = self.utc_time()?;
+        let lto = self.lto.unwrap_or(0) as i64;
+        Some(utc.add_millis(lto * 30 * 60 * 1000))
+    }
+
+    pub fn announcements(&self) -> Vec<&Announcement> {
+        self.announcements
+            .values()
+            .sorted_by(|a, b| Ord::cmp(&a.cluster, &b.cluster))
+            .collect_vec()
+    }
+
+    /// The announcement to switch to from `service`: one in a cluster the
+    /// service belongs to, of a type it supports and `enabled` allows.
+    pub fn announcement_for(&self, service: &Service, enabled: u16) -> Option<&Announcement> {
+        self.announcements()
+            .into_iter()
+            .find(|a| service.clusters.contains(&a.cluster) && a.asw & service.asu & enabled != 0)
+    }
+
+    /// Where else `service` can be received: the same service in other
+    /// ensembles, and services hard linked to it on DAB or FM, with their
+    /// frequencies where known.
+    pub fn alternatives(&self, service: &Service) -> Vec<Alternative> {
+        let mut dab = vec![service.id];
+        let mut fm = Vec::new();
+        // a 16 bit SId is commonly also the RDS PI code
+        if service.id <= 0xffff {
+            fm.push(service.id as u16);
+        }
+        for set in self.linkage_sets.iter().sorted_by_key(|(k, _)| **k) {
+            let ((_, hard, _), set) = set;
+            if !hard || !set.active || !set.ids.contains(&(IDLQ_DAB, service.id)) {
+                continue;
+            }
+            for (IdLQ, id) in &set.ids {
+                match *IdLQ {
+                    IDLQ_DAB if !dab.contains(id) => dab.push(*id),
+                    IDLQ_RDS if !fm.contains(&(*id as u16)) => fm.push(*id as u16),
+                    _ => {}
+                }
+            }
+        }
+
+        let mut alternatives = Vec::new();
+        for sid in dab {
+            let eids = self.oe_services.get(&sid).into_iter().flatten();
+            for eid in eids.filter(|eid| **eid != self.id) {
+                match self.dab_frequencies.get(eid) {
+                    Some(frequencies) => {
+                        alternatives.extend(frequencies.iter().map(|f| Alternative::Dab {
+                            sid,
+                            eid: *eid,
+                            frequency: Some(*f),
+                        }))
+                    }
+                    None => alternatives.push(Alternative::Dab {
+                        sid,
+                        eid: *eid,
+                        frequency: None,
+                    }),
+                }
+            }
+        }
+        for pi in fm {
+            let frequencies = self.fm_frequencies.get(&pi).into_iter().flatten();
+            alternatives.extend(frequencies.map(|f| Alternative::Fm { pi, frequency: *f }));
+        }
+        alternatives
+    }
+
     pub fn services(&self) -> Vec<&Service> {
         self.services
             .values()
@@ -230,22 +716,44 @@ impl Ensemble {
             .collect_vec()
     }
 
+    /// Every organised subchannel in the CIF, once each however many
+    /// services share it, in order of start address
+    pub fn subchannels(&self) -> Vec<SubChannelOrganisation> {
+        self.services
+            .values()
+            .flat_map(|s| {
+                let audio = s.audio_subchannels.values().filter_map(|a| a.org);
+                let data = s.data_subchannels.values().filter_map(|d| d.org);
+                audio.chain(data)
+            })
+            .unique_by(|org| org.SubChId)
+            .sorted_by_key(|org| org.start)
+            .collect_vec()
+    }
+
     pub fn display(&self) {
         eprintln!("Ensemble:");
         eprintln!("{:16} (0x{:04x})", self.name, self.id);
+        if let (Some(ecc), Some(lto)) = (self.ecc, self.lto) {
+            eprintln!("ECC=0x{:02x} LTO={}", ecc, lto as f32 / 2.0);
+        }
+        if let Some(local_time) = self.local_time() {
+            eprintln!("{}", local_time);
+        }
         for service in self.services() {
             for subchannel in service.audio_subchannels.values() {
                 let PS = if subchannel.primary { "Pri" } else { "Sec " };
                 eprintln!(
-                    "{:16} (0x{:04x}) {} subch={} start={} size={} bitrate={} {:?}",
+                    "{:16} (0x{:04x}) {} subch={} start={} size={} bitrate={} {:?} {:?}",
                     service.name,
                     service.id,
                     PS,
                     subchannel.id,
-                    subchannel.start,
-                    subchannel.size,
-                    subchannel.bitrate,
-                    subchannel.prot
+                    subchannel.startaddr(),
+                    subchannel.size(),
+                    subchannel.bitrate(),
+                    subchannel.protection(),
+                    subchannel.codec
                 );
             }
             for data_subchannel in service.data_subchannels.values() {
@@ -261,10 +769,10 @@ impl Ensemble {
                     PS,
                     data_subchannel.subchid,
                     data_subchannel.id,
-                    data_subchannel.start,
-                    data_subchannel.size,
+                    data_subchannel.startaddr(),
+                    data_subchannel.size(),
                     data_subchannel.packet_addr,
-                    data_subchannel.prot
+                    data_subchannel.protection()
                 );
             }
         }
@@ -273,72 +781,50 @@ impl Ensemble {
 
     pub fn add_fig(&mut self, fig: Fig) {
         match fig.figtype {
+            FigType::Type0(fig0) if fig0.next => {
+                for info in fig0.info {
+                    self.stage(info);
+                }
+            }
             FigType::Type0(fig0) => {
                 for info in fig0.info {
                     match info {
-                        Information::Ensemble { EId, .. } => self.set_id(EId),
+                        Information::Ensemble {
+                            EId,
+                            CIFCntH,
+                            CIFCntL,
+                            ChgFlg,
+                            OccChg,
+                            ..
+                        } => {
+                            self.set_id(EId);
+                            let count = CIFCntH as u16 * 250 + CIFCntL as u16;
+                            self.cif_count = Some(count);
+                            if ChgFlg != 0 && self.next.change_at.is_none() {
+                                // OccChg is the CIFCntL of the first CIF in the new
+                                // configuration, signalled up to 6s ahead
+                                let cifs = (OccChg as u16 + 250 - CIFCntL as u16) % 250;
+                                self.next.change_at = Some((count, cifs));
+                            }
+                            if let Some((signalled, cifs)) = self.next.change_at
+                                && (count + CIF_COUNT_MAX - signalled) % CIF_COUNT_MAX >= cifs
+                            {
+                                self.reconfigure();
+                            }
+                        }
                         Information::Service {
                             SId, components, ..
                         } => {
+                            self.service_seen(SId);
                             self.add_service(new_service(SId));
-                            for component in components {
-                                match component {
-                                    ServiceComponent::StreamAudio { SubChId, PS, .. } => self
-                                        .add_service_subchannel(
-                                            SId,
-                                            new_subchannel(SubChId, PS != 0),
-                                        ),
-                                    ServiceComponent::PacketData { SCId, PS, .. } => self
-                                        .add_service_data_subchannel(
-                                            SId,
-                                            new_data_subchannel(SCId, PS != 0),
-                                        ),
-                                    _ => {}
-                                }
-                            }
+                            self.set_service_components(SId, components, false);
                         }
-                        Information::SubChannelShort {
-                            SubChId,
-                            StartAddr,
-                            TabIndx,
-                            ..
-                        } => {
+                        Information::SubChannelShort { SubChId, .. }
+                        | Information::SubChannelLong { SubChId, .. } => {
                             if let Some(SId) = self.find_service_for_subchannel(SubChId)
-                                && TabIndx < 64
+                                && let Ok(org) = new_subchannel_organisation(&info)
                             {
-                                let uep = UEPTABLE[TabIndx as usize];
-                                self.set_service_subchannel_info(
-                                    SId,
-                                    SubChId,
-                                    StartAddr,
-                                    uep.BitRate,
-                                    uep.SubChSz,
-                                    uep.ProtLvl,
-                                    0,
-                                    Protection::UEP,
-                                    TabIndx as usize,
-                                );
-                            }
-                        }
-                        Information::SubChannelLong {
-                            SubChId,
-                            StartAddr,
-                            Opt,
-                            ProtLvl,
-                            SubChSz,
-                        } => {
-                            if let Some(SId) = self.find_service_for_subchannel(SubChId) {
-                                self.set_service_subchannel_info(
-                                    SId,
-                                    SubChId,
-                                    StartAddr,
-                                    0,
-                                    SubChSz,
-                                    ProtLvl,
-                                    Opt,
-                                    Protection::EEP,
-                                    0,
-                                );
+                                self.set_service_subchannel_info(SId, org);
                             }
                         }
                         Information::PacketService {
@@ -356,26 +842,252 @@ impl Ensemble {
                                 );
                             }
                         }
+                        Information::Language {
+                            component,
+                            Language,
+                        } => self.set_component_language(component, Language),
+                        Information::GlobalDefinition {
+                            SId,
+                            SCIdS,
+                            component,
+                        } => self.set_component_scids(SId, component, SCIdS),
+                        Information::Country {
+                            LTO,
+                            ECC,
+                            InterTableId,
+                        } => {
+                            self.lto = Some(LTO);
+                            self.ecc = Some(ECC);
+                            self.inter_table_id = Some(InterTableId);
+                        }
+                        Information::DateTime {
+                            MJD,
+                            hours,
+                            minutes,
+                            seconds,
+                            milliseconds,
+                            ..
+                        } => {
+                            self.date_time = Some(DateTime {
+                                mjd: MJD,
+                                hours,
+                                minutes,
+                                seconds,
+                                milliseconds,
+                            });
+                            self.date_time_cif = self.cif_count.unwrap_or(0);
+                        }
+                        Information::UserApplications {
+                            SId,
+                            SCIdS,
+                            applications,
+                        } => {
+                            if let Some(service) = self.services.get_mut(&SId) {
+                                service.user_applications.insert(SCIdS, applications);
+                            }
+                        }
+                        Information::ProgrammeType {
+                            SId, SD, IntCode, ..
+                        } => {
+                            if let Some(service) = self.services.get_mut(&(SId as u32)) {
+                                let pty = if SD {
+                                    &mut service.dynamic_pty
+                                } else {
+                                    &mut service.static_pty
+                                };
+                                if *pty != Some(IntCode) {
+                                    *pty = Some(IntCode);
+                                    self.changed = true;
+                                }
+                            }
+                        }
+                        Information::AnnouncementSupport { SId, ASu, clusters } => {
+                            if let Some(service) = self.services.get_mut(&(SId as u32)) {
+                                service.asu = ASu;
+                                service.clusters = clusters;
+                            }
+                        }
+                        Information::AnnouncementSwitching {
+                            ClusterId,
+                            ASw,
+                            New,
+                            SubChId,
+                            RegionId,
+                        } => {
+                            if ASw == 0 {
+                                self.announcements.remove(&ClusterId);
+                            } else {
+                                self.announcements.insert(
+                                    ClusterId,
+                                    Announcement {
+                                        cluster: ClusterId,
+                                        asw: ASw,
+                                        new: New,
+                                        subchid: SubChId,
+                                        region: RegionId,
+                                    },
+                                );
+                            }
+                        }
+                        Information::ServiceLinking {
+                            LA,
+                            SH,
+                            ILS,
+                            LSN,
+                            IdLQ,
+                            ids,
+                        } => {
+                            let set =
+                                self.linkage_sets
+                                    .entry((LSN, SH, ILS))
+                                    .or_insert(LinkageSet {
+                                        active: LA,
+                                        ids: Vec::new(),
+                                    });
+                            set.active = LA;
+                            if let Some(IdLQ) = IdLQ {
+                                for id in ids {
+                                    if !set.ids.contains(&(IdLQ, id)) {
+                                        set.ids.push((IdLQ, id));
+                                    }
+                                }
+                            }
+                        }
+                        Information::FrequencyInformation {
+                            Id,
+                            RM,
+                            frequencies,
+                        } => {
+                            let known = match RM {
+                                FI_DAB => self.dab_frequencies.entry(Id).or_default(),
+                                FI_FM_RDS => self.fm_frequencies.entry(Id).or_default(),
+                                _ => continue,
+                            };
+                            for frequency in frequencies {
+                                if !known.contains(&frequency) {
+                                    known.push(frequency);
+                                }
+                            }
+                        }
+                        Information::OEServices { SId, EIds } => {
+                            self.oe_services.insert(SId, EIds);
+                        }
                         _ => {}
                     }
                 }
             }
             FigType::Type1(fig1) => {
                 match fig1.purpose {
-                    LabelPurpose::Ensemble { .. } => self.set_name(fig1.label), // assume one ensemble!
+                    // assume one ensemble!
+                    LabelPurpose::Ensemble { .. } => self.set_name(fig1.label, fig1.short_label),
                     LabelPurpose::ProgrammeService { SId } => {
-                        self.set_service_name(SId as u32, fig1.label)
+                        self.set_service_name(SId as u32, fig1.label, fig1.short_label)
+                    }
+                    LabelPurpose::DataService { SId } => {
+                        self.set_service_name(SId, fig1.label, fig1.short_label)
+                    }
+                    LabelPurpose::ServiceComponent { SId, SCIdS, .. } => {
+                        self.set_component_name(SId, SCIdS, fig1.label, fig1.short_label)
                     }
-                    LabelPurpose::DataService { SId } => self.set_service_name(SId, fig1.label),
                     _ => {}
                 }
             }
             _ => {}
         }
+        self.update_acquisition();
     }
 
-    pub fn set_name(&mut self, name: String) {
-        self.name = name;
+    fn set_service_components(
+        &mut self,
+        SId: u32,
+        components: Vec<ServiceComponent>,
+        replace: bool,
+    ) {
+        // a reconfiguration lists all of a service's components, drop the rest
+        if replace && let Some(service) = self.services.get_mut(&SId) {
+            service.audio_subchannels.retain(|id, _| {
+                components.iter().any(
+                    |c| matches!(c, ServiceComponent::StreamAudio { SubChId, .. } if SubChId == id),
+                )
+            });
+            service.data_subchannels.retain(|id, _| {
+                components
+                    .iter()
+                    .any(|c| matches!(c, ServiceComponent::PacketData { SCId, .. } if SCId == id))
+            });
+        }
+        for component in components {
+            match component {
+                ServiceComponent::StreamAudio {
+                    ASCTy, SubChId, PS, ..
+                } => self.add_service_subchannel(SId, new_subchannel(SubChId, PS != 0, ASCTy)),
+                ServiceComponent::PacketData { SCId, PS, .. } => {
+                    self.add_service_data_subchannel(SId, new_data_subchannel(SCId, PS != 0))
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Hold on to the next configuration until the change occurs
+    fn stage(&mut self, info: Information) {
+        match info {
+            Information::Service {
+                SId, components, ..
+            } => {
+                self.next.services.insert(SId, components);
+            }
+            Information::SubChannelShort { .. } | Information::SubChannelLong { .. } => {
+                if let Ok(org) = new_subchannel_organisation(&info) {
+                    self.next.subchannels.insert(org.SubChId, org);
+                }
+            }
+            // the rest is picked up once it is repeated for the current configuration
+            _ => {}
+        }
+    }
+
+    fn reconfigure(&mut self) {
+        let next = std::mem::take(&mut self.next);
+        if next.services.is_empty() && next.subchannels.is_empty() {
+            return;
+        }
+        // the next configuration is signalled in full, so services and
+        // subchannels it leaves out are gone
+        if !next.services.is_empty() {
+            self.services
+                .retain(|SId, _| next.services.contains_key(SId));
+        }
+        for (SId, components) in next.services {
+            self.add_service(new_service(SId));
+            self.set_service_components(SId, components, true);
+        }
+        if !next.subchannels.is_empty() {
+            for service in self.services.values_mut() {
+                for subchannel in service.audio_subchannels.values_mut() {
+                    subchannel.org = next.subchannels.get(&subchannel.id).copied();
+                }
+                for subchannel in service.data_subchannels.values_mut() {
+                    subchannel.org = next.subchannels.get(&subchannel.subchid).copied();
+                }
+            }
+        }
+        self.reconfigured = true;
+        self.changed = true;
+    }
+
+    /// Whether the multiplex has been reconfigured since this was last
+    /// asked, and subchannels may have moved.
+    pub fn take_reconfigured(&mut self) -> bool {
+        std::mem::take(&mut self.reconfigured)
+    }
+
+    pub fn set_name(&mut self, name: String, short_name: String) {
+        if self.name != name || self.short_name != short_name {
+            self.name = name;
+            self.short_name = short_name;
+            self.changed = true;
+        }
     }
 
     pub fn set_id(&mut self, id: u16) {
@@ -383,12 +1095,55 @@ impl Ensemble {
     }
 
     pub fn add_service(&mut self, service: Service) {
-        self.services.entry(service.id).or_insert(service);
+        if let Entry::Vacant(entry) = self.services.entry(service.id) {
+            entry.insert(service);
+            self.changed = true;
+        }
     }
 
-    pub fn set_service_name(&mut self, service_id: u32, name: String) {
-        if let Some(service) = self.services.get_mut(&service_id) {
+    pub fn set_service_name(&mut self, service_id: u32, name: String, short_name: String) {
+        if let Some(service) = self.services.get_mut(&service_id)
+            && (service.name != name || service.short_name != short_name)
+        {
             service.name = name;
+            service.short_name = short_name;
+            self.changed = true;
+        }
+    }
+
+    pub fn set_component_name(
+        &mut self,
+        service_id: u32,
+        SCIdS: u8,
+        label: String,
+        short_label: String,
+    ) {
+        if let Some(service) = self.services.get_mut(&service_id) {
+            service
+                .component_labels
+                .insert(SCIdS, ComponentLabel { label, short_label });
+        }
+    }
+
+    pub fn set_component_language(&mut self, component: ComponentId, language: u8) {
+        for service in self.services.values_mut() {
+            if let Some(subchannel) = service.audio_subchannel_mut(component) {
+                subchannel.language = Some(language);
+            }
+            if let Some(data_subchannel) = service.data_subchannel_mut(component) {
+                data_subchannel.language = Some(language);
+            }
+        }
+    }
+
+    pub fn set_component_scids(&mut self, service_id: u32, component: ComponentId, SCIdS: u8) {
+        if let Some(service) = self.services.get_mut(&service_id) {
+            if let Some(subchannel) = service.audio_subchannel_mut(component) {
+                subchannel.scids = Some(SCIdS);
+            }
+            if let Some(data_subchannel) = service.data_subchannel_mut(component) {
+                data_subchannel.scids = Some(SCIdS);
+            }
         }
     }
 
@@ -410,37 +1165,15 @@ impl Ensemble {
         }
     }
 
-    #[allow(clippy::too_many_arguments)]
-    pub fn set_service_subchannel_info(
-        &mut self,
-        service_id: u32,
-        subchannel_id: u8,
-        start: u16,
-        bitrate: u16,
-        size: u16,
-        protlvl: u8,
-        opt: u8,
-        prot: Protection,
-        uep_index: usize,
-    ) {
+    pub fn set_service_subchannel_info(&mut self, service_id: u32, org: SubChannelOrganisation) {
         if let Some(service) = self.services.get_mut(&service_id) {
-            if let Some(subchannel) = service.audio_subchannels.get_mut(&subchannel_id) {
-                subchannel.start = start;
-                subchannel.bitrate = bitrate;
-                subchannel.size = size;
-                subchannel.protlvl = protlvl;
-                subchannel.prot = prot;
-                subchannel.uep_index = uep_index;
+            if let Some(subchannel) = service.audio_subchannels.get_mut(&org.SubChId) {
+                self.changed |= subchannel.org.replace(org).is_none();
                 return;
             }
             for data_subchannel in service.data_subchannels.values_mut() {
-                if data_subchannel.subchid == subchannel_id {
-                    data_subchannel.start = start;
-                    data_subchannel.size = size;
-                    data_subchannel.protlvl = protlvl;
-                    data_subchannel.opt = opt;
-                    data_subchannel.prot = prot;
-                    data_subchannel.uep_index = uep_index;
+                if data_subchannel.subchid == org.SubChId {
+                    self.changed |= data_subchannel.org.replace(org).is_none();
                     return;
                 }
             }
@@ -504,15 +1237,123 @@ impl Service {
         &self.name
     }
 
+    pub fn short_label(&self) -> &str {
+        &self.short_name
+    }
+
+    pub fn component_label(&self, SCIdS: u8) -> Option<&ComponentLabel> {
+        self.component_labels.get(&SCIdS)
+    }
+
+    // International programme type code from FIG 0/17: the programme
+    // currently on air if signalled, otherwise the service's usual type
+    pub fn pty(&self) -> Option<u8> {
+        self.dynamic_pty.or(self.static_pty)
+    }
+
+    pub fn static_pty(&self) -> Option<u8> {
+        self.static_pty
+    }
+
+    pub fn dynamic_pty(&self) -> Option<u8> {
+        self.dynamic_pty
+    }
+
+    // Language of the primary component, from FIG 0/5
+    pub fn language(&self) -> Option<u8> {
+        let audio = self.audio_subchannels.values().filter(|s| s.primary);
+        let data = self.data_subchannels.values().filter(|s| s.primary);
+        audio
+            .map(|s| s.language)
+            .chain(data.map(|s| s.language))
+            .flatten()
+            .next()
+    }
+
+    // Announcement types the service supports, and the clusters it belongs to
+    pub fn asu(&self) -> u16 {
+        self.asu
+    }
+
+    pub fn clusters(&self) -> &[u8] {
+        &self.clusters
+    }
+
+    pub fn user_applications(&self, SCIdS: u8) -> &[UserApplication] {
+        self.user_applications
+            .get(&SCIdS)
+            .map(|apps| apps.as_slice())
+            .unwrap_or_default()
+    }
+
+    fn audio_subchannel_mut(&mut self, component: ComponentId) -> Option<&mut AudioSubChannel> {
+        match component {
+            ComponentId::SubChannel { SubChId } => self.audio_subchannels.get_mut(&SubChId),
+            _ => None,
+        }
+    }
+
+    fn data_subchannel_mut(&mut self, component: ComponentId) -> Option<&mut DataSubChannel> {
+        match component {
+            ComponentId::SubChannel { SubChId } => self
+                .data_subchannels
+                .values_mut()
+                .find(|s| s.subchid == SubChId),
+            ComponentId::Packet { SCId } => self.data_subchannels.get_mut(&SCId),
+            _ => None,
+        }
+    }
+
+    /// The codec of the service's audio in the subchannel, if it has any
+    /// there
+    pub fn audio_codec(&self, SubChId: u8) -> Option<AudioCodec> {
+        self.audio_subchannels.get(&SubChId).map(|a| a.codec)
+    }
+
+    pub fn data_subchannels(&self) -> Vec<&DataSubChannel> {
+        self.data_subchannels
+            .values()
+            .sorted_by(|a, b| Ord::cmp(&a.id, &b.id))
+            .collect_vec()
+    }
+
     // TODO; deal with more than one subchannel
-    pub fn subchannel(&self) -> &dyn SubChannel {
+    pub fn subchannel(&self) -> Option<&dyn SubChannel> {
         if let Some(subchannel) = self.audio_subchannels.values().next() {
-            return subchannel;
+            return Some(subchannel);
         }
         if let Some(subchannel) = self.data_subchannels.values().next() {
-            return subchannel;
+            return Some(subchannel);
         }
-        panic!("no subchannels?");
+        None
+    }
+
+    // Whether there is audio organised in the CIF to decode
+    pub fn is_audio(&self) -> bool {
+        self.audio_subchannels.values().any(|a| a.org.is_some())
+    }
+}
+
+impl DataSubChannel {
+    pub fn scid(&self) -> u16 {
+        self.id
+    }
+
+    pub fn subchid(&self) -> u8 {
+        self.subchid
+    }
+
+    pub fn packet_addr(&self) -> u16 {
+        self.packet_addr
+    }
+
+    pub fn dscty(&self) -> u8 {
+        self.dscty
+    }
+
+    // DG flag from FIG 0/3: zero when MSC data groups are used
+    pub fn data_groups(&self) -> bool {
+        self.dg == 0
     }
 }
 
@@ -528,31 +1369,52 @@ pub trait SubChannel {
     fn protection(&self) -> Protection;
     fn subchannel_type(&self) -> SubChannelType;
     fn uep_profile(&self) -> Option<UepProf>;
+    fn eep_profile(&self) -> Option<EepProf>;
     fn bitrate(&self) -> u16;
+    fn audio_codec(&self) -> Option<AudioCodec>;
+    // SCIdS from FIG 0/8, linking the component to its label and applications
+    fn scids(&self) -> Option<u8>;
+    fn language(&self) -> Option<u8>;
+    // position and protection from FIG 0/1, once signalled
+    fn organisation(&self) -> Option<SubChannelOrganisation>;
     // fn as_any(&self) -> &dyn Any;
 }
 
 impl SubChannel for AudioSubChannel {
     fn startaddr(&self) -> u16 {
-        self.start
+        self.org.map(|org| org.start).unwrap_or(0)
     }
     fn size(&self) -> u16 {
-        self.size
+        self.org.map(|org| org.size).unwrap_or(0)
     }
     fn protection(&self) -> Protection {
-        self.prot
+        self.org
+            .map(|org| org.protection())
+            .unwrap_or(Protection::Unknown)
     }
     fn subchannel_type(&self) -> SubChannelType {
         SubChannelType::Audio
     }
     fn uep_profile(&self) -> Option<UepProf> {
-        if self.prot == Protection::UEP {
-            return Some(UEPTABLE[self.uep_index]);
-        }
-        None
+        self.org.and_then(|org| org.uep_profile())
+    }
+    fn eep_profile(&self) -> Option<EepProf> {
+        self.org.and_then(|org| org.eep_profile())
     }
     fn bitrate(&self) -> u16 {
-        self.bitrate
+        self.org.map(|org| org.bitrate).unwrap_or(0)
+    }
+    fn audio_codec(&self) -> Option<AudioCodec> {
+        Some(self.codec)
+    }
+    fn scids(&self) -> Option<u8> {
+        self.scids
+    }
+    fn language(&self) -> Option<u8> {
+        self.language
+    }
+    fn organisation(&self) -> Option<SubChannelOrganisation> {
+        self.org
     }
     // fn as_any(&self) -> &dyn Any {
     //     self
@@ -561,27 +1423,191 @@ impl SubChannel for AudioSubChannel {
 
 impl SubChannel for DataSubChannel {
     fn startaddr(&self) -> u16 {
-        self.start
+        self.org.map(|org| org.start).unwrap_or(0)
     }
     fn size(&self) -> u16 {
-        self.size
+        self.org.map(|org| org.size).unwrap_or(0)
     }
     fn protection(&self) -> Protection {
-        self.prot
+        self.org
+            .map(|org| org.protection())
+            .unwrap_or(Protection::Unknown)
     }
     fn subchannel_type(&self) -> SubChannelType {
         SubChannelType::Data
     }
     fn uep_profile(&self) -> Option<UepProf> {
-        if self.prot == Protection::UEP {
-            return Some(UEPTABLE[self.uep_index]);
-        }
-        None
+        self.org.and_then(|org| org.uep_profile())
+    }
+    fn eep_profile(&self) -> Option<EepProf> {
+        self.org.and_then(|org| org.eep_profile())
     }
     fn bitrate(&self) -> u16 {
-        0
+        self.org.map(|org| org.bitrate).unwrap_or(0)
+    }
+    fn audio_codec(&self) -> Option<AudioCodec> {
+        None
+    }
+    fn scids(&self) -> Option<u8> {
+        self.scids
+    }
+    fn language(&self) -> Option<u8> {
+        self.language
+    }
+    fn organisation(&self) -> Option<SubChannelOrganisation> {
+        self.org
     }
     // fn as_any(&self) -> &dyn Any {
     //     self
     // }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fic::fig::{FigHeader, Type0, fig_header};
+
+    fn fig0(next: bool, info: Vec<Information>) -> Fig {
+        Fig {
+            header: FigHeader { figtype: 0, len: 0 },
+            figtype: FigType::Type0(Type0 { next, info }),
+        }
+    }
+
+    // a FIG 0 as broadcast, its type 0 header then its data field
+    fn parse(data: Vec<u8>) -> Fig {
+        let mut fig = fig_header(data.len() as u8).unwrap();
+        fig.push_data(data).unwrap();
+        fig
+    }
+
+    fn cif_count(CIFCntL: u8, ChgFlg: u8, OccChg: u8) -> Information {
+        Information::Ensemble {
+            OccChg,
+            CIFCntL,
+            CIFCntH: 0,
+            AlrmFlg: 0,
+            ChgFlg,
+            EId: 0xce15,
+        }
+    }
+
+    fn service(SId: u32, SubChId: u8) -> Information {
+        Information::Service {
+            SId,
+            PD: false,
+            components: vec![ServiceComponent::StreamAudio {
+                ASCTy: 0,
+                SubChId,
+                PS: 1,
+                CAFlg: 0,
+            }],
+        }
+    }
+
+    fn subchannel(SubChId: u8, StartAddr: u16) -> Information {
+        Information::SubChannelLong {
+            SubChId,
+            StartAddr,
+            Opt: 0,
+            ProtLvl: 2,
+            SubChSz: 72,
+        }
+    }
+
+    #[test]
+    fn reconfiguration_replaces_services_and_subchannels() {
+        let mut ens = new_ensemble();
+        ens.add_fig(fig0(false, vec![cif_count(0, 0, 0)]));
+        ens.add_fig(fig0(false, vec![service(0xc221, 1), service(0xc222, 2)]));
+        ens.add_fig(fig0(false, vec![subchannel(1, 0), subchannel(2, 72)]));
+        assert_eq!(ens.services().len(), 2);
+
+        // only the first service, moved to a new subchannel, from CIF 20
+        ens.add_fig(fig0(true, vec![service(0xc221, 3)]));
+        ens.add_fig(fig0(true, vec![subchannel(3, 144)]));
+        ens.add_fig(fig0(false, vec![cif_count(10, 1, 20)]));
+        ens.add_fig(fig0(false, vec![cif_count(15, 1, 20)]));
+        assert!(!ens.take_reconfigured());
+        assert_eq!(ens.services().len(), 2);
+
+        ens.add_fig(fig0(false, vec![cif_count(20, 1, 20)]));
+        assert!(ens.take_reconfigured());
+        let services = ens.services();
+        assert_eq!(services.len(), 1);
+        assert_eq!(services[0].subchannel().map(|s| s.startaddr()), Some(144));
+        let subchannels = ens.subchannels();
+        assert_eq!(subchannels.len(), 1);
+        assert_eq!(subchannels[0].SubChId, 3);
+    }
+
+    #[test]
+    fn si_continuation_applies_now() {
+        let mut ens = new_ensemble();
+        ens.add_fig(fig0(false, vec![service(0xc221, 1)]));
+
+        // FIG 0/17 with C/N set, continuing the database: News
+        let fig = parse(vec![0x80 | 17, 0xc2, 0x21, 0x00, 0x01]);
+        assert!(matches!(&fig.figtype, FigType::Type0(fig0) if !fig0.next));
+        ens.add_fig(fig);
+        assert_eq!(ens.services()[0].pty(), Some(1));
+
+        // FIG 0/1 with C/N set is the next configuration's
+        let fig = parse(vec![0x80 | 1, 3 << 2, 0x00, 0x00]);
+        assert!(matches!(&fig.figtype, FigType::Type0(fig0) if fig0.next));
+    }
+
+    #[test]
+    fn system_time_from_mjd() {
+        let date_time = |mjd| DateTime {
+            mjd,
+            hours: 12,
+            minutes: 0,
+            seconds: 1,
+            milliseconds: 500,
+        };
+        assert_eq!(
+            date_time(MJD_UNIX_EPOCH).system_time(),
+            Some(UNIX_EPOCH + Duration::from_millis(43_201_500))
+        );
+        assert_eq!(date_time(MJD_UNIX_EPOCH - 1).system_time(), None);
+        assert_eq!(date_time(0).system_time(), None);
+    }
+
+    #[test]
+    fn subchannel_organisations_are_checked() {
+        let short = |TableSw, TabIndx| Information::SubChannelShort {
+            SubChId: 1,
+            StartAddr: 0,
+            TableSw,
+            TabIndx,
+        };
+        let long = |StartAddr, SubChSz| Information::SubChannelLong {
+            SubChId: 2,
+            StartAddr,
+            Opt: 0,
+            ProtLvl: 2,
+            SubChSz,
+        };
+
+        let org = new_subchannel_organisation(&short(0, 63)).unwrap();
+        assert_eq!(org.protection(), Protection::UEP);
+        let org = new_subchannel_organisation(&long(0, 72)).unwrap();
+        assert_eq!((org.size, org.bitrate), (72, 96));
+
+        let bad = Some(Error::Fig("bad UEP table index"));
+        assert_eq!(new_subchannel_organisation(&short(0, 64)).err(), bad);
+        // the second table is reserved
+        assert_eq!(new_subchannel_organisation(&short(1, 0)).err(), bad);
+        // 3A is 6 CUs per 8kbit/s
+        assert_eq!(
+            new_subchannel_organisation(&long(0, 70)).err(),
+            Some(Error::Fig("bad EEP profile"))
+        );
+        assert_eq!(
+            new_subchannel_organisation(&long(800, 72)).err(),
+            Some(Error::Fig("subchannel beyond end of CIF"))
+        );
+        assert!(new_subchannel_organisation(&Information::Unknown).is_err());
+    }
+}
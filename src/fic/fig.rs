@@ -1,6 +1,8 @@
 #![allow(non_snake_case)]
 #![allow(unused_variables)]
 
+use crate::charset;
+use crate::error::{Error, Result};
 use bitvec::{
     field::BitField,
     order::{Lsb0, Msb0},
@@ -9,6 +11,10 @@ use bitvec::{
 };
 use core::fmt::Debug;
 
+/* ETSI EN 300 401 V2.1.1 (2017-01), 8.1.8 Table 16: FIG 0/21 range and modulation */
+pub const FI_DAB: u8 = 0b0000;
+pub const FI_FM_RDS: u8 = 0b1000;
+
 #[derive(Debug)]
 pub struct Fig {
     pub header: FigHeader,
@@ -30,6 +36,9 @@ pub enum FigType {
 
 #[derive(Debug)]
 pub struct Type0 {
+    // C/N: the MCI is for the next configuration, not the current one. For
+    // SI it only marks a continuation of the database, which still applies now
+    pub next: bool,
     pub info: Vec<Information>,
 }
 
@@ -71,9 +80,87 @@ pub enum Information {
         PacketAddr: u16,
         SCCA: u16,
     },
+    Language {
+        component: ComponentId,
+        Language: u8,
+    },
+    GlobalDefinition {
+        SId: u32,
+        SCIdS: u8,
+        component: ComponentId,
+    },
+    Country {
+        LTO: i8,
+        ECC: u8,
+        InterTableId: u8,
+    },
+    DateTime {
+        MJD: u32,
+        LSI: bool,
+        hours: u8,
+        minutes: u8,
+        seconds: u8,
+        milliseconds: u16,
+    },
+    UserApplications {
+        SId: u32,
+        SCIdS: u8,
+        applications: Vec<UserApplication>,
+    },
+    ProgrammeType {
+        SId: u16,
+        SD: bool,
+        Language: Option<u8>,
+        IntCode: u8,
+        CompCode: Option<u8>,
+    },
+    AnnouncementSupport {
+        SId: u16,
+        ASu: u16,
+        clusters: Vec<u8>,
+    },
+    AnnouncementSwitching {
+        ClusterId: u8,
+        ASw: u16,
+        New: bool,
+        SubChId: u8,
+        RegionId: Option<u8>,
+    },
+    ServiceLinking {
+        LA: bool,
+        SH: bool,
+        ILS: bool,
+        LSN: u16,
+        IdLQ: Option<u8>,
+        ids: Vec<u32>,
+    },
+    FrequencyInformation {
+        Id: u16,
+        RM: u8,
+        frequencies: Vec<u32>,
+    },
+    OEServices {
+        SId: u32,
+        EIds: Vec<u16>,
+    },
 }
 
-#[derive(Debug)]
+// How FIG 0/5 and 0/8 refer to a service component: short form by
+// subchannel (or FIDC), long form by SCId.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ComponentId {
+    SubChannel { SubChId: u8 },
+    FIDC { FIDCId: u8 },
+    Packet { SCId: u16 },
+}
+
+#[derive(Debug, Clone)]
+pub struct UserApplication {
+    pub UAType: u16,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
 pub enum ServiceComponent {
     Unknown,
     StreamAudio {
@@ -104,6 +191,7 @@ pub enum ServiceComponent {
 #[derive(Debug)]
 pub struct Type1 {
     pub label: String,
+    pub short_label: String,
     pub purpose: LabelPurpose,
 }
 
@@ -128,17 +216,21 @@ pub enum LabelPurpose {
 }
 
 impl Fig {
-    pub fn push_data(&mut self, bytes: Vec<u8>) {
+    pub fn push_data(&mut self, bytes: Vec<u8>) -> Result<()> {
+        // the FIB may end before the FIG does
+        if bytes.len() != self.header.len {
+            return Err(Error::Fig("truncated FIG"));
+        }
         self.figtype.push_data(bytes)
     }
 }
 
 impl FigType {
-    pub fn push_data(&mut self, bytes: Vec<u8>) {
+    pub fn push_data(&mut self, bytes: Vec<u8>) -> Result<()> {
         match self {
             FigType::Type0(fig0) => fig0.push_data(bytes),
             FigType::Type1(fig1) => fig1.push_data(bytes),
-            _ => (),
+            _ => Ok(()),
         }
     }
 }
@@ -171,6 +263,7 @@ fn fig_0(len: usize) -> Fig {
     Fig {
         header: FigHeader { figtype: 0, len },
         figtype: FigType::Type0(Type0 {
+            next: false,
             info: vec![Information::Unknown],
         }),
     }
@@ -182,55 +275,86 @@ fn fig_1(len: usize) -> Fig {
         figtype: FigType::Type1(Type1 {
             purpose: LabelPurpose::Unknown,
             label: "".to_owned(),
+            short_label: "".to_owned(),
         }),
     }
 }
 
 impl Type0 {
-    pub fn push_data(&mut self, bytes: Vec<u8>) {
-        let header = bytes[0].view_bits::<Lsb0>();
+    pub fn push_data(&mut self, bytes: Vec<u8>) -> Result<()> {
+        let Some(header) = bytes.first() else {
+            return Err(Error::Fig("empty FIG 0"));
+        };
+        let header = header.view_bits::<Lsb0>();
         let extn: u8 = header[0..5].load_be();
         let pd: u8 = header[5..6].load_be();
         let oe: u8 = header[6..7].load_be();
         let cn: u8 = header[7..8].load_be();
+        self.next = cn != 0 && matches!(extn, 1 | 2 | 3 | 4 | 8);
         self.info = match extn {
-            0 => Type0::ensemble(pd, &bytes[1..]),
-            1 => Type0::subchannel(pd, &bytes[1..]),
-            2 => Type0::service(pd, &bytes[1..]),
-            3 => Type0::packet_service_component(pd, &bytes[1..]),
+            0 => Type0::ensemble(pd, &bytes[1..])?,
+            1 => Type0::subchannel(pd, &bytes[1..])?,
+            2 => Type0::service(pd, &bytes[1..])?,
+            3 => Type0::packet_service_component(pd, &bytes[1..])?,
+            // other ensembles are handled by service linking
+            5 | 8 | 13 | 17 | 18 | 19 if oe != 0 => vec![Information::Unknown],
+            5 => Type0::language(&bytes[1..]),
+            8 => Type0::global_definition(pd, &bytes[1..]),
+            9 => Type0::country(&bytes[1..]),
+            10 => Type0::date_time(&bytes[1..]),
+            13 => Type0::user_applications(pd, &bytes[1..]),
+            17 => Type0::programme_type(&bytes[1..]),
+            18 => Type0::announcement_support(&bytes[1..]),
+            19 => Type0::announcement_switching(&bytes[1..]),
+            6 => Type0::service_linking(pd, &bytes[1..]),
+            21 => Type0::frequency_information(&bytes[1..]),
+            24 => Type0::oe_services(pd, &bytes[1..]),
             _ => vec![Information::Unknown],
         };
+        Ok(())
     }
 
-    fn ensemble(pd: u8, bytes: &[u8]) -> Vec<Information> {
-        assert!(bytes.len() == 4);
-        let data = bytes.view_bits::<Lsb0>();
+    fn ensemble(pd: u8, bytes: &[u8]) -> Result<Vec<Information>> {
+        if bytes.len() < 4 {
+            return Err(Error::Fig("short FIG 0/0"));
+        }
+        let data = bytes.view_bits::<Msb0>();
         let EId: u16 = data[0..16].load_be();
         let ChgFlg: u8 = data[16..18].load_be();
         let AlrmFlg: u8 = data[18..19].load_be();
         let CIFCntH: u8 = data[19..24].load_be();
         let CIFCntL: u8 = data[24..32].load_be();
-        // let OccChg: u8 = data[32..40].load_be();
-        vec![Information::Ensemble {
-            OccChg: 0,
+        // OccChg is only present while a change is signalled
+        let OccChg: u8 = bytes.get(4).copied().unwrap_or(0);
+        // The CIF count runs modulo 5000 as modulo 20 and modulo 250 parts
+        if CIFCntH >= 20 || CIFCntL >= 250 || OccChg >= 250 {
+            return Err(Error::Fig("CIF count out of range"));
+        }
+        Ok(vec![Information::Ensemble {
+            OccChg,
             CIFCntL,
             CIFCntH,
             AlrmFlg,
             ChgFlg,
             EId,
-        }]
+        }])
     }
 
-    fn subchannel(pd: u8, bytes: &[u8]) -> Vec<Information> {
+    fn subchannel(pd: u8, bytes: &[u8]) -> Result<Vec<Information>> {
         let mut offset = 0;
         let mut subchannels = Vec::new();
         while offset < bytes.len() {
+            if bytes[offset..].len() < 3 {
+                return Err(Error::Fig("short FIG 0/1"));
+            }
             let data = bytes[offset..].view_bits::<Msb0>();
             let SubChId: u8 = data[0..6].load_be();
             let StartAddr: u16 = data[6..16].load_be();
             let LongForm: u8 = data[16..17].load_be();
             if LongForm != 0 {
-                assert!(bytes[offset..].len() >= 4);
+                if bytes[offset..].len() < 4 {
+                    return Err(Error::Fig("short FIG 0/1 long form"));
+                }
                 offset += 4;
                 let Opt: u8 = data[17..20].load_be();
                 let ProtLvl: u8 = data[20..22].load_be();
@@ -243,7 +367,6 @@ impl Type0 {
                     SubChSz,
                 });
             } else {
-                assert!(bytes[offset..].len() >= 3);
                 offset += 3;
                 let TableSw: u8 = data[17..18].load_be();
                 let TabIndx: u8 = data[18..24].load_be();
@@ -255,13 +378,17 @@ impl Type0 {
                 });
             }
         }
-        subchannels
+        Ok(subchannels)
     }
 
-    fn service(pd: u8, bytes: &[u8]) -> Vec<Information> {
+    fn service(pd: u8, bytes: &[u8]) -> Result<Vec<Information>> {
+        let sid_len = if pd != 0 { 4 } else { 2 };
         let mut offset = 0;
         let mut services = Vec::new();
         while offset < bytes.len() {
+            if bytes[offset..].len() < sid_len + 1 {
+                return Err(Error::Fig("short FIG 0/2"));
+            }
             let mut data = bytes[offset..].view_bits::<Msb0>();
             let SId: u32;
             if pd != 0 {
@@ -276,6 +403,9 @@ impl Type0 {
             let CAId: u8 = data[1..4].load_be();
             let NumSCmp: u8 = data[4..8].load_be();
             offset += 1;
+            if bytes[offset..].len() < NumSCmp as usize * 2 {
+                return Err(Error::Fig("short FIG 0/2 components"));
+            }
             let mut components = vec![];
             for i in 0..NumSCmp {
                 data = bytes[offset..].view_bits::<Msb0>();
@@ -333,13 +463,16 @@ impl Type0 {
                 components,
             })
         }
-        services
+        Ok(services)
     }
 
-    fn packet_service_component(pd: u8, bytes: &[u8]) -> Vec<Information> {
+    fn packet_service_component(pd: u8, bytes: &[u8]) -> Result<Vec<Information>> {
         let mut offset = 0;
         let mut service_components = Vec::new();
         while offset < bytes.len() {
+            if bytes[offset..].len() < 5 {
+                return Err(Error::Fig("short FIG 0/3"));
+            }
             let data = bytes[offset..].view_bits::<Msb0>();
             let SCId: u16 = data[0..12].load_be();
             let Rfa: u8 = data[12..15].load_be();
@@ -360,19 +493,384 @@ impl Type0 {
                 SCCA: 0,
             });
             offset += 5;
+            // the SCCA is not needed, but has to be skipped
+            if SCCAFlag != 0 {
+                offset += 2;
+            }
         }
-        service_components
+        Ok(service_components)
+    }
+
+    // Short or long form component reference of FIG 0/5 and 0/8, with its length
+    fn component_id(data: &BitSlice<u8, Msb0>) -> (ComponentId, usize) {
+        let LS = data[0];
+        if LS {
+            let SCId: u16 = data[4..16].load_be();
+            (ComponentId::Packet { SCId }, 2)
+        } else {
+            let FIC = data[1];
+            let id: u8 = data[2..8].load_be();
+            if FIC {
+                (ComponentId::FIDC { FIDCId: id }, 1)
+            } else {
+                (ComponentId::SubChannel { SubChId: id }, 1)
+            }
+        }
+    }
+
+    /* ETSI EN 300 401 V2.1.1 (2017-01), 8.1.2: service component language */
+    fn language(bytes: &[u8]) -> Vec<Information> {
+        let mut offset = 0;
+        let mut languages = Vec::new();
+        while offset < bytes.len() {
+            let len = if bytes[offset] & 0x80 != 0 { 3 } else { 2 };
+            if offset + len > bytes.len() {
+                break;
+            }
+            let data = bytes[offset..].view_bits::<Msb0>();
+            let (component, n) = Type0::component_id(data);
+            let Language: u8 = bytes[offset + n];
+            languages.push(Information::Language {
+                component,
+                Language,
+            });
+            offset += len;
+        }
+        languages
+    }
+
+    /* 6.3.5: service component global definition */
+    fn global_definition(pd: u8, bytes: &[u8]) -> Vec<Information> {
+        let sid_len = if pd != 0 { 4 } else { 2 };
+        let mut offset = 0;
+        let mut definitions = Vec::new();
+        while offset + sid_len + 2 <= bytes.len() {
+            let data = bytes[offset..].view_bits::<Msb0>();
+            let SId: u32 = data[0..(sid_len * 8)].load_be();
+            offset += sid_len;
+
+            let data = bytes[offset..].view_bits::<Msb0>();
+            let ExtFlag = data[0];
+            let SCIdS: u8 = data[4..8].load_be();
+            offset += 1;
+
+            let LS = bytes[offset] & 0x80 != 0;
+            if LS && offset + 2 > bytes.len() {
+                break;
+            }
+            let (component, n) = Type0::component_id(bytes[offset..].view_bits::<Msb0>());
+            offset += n;
+            if ExtFlag {
+                offset += 1;
+            }
+            definitions.push(Information::GlobalDefinition {
+                SId,
+                SCIdS,
+                component,
+            });
+        }
+        definitions
+    }
+
+    /* 8.1.3.2: country, LTO and international table */
+    fn country(bytes: &[u8]) -> Vec<Information> {
+        if bytes.len() < 3 {
+            return vec![Information::Unknown];
+        }
+        let data = bytes.view_bits::<Msb0>();
+        let sense = data[2];
+        let halfhours = data[3..8].load_be::<u8>() as i8;
+        vec![Information::Country {
+            LTO: if sense { -halfhours } else { halfhours },
+            ECC: bytes[1],
+            InterTableId: bytes[2],
+        }]
+    }
+
+    /* 8.1.3.1: date and time */
+    fn date_time(bytes: &[u8]) -> Vec<Information> {
+        if bytes.len() < 4 {
+            return vec![Information::Unknown];
+        }
+        let data = bytes.view_bits::<Msb0>();
+        let MJD: u32 = data[1..18].load_be();
+        let LSI = data[18];
+        let UTCFlag = data[20];
+        let hours: u8 = data[21..26].load_be();
+        let minutes: u8 = data[26..32].load_be();
+        let (seconds, milliseconds) = if UTCFlag && bytes.len() >= 6 {
+            (data[32..38].load_be(), data[38..48].load_be())
+        } else {
+            (0, 0)
+        };
+        vec![Information::DateTime {
+            MJD,
+            LSI,
+            hours,
+            minutes,
+            seconds,
+            milliseconds,
+        }]
+    }
+
+    /* 6.3.6: user application information */
+    fn user_applications(pd: u8, bytes: &[u8]) -> Vec<Information> {
+        let sid_len = if pd != 0 { 4 } else { 2 };
+        let mut offset = 0;
+        let mut services = Vec::new();
+        while offset + sid_len < bytes.len() {
+            let data = bytes[offset..].view_bits::<Msb0>();
+            let SId: u32 = data[0..(sid_len * 8)].load_be();
+            offset += sid_len;
+
+            let SCIdS = bytes[offset] >> 4;
+            let NumApps = bytes[offset] & 0x0f;
+            offset += 1;
+
+            let mut applications = Vec::new();
+            for _ in 0..NumApps {
+                if offset + 2 > bytes.len() {
+                    break;
+                }
+                let data = bytes[offset..].view_bits::<Msb0>();
+                let UAType: u16 = data[0..11].load_be();
+                let len: usize = data[11..16].load_be();
+                offset += 2;
+                let end = (offset + len).min(bytes.len());
+                applications.push(UserApplication {
+                    UAType,
+                    data: bytes[offset..end].to_vec(),
+                });
+                offset = end;
+            }
+            services.push(Information::UserApplications {
+                SId,
+                SCIdS,
+                applications,
+            });
+        }
+        services
+    }
+
+    /* 8.1.5: programme type. The language and complementary code flags
+    were withdrawn in V2, but are still parsed for older multiplexers. */
+    fn programme_type(bytes: &[u8]) -> Vec<Information> {
+        let mut offset = 0;
+        let mut types = Vec::new();
+        while offset + 4 <= bytes.len() {
+            let data = bytes[offset..].view_bits::<Msb0>();
+            let SId: u16 = data[0..16].load_be();
+            let SD = data[16];
+            let LFlag = data[18];
+            let CCFlag = data[19];
+            offset += 3;
+
+            let Language = if LFlag {
+                offset += 1;
+                bytes.get(offset - 1).copied()
+            } else {
+                None
+            };
+            let Some(IntCode) = bytes.get(offset).map(|b| b & 0x1f) else {
+                break;
+            };
+            offset += 1;
+            let CompCode = if CCFlag {
+                offset += 1;
+                bytes.get(offset - 1).map(|b| b & 0x1f)
+            } else {
+                None
+            };
+            types.push(Information::ProgrammeType {
+                SId,
+                SD,
+                Language,
+                IntCode,
+                CompCode,
+            });
+        }
+        types
+    }
+
+    /* 8.1.6.1: announcement support */
+    fn announcement_support(bytes: &[u8]) -> Vec<Information> {
+        let mut offset = 0;
+        let mut services = Vec::new();
+        while offset + 5 <= bytes.len() {
+            let SId = u16::from_be_bytes([bytes[offset], bytes[offset + 1]]);
+            let ASu = u16::from_be_bytes([bytes[offset + 2], bytes[offset + 3]]);
+            let NumClusters = (bytes[offset + 4] & 0x1f) as usize;
+            offset += 5;
+            let end = (offset + NumClusters).min(bytes.len());
+            services.push(Information::AnnouncementSupport {
+                SId,
+                ASu,
+                clusters: bytes[offset..end].to_vec(),
+            });
+            offset = end;
+        }
+        services
+    }
+
+    /* 8.1.6.2: announcement switching */
+    fn announcement_switching(bytes: &[u8]) -> Vec<Information> {
+        let mut offset = 0;
+        let mut switching = Vec::new();
+        while offset + 4 <= bytes.len() {
+            let ClusterId = bytes[offset];
+            let ASw = u16::from_be_bytes([bytes[offset + 1], bytes[offset + 2]]);
+            let data = bytes[offset + 3].view_bits::<Msb0>();
+            let New = data[0];
+            let RegionFlag = data[1];
+            let SubChId: u8 = data[2..8].load_be();
+            offset += 4;
+            let RegionId = if RegionFlag {
+                offset += 1;
+                bytes.get(offset - 1).map(|b| b & 0x3f)
+            } else {
+                None
+            };
+            switching.push(Information::AnnouncementSwitching {
+                ClusterId,
+                ASw,
+                New,
+                SubChId,
+                RegionId,
+            });
+        }
+        switching
+    }
+
+    /* 8.1.15: service linking information */
+    fn service_linking(pd: u8, bytes: &[u8]) -> Vec<Information> {
+        let mut offset = 0;
+        let mut sets = Vec::new();
+        while offset + 2 <= bytes.len() {
+            let data = bytes[offset..].view_bits::<Msb0>();
+            let IdListFlag = data[0];
+            let LA = data[1];
+            let SH = data[2];
+            let ILS = data[3];
+            let LSN: u16 = data[4..16].load_be();
+            offset += 2;
+
+            let mut IdLQ = None;
+            let mut ids = Vec::new();
+            if IdListFlag {
+                let Some(usage) = bytes.get(offset) else {
+                    break;
+                };
+                IdLQ = Some((usage >> 5) & 0x03);
+                let NumIds = (usage & 0x0f) as usize;
+                offset += 1;
+
+                // an ECC precedes each international 16 bit Id
+                let len = match (pd != 0, ILS) {
+                    (true, _) => 4,
+                    (false, true) => 3,
+                    (false, false) => 2,
+                };
+                for id in bytes[offset..].chunks_exact(len).take(NumIds) {
+                    ids.push(match len {
+                        4 => u32::from_be_bytes([id[0], id[1], id[2], id[3]]),
+                        _ => u16::from_be_bytes([id[len - 2], id[len - 1]]) as u32,
+                    });
+                }
+                offset += NumIds * len;
+            }
+            sets.push(Information::ServiceLinking {
+                LA,
+                SH,
+                ILS,
+                LSN,
+                IdLQ,
+                ids,
+            });
+        }
+        sets
+    }
+
+    /* 8.1.8: frequency information. DAB ensemble frequencies are in units
+    of 16kHz, FM ones are 100kHz steps from 87.5MHz; both are returned in kHz.
+    Other R&M are skipped. */
+    fn frequency_information(bytes: &[u8]) -> Vec<Information> {
+        let mut offset = 0;
+        let mut lists = Vec::new();
+        while offset + 2 <= bytes.len() {
+            let len = (bytes[offset + 1] & 0x1f) as usize;
+            offset += 2;
+            let end = (offset + len).min(bytes.len());
+
+            while offset + 3 <= end {
+                let Id = u16::from_be_bytes([bytes[offset], bytes[offset + 1]]);
+                let RM = bytes[offset + 2] >> 4;
+                let list_len = (bytes[offset + 2] & 0x07) as usize;
+                offset += 3;
+                let list = &bytes[offset..(offset + list_len).min(end)];
+                offset += list_len;
+
+                let frequencies = match RM {
+                    FI_DAB => list
+                        .chunks_exact(3)
+                        .map(|f| {
+                            let freq = u32::from_be_bytes([0, f[0], f[1], f[2]]) & 0x7ffff;
+                            freq * 16
+                        })
+                        .collect(),
+                    FI_FM_RDS => list
+                        .iter()
+                        .filter(|f| (1..=204).contains(*f))
+                        .map(|f| 87500 + *f as u32 * 100)
+                        .collect(),
+                    _ => vec![],
+                };
+                lists.push(Information::FrequencyInformation {
+                    Id,
+                    RM,
+                    frequencies,
+                });
+            }
+            offset = end;
+        }
+        lists
+    }
+
+    /* 8.1.10: OE services */
+    fn oe_services(pd: u8, bytes: &[u8]) -> Vec<Information> {
+        let sid_len = if pd != 0 { 4 } else { 2 };
+        let mut offset = 0;
+        let mut services = Vec::new();
+        while offset + sid_len < bytes.len() {
+            let data = bytes[offset..].view_bits::<Msb0>();
+            let SId: u32 = data[0..(sid_len * 8)].load_be();
+            offset += sid_len;
+            let NumEIds = (bytes[offset] & 0x0f) as usize;
+            offset += 1;
+
+            let EIds = bytes[offset..]
+                .chunks_exact(2)
+                .take(NumEIds)
+                .map(|e| u16::from_be_bytes([e[0], e[1]]))
+                .collect();
+            offset += NumEIds * 2;
+            services.push(Information::OEServices { SId, EIds });
+        }
+        services
     }
 }
 
-fn label(bytes: Vec<u8>) -> String {
-    String::from_utf8(
-        bytes
-            .iter()
-            .map(|b| if *b == 0 { 32 } else { *b })
-            .collect(),
-    )
-    .unwrap()
+fn label(charset: u8, bytes: &[u8]) -> String {
+    // unused characters may be sent as nulls rather than spaces
+    match charset {
+        charset::UCS2 => charset::decode(charset, bytes).replace('\0', " "),
+        _ => {
+            let bytes: Vec<u8> = bytes
+                .iter()
+                .map(|b| if *b == 0 { 32 } else { *b })
+                .collect();
+            charset::decode(charset, &bytes)
+        }
+    }
 }
 
 struct Type1Header<'a> {
@@ -396,7 +894,7 @@ impl Type1Header<'_> {
         self.bits[3..4].load_be()
     }
 
-    fn _charset(&self) -> u8 {
+    fn charset(&self) -> u8 {
         self.bits[4..8].load_be()
     }
 
@@ -405,12 +903,46 @@ impl Type1Header<'_> {
     }
 
     fn label(&self, offset: usize) -> String {
-        label(self.bytes[offset..offset + 16].to_vec())
+        label(self.charset(), &self.bytes[offset..offset + 16])
+            .trim_end()
+            .to_owned()
+    }
+
+    // The character flag field follows the label: b15 flags the first
+    // character for the abbreviated label, and so on. At most 8 are set.
+    fn short_label(&self, offset: usize) -> String {
+        let label = label(self.charset(), &self.bytes[offset..offset + 16]);
+        let flags = match self.bytes.get((offset + 16)..(offset + 18)) {
+            Some(flags) => u16::from_be_bytes([flags[0], flags[1]]),
+            None => 0,
+        };
+        if flags == 0 {
+            return label
+                .trim()
+                .chars()
+                .take(8)
+                .collect::<String>()
+                .trim_end()
+                .to_owned();
+        }
+        label
+            .chars()
+            .enumerate()
+            .filter(|(i, _)| *i < 16 && flags & (0x8000 >> i) != 0)
+            .map(|(_, c)| c)
+            .take(8)
+            .collect::<String>()
+            .trim()
+            .to_owned()
     }
 }
 
 impl Type1 {
-    pub fn push_data(&mut self, bytes: Vec<u8>) {
+    pub fn push_data(&mut self, bytes: Vec<u8>) -> Result<()> {
+        // the largest identifier, of FIG 1/4 with a 32 bit SId
+        if bytes.len() < 6 {
+            return Err(Error::Fig("short FIG 1"));
+        }
         let header = new_type1(&bytes);
         self.purpose = match header.extn() {
             0 => Type1::ensemble(&bytes),
@@ -419,13 +951,20 @@ impl Type1 {
             5 => Type1::data_service(&bytes),
             _ => LabelPurpose::Unknown,
         };
-        self.label = match header.extn() {
-            0 => header.label(3),
-            1 => header.label(3),
-            4 => header.label(if header.pd() != 0 { 6 } else { 4 }),
-            5 => header.label(5),
-            _ => "".to_owned(),
+        let offset = match header.extn() {
+            0 | 1 => Some(3),
+            4 => Some(if header.pd() != 0 { 6 } else { 4 }),
+            5 => Some(5),
+            _ => None,
         };
+        if let Some(offset) = offset {
+            if bytes.len() < offset + 16 {
+                return Err(Error::Fig("short FIG 1 label"));
+            }
+            self.label = header.label(offset);
+            self.short_label = header.short_label(offset);
+        }
+        Ok(())
     }
 
     fn ensemble(bytes: &[u8]) -> LabelPurpose {
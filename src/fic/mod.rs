@@ -1,10 +1,12 @@
 use core::fmt;
 
+use crate::decode::crc16_ccitt;
 use crate::wavefinder::Buffer;
 
 pub mod decoder;
 pub mod ensemble;
 pub mod fig;
+pub mod pty;
 
 pub use decoder::new_decoder;
 
@@ -78,6 +80,16 @@ pub struct FastInformationBlock {
     bytes: [u8; 30],
 }
 
+impl FastInformationBlock {
+    /// The FIB as broadcast, followed by its CRC
+    pub fn with_crc(&self) -> [u8; 32] {
+        let mut bytes = [0; 32];
+        bytes[0..30].copy_from_slice(&self.bytes);
+        bytes[30..32].copy_from_slice(&crc16_ccitt(&self.bytes).to_be_bytes());
+        bytes
+    }
+}
+
 impl fmt::Debug for FastInformationBlock {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut s = String::new();
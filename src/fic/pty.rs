@@ -0,0 +1,124 @@
+/* ETSI TS 101 756 V2.2.1 (2017-01), 5.4 Tables 12 and 13: international
+programme type codes, by the international table identifier of FIG 0/9 */
+pub const RDS_EUROPE: u8 = 1;
+pub const RBDS: u8 = 2;
+
+const PTY_EUROPE: [&str; 32] = [
+    "None",
+    "News",
+    "Current Affairs",
+    "Information",
+    "Sport",
+    "Education",
+    "Drama",
+    "Culture",
+    "Science",
+    "Varied",
+    "Pop Music",
+    "Rock Music",
+    "Easy Listening",
+    "Light Classical",
+    "Serious Classical",
+    "Other Music",
+    "Weather",
+    "Finance",
+    "Children's",
+    "Social Affairs",
+    "Religion",
+    "Phone In",
+    "Travel",
+    "Leisure",
+    "Jazz Music",
+    "Country Music",
+    "National Music",
+    "Oldies Music",
+    "Folk Music",
+    "Documentary",
+    "Alarm Test",
+    "Alarm",
+];
+
+const PTY_RBDS: [&str; 32] = [
+    "None",
+    "News",
+    "Information",
+    "Sports",
+    "Talk",
+    "Rock",
+    "Classic Rock",
+    "Adult Hits",
+    "Soft Rock",
+    "Top 40",
+    "Country",
+    "Oldies",
+    "Soft",
+    "Nostalgia",
+    "Jazz",
+    "Classical",
+    "Rhythm and Blues",
+    "Soft R&B",
+    "Language",
+    "Religious Music",
+    "Religious Talk",
+    "Personality",
+    "Public",
+    "College",
+    "Unassigned",
+    "Unassigned",
+    "Unassigned",
+    "Unassigned",
+    "Unassigned",
+    "Weather",
+    "Emergency Test",
+    "Emergency",
+];
+
+fn table(inter_table_id: u8) -> &'static [&'static str; 32] {
+    match inter_table_id {
+        RBDS => &PTY_RBDS,
+        // RDS_EUROPE, and the default
+        _ => &PTY_EUROPE,
+    }
+}
+
+pub fn name(inter_table_id: u8, code: u8) -> &'static str {
+    table(inter_table_id)
+        .get(code as usize)
+        .copied()
+        .unwrap_or("Unknown")
+}
+
+/// Look up a programme type by its name, ignoring case, or by its code.
+pub fn from_name(inter_table_id: u8, name: &str) -> Option<u8> {
+    if let Ok(code) = name.parse::<u8>() {
+        return (code < 32).then_some(code);
+    }
+    table(inter_table_id)
+        .iter()
+        .position(|n| n.eq_ignore_ascii_case(name))
+        .map(|code| code as u8)
+}
+
+/// Parse a programme type given on the command line, by its name in either
+/// table or by its code, before the ensemble says which table it uses.
+pub fn parse_genre(s: &str) -> Result<u8, String> {
+    from_name(RDS_EUROPE, s)
+        .or_else(|| from_name(RBDS, s))
+        .ok_or_else(|| format!("{} is not a programme type name or a code below 32", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn genre_names() {
+        assert_eq!(parse_genre("news"), Ok(1));
+        assert_eq!(parse_genre("Rock Music"), Ok(11));
+        // only in the RBDS table
+        assert_eq!(parse_genre("top 40"), Ok(9));
+        assert_eq!(parse_genre("31"), Ok(31));
+        assert!(parse_genre("32").is_err());
+        assert!(parse_genre("polka").is_err());
+    }
+}
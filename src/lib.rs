@@ -1,24 +1,49 @@
 use clap::Parser;
 
+pub mod channels;
+pub mod charset;
 pub mod decode;
+pub mod error;
+pub mod eti;
 pub mod fic;
 pub mod msc;
+pub mod ofdm;
 pub mod output;
 pub mod pad;
 pub mod prs;
 pub mod source;
+pub mod stats;
 pub mod wavefinder;
 
 pub mod receiver;
+pub mod scan;
 
 pub use decode::new_viterbi;
 
-use crate::fic::ensemble::{Ensemble, Service};
+use crate::error::ErrorCounts;
+use crate::stats::ReceptionStats;
+use crate::fic::ensemble::{Alternative, Announcement, Ensemble, Service};
+use crate::pad::Label;
+use crate::pad::mot::Slide;
+use crate::source::iq::IqFormat;
 
 pub enum EventData {
+    // the ensemble once acquired, and again whenever it changes
     Ensemble(Ensemble),
     Service(Service),
-    Label(String),
+    Label(Label),
+    Slide(Slide),
+    // an announcement has started, or with None, ended
+    Announcement(Option<Announcement>),
+    // reception was lost, and where else the service can be found, or with
+    // None, reception resumed
+    Alternatives(Option<Vec<Alternative>>),
+    // how much corrupt data has been skipped, sent as it grows
+    Errors(ErrorCounts),
+    // reception quality over the last second
+    Stats(ReceptionStats),
+    // the frequency in MHz received on, where the source knows it
+    Frequency(f64),
 }
 
 pub struct UiEvent {
@@ -39,6 +64,34 @@ pub struct ControlEvent {
 pub enum CliSource {
     Wavefinder,
     File,
+    // I/Q samples from an SDR, in --file
+    Iq,
+    // an RTL-SDR served by rtl_tcp at --address
+    RtlTcp,
+    // an ETI(NI) recording in --file, already decoded
+    Eti,
+}
+
+// Announcement types to switch to, in ASu/ASw flag order
+#[derive(Debug, Clone, Copy, clap::ValueEnum, PartialEq)]
+pub enum CliAnnouncement {
+    Alarm,
+    Traffic,
+    Transport,
+    Warning,
+    News,
+    Weather,
+    Event,
+    Special,
+    Programme,
+    Sport,
+    Finance,
+}
+
+impl CliAnnouncement {
+    pub fn flag(&self) -> u16 {
+        1 << (*self as u16)
+    }
 }
 
 #[derive(Parser, Debug)]
@@ -46,10 +99,52 @@ pub enum CliSource {
 pub struct Cli {
     #[clap(value_enum, default_value_t=CliSource::Wavefinder)]
     source: CliSource,
-    #[arg(short, long)]
-    service: String,
+    #[arg(short, long, required_unless_present_any = ["scan", "eti"])]
+    service: Option<String>,
     #[arg(short, long)]
     file: Option<std::path::PathBuf>,
+    /// Sample format of an iq --file, at 2.048 Msps
+    #[arg(long, value_enum, default_value_t = IqFormat::Cu8)]
+    iq_format: IqFormat,
+    /// rtl_tcp server to stream samples from, as host:port
+    #[arg(long, default_value = "127.0.0.1:1234")]
+    address: String,
+    /// rtl_tcp tuner gain in dB, automatic if not given
+    #[arg(long)]
+    gain: Option<f64>,
+    /// Channel to tune to, by name (e.g. 12B, LA) or in MHz
+    #[arg(long, value_parser = channels::parse_frequency)]
+    frequency: Option<f64>,
+    /// Directory to write SlideShow images to
+    #[arg(long)]
+    slides: Option<std::path::PathBuf>,
+    /// Announcement types to switch to, e.g. traffic,news
+    #[arg(long, value_enum, value_delimiter = ',')]
+    announcements: Vec<CliAnnouncement>,
+    /// Only list services of this programme type, by name or code
+    #[arg(long, value_parser = fic::pty::parse_genre)]
+    pub genre: Option<u8>,
+    /// Services to record alongside the one playing, by id, or all
+    #[arg(long, value_delimiter = ',')]
+    record: Vec<String>,
+    /// Directory to write recordings to
+    #[arg(long, default_value = ".")]
+    record_dir: std::path::PathBuf,
+    /// Seconds to wait for the ensemble's configuration to be complete
+    #[arg(long, default_value_t = 10)]
+    fic_timeout: u64,
+    /// Scan the Band III channels and write the ensembles found to
+    /// --stations, instead of playing a service
+    #[arg(long)]
+    pub scan: bool,
+    /// Station database written by --scan
+    #[arg(long, default_value = "stations.tsv")]
+    pub stations: std::path::PathBuf,
+    /// Seconds to wait for synchronisation on each channel when scanning
+    #[arg(long, default_value_t = 2)]
+    lock_timeout: u64,
+    /// Write the whole ensemble as ETI(NI) to this file, or - for standard
+    /// output, instead of playing a service
     #[arg(long)]
-    frequency: Option<String>,
+    pub eti: Option<std::path::PathBuf>,
 }
@@ -6,13 +6,19 @@
 #![allow(clippy::upper_case_acronyms)]
 #![allow(clippy::too_many_arguments)]
 
-use std::io;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
 use std::sync::mpsc::{Receiver, Sender};
 use std::thread::JoinHandle;
 use std::time::Duration;
 
 use color_eyre::Result;
-use dab::fic::ensemble::{Ensemble, Service};
+use dab::error::ErrorCounts;
+use dab::stats::ReceptionStats;
+use dab::fic::ensemble::{Alternative, Announcement, Ensemble, Service, announcement_types};
+use dab::fic::pty;
+use itertools::Itertools;
 use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, poll};
 use ratatui::layout::{Alignment, Constraint, Direction, Layout, Rect};
 use ratatui::style::{Modifier, Style};
@@ -22,7 +28,10 @@ use ratatui::widgets::{Block, Cell, Paragraph, Row, Table, TableState};
 use ratatui::{DefaultTerminal, Frame};
 
 use clap::Parser;
+use dab::eti::new_eti_recorder;
+use dab::pad::Label;
 use dab::receiver::new_receiver;
+use dab::scan::{new_scanner, write_stations};
 use dab::{Cli, ControlData, ControlEvent, EventData, UiEvent};
 
 struct App {
@@ -31,17 +40,37 @@ struct App {
     ui_rx: Receiver<UiEvent>,
     ensemble: Option<Ensemble>,
     service: Option<Service>,
-    label: Option<String>,
+    label: Option<Label>,
+    slide: Option<String>,
+    announcement: Option<Announcement>,
+    alternatives: Option<Vec<Alternative>>,
+    // DAB frequencies in MHz the service was last said to be on, and the
+    // next to tune to
+    tune_targets: Vec<f64>,
+    next_target: usize,
+    errors: ErrorCounts,
+    stats: Option<ReceptionStats>,
+    frequency: Option<f64>,
+    genre: Option<u8>,
     tablestate: TableState,
 }
 
 fn main() -> Result<()> {
     let args = Cli::parse();
+    let genre = args.genre;
     color_eyre::install()?;
-    let terminal = ratatui::init();
+
+    if args.scan {
+        return scan(args);
+    }
+    if let Some(path) = args.eti.clone() {
+        return eti(args, &path);
+    }
 
     let mut receiver = new_receiver(args);
-    let (ui_rx, control_tx, receiver_t) = receiver.run();
+    let (ui_rx, control_tx, receiver_t) = receiver.run()?;
+
+    let terminal = ratatui::init();
 
     let mut app = App {
         ui_rx,
@@ -49,6 +78,15 @@ fn main() -> Result<()> {
         ensemble: None,
         service: None,
         label: None,
+        slide: None,
+        announcement: None,
+        alternatives: None,
+        tune_targets: vec![],
+        next_target: 0,
+        errors: ErrorCounts::default(),
+        stats: None,
+        frequency: None,
+        genre,
         exit: false,
         tablestate: TableState::default().with_selected(0),
     };
@@ -58,6 +96,85 @@ fn main() -> Result<()> {
     result
 }
 
+// Scan Band III without the TUI, reporting each channel as it goes
+fn scan(args: Cli) -> Result<()> {
+    let path = args.stations.clone();
+    let stations = new_scanner(args).run(|channel, station| match station {
+        Some(station) => println!(
+            "{:>3} {:.3}MHz: {:04x} {} ({} services)",
+            channel.name,
+            channel.frequency,
+            station.eid,
+            station.label,
+            station.services.len()
+        ),
+        None => println!("{:>3} {:.3}MHz: no signal", channel.name, channel.frequency),
+    });
+    write_stations(&path, &stations)?;
+    println!("{} ensembles written to {:?}", stations.len(), path);
+    Ok(())
+}
+
+// Progress goes to stderr, the ETI may be going to stdout
+fn eti(args: Cli, path: &Path) -> Result<()> {
+    let mut out: Box<dyn Write> = if path == Path::new("-") {
+        Box::new(BufWriter::new(io::stdout().lock()))
+    } else {
+        Box::new(BufWriter::new(File::create(path)?))
+    };
+    let written = new_eti_recorder(args).run(&mut out, |ens, written| {
+        eprintln!(
+            "{} ({} subchannels): {} frames",
+            ens.label().trim(),
+            ens.subchannels().len(),
+            written
+        )
+    })?;
+    eprintln!("{} ETI frames written to {:?}", written, path);
+    Ok(())
+}
+
+// The services listed, narrowed down to the chosen genre
+fn genre_services(ensemble: &Ensemble, genre: Option<u8>) -> Vec<&Service> {
+    ensemble
+        .services()
+        .into_iter()
+        .filter(|s| genre.is_none() || s.pty() == genre)
+        .collect()
+}
+
+// Reception quality over the last second, a dash where nothing was received
+fn render_signal(frame: &mut Frame, area: Rect, stats: &ReceptionStats) {
+    let percent = |rate: Option<f64>| match rate {
+        Some(r) => format!("{:.1}%", r * 100.0),
+        None => "-".to_owned(),
+    };
+    let sync = match stats.sync {
+        Some(s) => format!(
+            "{} c {:.2e} ir {:.1}",
+            if s.locked { "Locked" } else { "Unlocked" },
+            s.c,
+            s.ir
+        ),
+        None => "-".to_owned(),
+    };
+    let ber = match stats.bit_error_rate() {
+        Some(r) => format!("{:.1e}", r),
+        None => "-".to_owned(),
+    };
+    let lines = vec![
+        Line::from(format!("Sync: {}", sync)),
+        Line::from(format!("FIB CRC errors: {}", percent(stats.fib_error_rate()))),
+        Line::from(format!("Channel BER: {}", ber)),
+        Line::from(format!("Audio frame errors: {}", percent(stats.audio_error_rate()))),
+    ];
+
+    let block = Block::bordered()
+        .title(Line::from(" Signal ").centered())
+        .border_set(border::THICK);
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
 impl App {
     fn run(&mut self, mut terminal: DefaultTerminal, receiver_t: JoinHandle<()>) -> Result<()> {
         loop {
@@ -87,6 +204,37 @@ impl App {
                     } => {
                         self.label = Some(label);
                     }
+                    UiEvent {
+                        data: EventData::Slide(slide),
+                    } => {
+                        self.slide = Some(slide.content_name);
+                    }
+                    UiEvent {
+                        data: EventData::Announcement(announcement),
+                    } => {
+                        self.announcement = announcement;
+                    }
+                    UiEvent {
+                        data: EventData::Alternatives(alternatives),
+                    } => {
+                        self.set_tune_targets(alternatives.as_deref());
+                        self.alternatives = alternatives;
+                    }
+                    UiEvent {
+                        data: EventData::Errors(errors),
+                    } => {
+                        self.errors = errors;
+                    }
+                    UiEvent {
+                        data: EventData::Stats(stats),
+                    } => {
+                        self.stats = Some(stats);
+                    }
+                    UiEvent {
+                        data: EventData::Frequency(frequency),
+                    } => {
+                        self.frequency = Some(frequency);
+                    }
                 }
             }
 
@@ -100,11 +248,36 @@ impl App {
         Ok(())
     }
 
+    fn services(&self) -> Vec<&Service> {
+        match &self.ensemble {
+            Some(ensemble) => genre_services(ensemble, self.genre),
+            None => vec![],
+        }
+    }
+
+    // Step through the genres of the services in the ensemble, then all of them
+    fn next_genre(&mut self) {
+        let Some(ensemble) = &self.ensemble else {
+            return;
+        };
+        let genres: Vec<u8> = ensemble
+            .services()
+            .into_iter()
+            .filter_map(|s| s.pty())
+            .sorted()
+            .dedup()
+            .collect();
+        self.genre = match self.genre {
+            None => genres.first().copied(),
+            Some(genre) => genres.into_iter().find(|g| *g > genre),
+        };
+        self.tablestate.select(if self.services().is_empty() { None } else { Some(0) });
+    }
+
     fn set_selected_service(&mut self) {
-        for (i, s) in self.ensemble.as_ref().unwrap().services().into_iter().enumerate() {
-            if self.service.as_ref().unwrap().id == s.id {
-                self.tablestate.select(Some(i));
-            }
+        let id = self.service.as_ref().unwrap().id;
+        if let Some(i) = self.services().into_iter().position(|s| s.id == id) {
+            self.tablestate.select(Some(i));
         }
     }
 
@@ -126,6 +299,8 @@ impl App {
             KeyCode::Char('j') | KeyCode::Down => self.next_row(),
             KeyCode::Char('k') | KeyCode::Up => self.previous_row(),
             KeyCode::Enter => self.select_service(),
+            KeyCode::Char('g') => self.next_genre(),
+            KeyCode::Char('t') => self.tune_alternative(),
             _ => (),
          }
     }
@@ -135,7 +310,9 @@ impl App {
             return;
         }
         if let Some(i) = self.tablestate.selected() {
-            let service = self.ensemble.as_ref().unwrap().services()[i];
+            let Some(service) = self.services().get(i).copied() else {
+                return;
+            };
             if self
                 .control_tx
                 .send(ControlEvent {
@@ -148,13 +325,52 @@ impl App {
         }
     }
 
+    // Kept when the ensemble tuned to knows of no alternatives itself, so
+    // the rest can still be tried
+    fn set_tune_targets(&mut self, alternatives: Option<&[Alternative]>) {
+        let targets: Vec<f64> = alternatives
+            .into_iter()
+            .flatten()
+            .filter_map(|a| match a {
+                Alternative::Dab {
+                    frequency: Some(f), ..
+                } => Some(*f as f64 / 1000.0),
+                _ => None,
+            })
+            .unique_by(|f| (f * 1000.0) as u32)
+            .collect();
+        if !targets.is_empty() && targets != self.tune_targets {
+            self.tune_targets = targets;
+            self.next_target = 0;
+        }
+    }
+
+    // Step through the frequencies the service is also on
+    fn tune_alternative(&mut self) {
+        if self.tune_targets.is_empty() {
+            return;
+        }
+        let frequency = self.tune_targets[self.next_target % self.tune_targets.len()];
+        self.next_target += 1;
+        if self
+            .control_tx
+            .send(ControlEvent {
+                data: ControlData::Tune(frequency),
+            })
+            .is_err()
+        {
+            eprintln!("failed to send Tune");
+        }
+    }
+
     fn next_row(&mut self) {
-        if self.ensemble.is_none() {
+        let len = self.services().len();
+        if len == 0 {
             return;
         }
         let i = match self.tablestate.selected() {
             Some(i) => {
-                if i >= self.ensemble.as_ref().unwrap().services().len() - 1 {
+                if i >= len - 1 {
                     0
                 } else {
                     i + 1
@@ -166,13 +382,14 @@ impl App {
     }
 
     fn previous_row(&mut self) {
-        if self.ensemble.is_none() {
+        let len = self.services().len();
+        if len == 0 {
             return;
         }
         let i = match self.tablestate.selected() {
             Some(i) => {
                 if i == 0 {
-                    self.ensemble.as_ref().unwrap().services().len() - 1
+                    len - 1
                 } else {
                     i - 1
                 }
@@ -211,12 +428,53 @@ impl App {
             .constraints(vec![Constraint::Percentage(20), Constraint::Percentage(10), Constraint::Percentage(70)])
             .split(frame.area());
 
+        // signal quality alongside the status, once there is any
+        let status_area = match &self.stats {
+            Some(stats) => {
+                let top = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints(vec![Constraint::Percentage(65), Constraint::Percentage(35)])
+                    .split(layout[0]);
+                render_signal(frame, top[1], stats);
+                top[0]
+            }
+            None => layout[0],
+        };
+
         if self.ensemble.is_some() {
-            let status_text = Line::from("Ensemble Found");
+            let found = match self.frequency {
+                Some(frequency) => format!("Ensemble Found on {:.3}MHz", frequency),
+                None => "Ensemble Found".to_owned(),
+            };
+            let mut status_text = vec![Line::from(found)];
+            if let Some(announcement) = &self.announcement {
+                status_text.push(Line::from(format!(
+                    "Announcement: {}",
+                    announcement_types(announcement.asw).join(", ")
+                )));
+            }
+            if let Some(alternatives) = &self.alternatives {
+                status_text.push(Line::from("Signal lost"));
+                if !alternatives.is_empty() {
+                    status_text.push(Line::from(format!(
+                        "Also on: {}",
+                        alternatives.iter().map(|a| a.to_string()).join(", ")
+                    )));
+                    if !self.tune_targets.is_empty() {
+                        status_text.push(Line::from("Press t to tune there"));
+                    }
+                }
+            }
+            if self.errors.total() > 0 {
+                status_text.push(Line::from(format!(
+                    "Skipped: {} FIG, {} MSC, {} PAD, {} audio",
+                    self.errors.fig, self.errors.msc, self.errors.pad, self.errors.output
+                )));
+            }
 
             frame.render_widget(
                 Paragraph::new(status_text).centered().block(top_block),
-                layout[0],
+                status_area,
             );
 
             self.render_table(frame, layout[2]);
@@ -225,14 +483,25 @@ impl App {
 
             frame.render_widget(
                 Paragraph::new(status_text).centered().block(top_block),
-                layout[0],
+                status_area,
             );
         }
 
         if let Some(label) = &self.label {
-            let paragraph = Paragraph::new(
-                Line::from(label.to_string())
-            ).alignment(Alignment::Left);
+            let mut lines = vec![Line::from(label.label.to_string())];
+            match (&label.artist, &label.title) {
+                (Some(artist), Some(title)) => {
+                    lines.push(Line::from(format!("Now playing: {} - {}", artist, title)));
+                }
+                (None, Some(title)) => {
+                    lines.push(Line::from(format!("Now playing: {}", title)));
+                }
+                _ => {}
+            }
+            if let Some(slide) = &self.slide {
+                lines.push(Line::from(format!("Slide: {}", slide)));
+            }
+            let paragraph = Paragraph::new(lines).alignment(Alignment::Left);
 
             frame.render_widget(
                 paragraph.block(dls_block),
@@ -244,36 +513,47 @@ impl App {
     fn render_table(&mut self, frame: &mut Frame, area: Rect) {
         let ensemble = self.ensemble.as_ref().unwrap();
 
-        let header = ["Ensemble", "Label", "Id", "Bitrate", "Type"]
+        let header = ["Ensemble", "Label", "Short", "Id", "Bitrate", "Type", "Genre"]
             .into_iter()
             .map(Cell::from)
             .collect::<Row>()
             .height(1);
 
-        let rows = ensemble
-            .services()
+        let rows = genre_services(ensemble, self.genre)
             .into_iter()
             .enumerate()
             .map(|(i, service)| {
                 [
-                    Cell::from(Text::from(ensemble.label())),
+                    Cell::from(Text::from(ensemble.short_label())),
                     Cell::from(Text::from(service.label())),
+                    Cell::from(Text::from(service.short_label())),
                     Cell::from(Text::from(format!("{:04x}", service.id))),
-                    Cell::from(Text::from(format!(
-                        "{}kbps",
-                        service.subchannel().bitrate()
-                    ))),
-                    Cell::from(Text::from(format!(
-                        "{:?}",
-                        service.subchannel().subchannel_type()
-                    ))),
+                    Cell::from(Text::from(
+                        service
+                            .subchannel()
+                            .map(|s| format!("{}kbps", s.bitrate()))
+                            .unwrap_or_default(),
+                    )),
+                    Cell::from(Text::from(
+                        service
+                            .subchannel()
+                            .map(|s| format!("{:?}", s.subchannel_type()))
+                            .unwrap_or_default(),
+                    )),
+                    Cell::from(Text::from(ensemble.pty_name(service).unwrap_or(""))),
                 ]
                 .into_iter()
                 .collect::<Row>()
                 .height(1)
             });
 
-        let bottom_title = Line::from(" Ensemble Details ");
+        let bottom_title = match self.genre {
+            Some(genre) => {
+                let table = ensemble.inter_table_id().unwrap_or(pty::RDS_EUROPE);
+                Line::from(format!(" Ensemble Details ({}) ", pty::name(table, genre)))
+            }
+            None => Line::from(" Ensemble Details "),
+        };
         let bottom_block = Block::bordered()
             .title(bottom_title.centered())
             .border_set(border::THICK);
@@ -284,11 +564,13 @@ impl App {
         let table = Table::new(
             rows,
             [
-                Constraint::Length(18),
-                Constraint::Length(18),
+                Constraint::Length(8),
+                Constraint::Length(16),
+                Constraint::Length(8),
                 Constraint::Length(4),
                 Constraint::Length(7),
                 Constraint::Length(20),
+                Constraint::Length(16),
             ],
         )
         .header(header)
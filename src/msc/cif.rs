@@ -1,15 +1,18 @@
-use crate::fic::ensemble::Service;
+use crate::error::{Error, Result};
+use crate::fic::ensemble::SubChannelOrganisation;
 use crate::msc::{ChannelSymbols, SymbolRange};
 
 const MSCSTART: u16 = 5;
 const CUSPERSYM: u16 = 48;
 const SYMSPERCIF: u8 = 18;
 
-pub fn channel_symbols(service: &Service) -> ChannelSymbols {
-    let subchannel = service.subchannel();
+pub fn channel_symbols(org: &SubChannelOrganisation) -> Result<ChannelSymbols> {
+    if org.size == 0 {
+        return Err(Error::Msc("subchannel not organised"));
+    }
 
-    let size = subchannel.size();
-    let start = subchannel.startaddr();
+    let size = org.size;
+    let start = org.start;
     let startcu = start % CUSPERSYM;
 
     let symbol_0 = SymbolRange {
@@ -30,9 +33,9 @@ pub fn channel_symbols(service: &Service) -> ChannelSymbols {
         })
         .collect();
 
-    ChannelSymbols {
+    Ok(ChannelSymbols {
         ranges: symbols.try_into().unwrap(),
         startcu,
         count,
-    }
+    })
 }
@@ -0,0 +1,128 @@
+#![allow(non_snake_case)]
+
+use bitvec::prelude::*;
+use std::collections::HashMap;
+
+use crate::decode::crc16_ccitt_check;
+use crate::error::{Error, Result};
+
+/// An MSC data group, ETSI EN 300 401 V2.1.1 (2017-01), 5.3.3
+#[derive(Debug, Clone)]
+pub struct DataGroup {
+    pub dg_type: u8,
+    pub continuity: u8,
+    pub repetition: u8,
+    pub extension: Option<u16>,
+    pub segment: Option<Segment>,
+    pub transport_id: Option<u16>,
+    pub end_user_address: Vec<u8>,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Segment {
+    pub last: bool,
+    pub number: u16,
+}
+
+/// Parse a complete data group, checking its CRC where present.
+pub fn parse_data_group(bytes: &[u8]) -> Result<DataGroup> {
+    if bytes.len() < 2 {
+        return Err(Error::Msc("data group too short"));
+    }
+    let header = bytes[0..2].view_bits::<Msb0>();
+    let ExtensionFlag = header[0];
+    let CrcFlag = header[1];
+    let SegmentFlag = header[2];
+    let UserAccessFlag = header[3];
+    let dg_type: u8 = header[4..8].load_be();
+    let continuity: u8 = header[8..12].load_be();
+    let repetition: u8 = header[12..16].load_be();
+
+    let mut end = bytes.len();
+    if CrcFlag {
+        if !crc16_ccitt_check(bytes) {
+            return Err(Error::Msc("data group crc check failed"));
+        }
+        end -= 2;
+    }
+
+    let mut offset = 2;
+    let mut take = |n: usize| -> Result<&[u8]> {
+        if offset + n > end {
+            return Err(Error::Msc("data group truncated"));
+        }
+        let field = &bytes[offset..offset + n];
+        offset += n;
+        Ok(field)
+    };
+
+    let extension = if ExtensionFlag {
+        let field = take(2)?;
+        Some(u16::from_be_bytes([field[0], field[1]]))
+    } else {
+        None
+    };
+
+    let segment = if SegmentFlag {
+        let field = take(2)?;
+        let word = u16::from_be_bytes([field[0], field[1]]);
+        Some(Segment {
+            last: word & 0x8000 != 0,
+            number: word & 0x7fff,
+        })
+    } else {
+        None
+    };
+
+    let mut transport_id = None;
+    let mut end_user_address = Vec::new();
+    if UserAccessFlag {
+        let field = take(1)?[0];
+        let TransportIdFlag = field & 0x10 != 0;
+        let LengthIndicator = (field & 0x0f) as usize;
+        let mut address = take(LengthIndicator)?;
+        if TransportIdFlag {
+            if address.len() < 2 {
+                return Err(Error::Msc("data group transport id truncated"));
+            }
+            transport_id = Some(u16::from_be_bytes([address[0], address[1]]));
+            address = &address[2..];
+        }
+        end_user_address = address.to_vec();
+    }
+
+    Ok(DataGroup {
+        dg_type,
+        continuity,
+        repetition,
+        extension,
+        segment,
+        transport_id,
+        end_user_address,
+        data: bytes[offset..end].to_vec(),
+    })
+}
+
+/// Data groups may be sent more than once; repeats carry the continuity
+/// index of the original, so only the first of each is passed on.
+#[derive(Debug, Default)]
+pub struct RepetitionFilter {
+    last: HashMap<u8, u8>,
+}
+
+pub fn new_repetition_filter() -> RepetitionFilter {
+    RepetitionFilter {
+        last: HashMap::new(),
+    }
+}
+
+impl RepetitionFilter {
+    pub fn is_new(&mut self, dg: &DataGroup) -> bool {
+        self.last.insert(dg.dg_type, dg.continuity) != Some(dg.continuity)
+    }
+
+    pub fn reset(&mut self) {
+        self.last.clear();
+    }
+}
@@ -4,13 +4,24 @@ use crate::{
     decode::{
         Bit, Viterbi, bit_reverse, bits_to_bytes, bytes_to_bits, qpsk_symbol_demapper, scramble,
     },
-    fic::ensemble::{Protection, SubChannel, SubChannelType},
-    msc::{Buffers, ChannelSymbols, MainServiceChannelBuffer, SizedBuffer, tables::PVEC},
+    error::{Error, Result},
+    fic::ensemble::SubChannelOrganisation,
+    msc::{
+        Buffers, ChannelSymbols, MainServiceChannelBuffer, SizedBuffer,
+        tables::{EepProf, PVEC, ProtectionProfile, UepProf},
+    },
     new_viterbi,
     wavefinder::Buffer,
 };
 use std::fmt;
 
+// Which of the 16 CIFs each bit of a logical frame is delayed to, by its
+// index modulo 16
+pub(super) const TD_MAP: [usize; 16] = [0, 8, 4, 12, 2, 10, 6, 14, 1, 9, 5, 13, 3, 11, 7, 15];
+
+// the protection profile doesn't match the subchannel size
+const TOO_FEW_BITS: Error = Error::Msc("too few bits to depuncture");
+
 pub struct MainServiceChannelDecoder {
     viterbi: Viterbi,
 }
@@ -41,25 +52,32 @@ impl MainServiceChannelDecoder {
     pub fn decode(
         &self,
         buffers: &SizedBuffer,
-        sc: &dyn SubChannel,
+        org: &SubChannelOrganisation,
         sym: &ChannelSymbols,
-    ) -> Vec<u8> {
+    ) -> Result<Vec<u8>> {
         // time disinterleave
         let dis = match buffers {
-            SizedBuffer::One(buffers) => self.time_disinterleave::<1>(buffers, sc, sym),
-            SizedBuffer::Two(buffers) => self.time_disinterleave::<2>(buffers, sc, sym),
-            SizedBuffer::Three(buffers) => self.time_disinterleave::<3>(buffers, sc, sym),
+            SizedBuffer::One(buffers) => self.time_disinterleave::<1>(buffers, org, sym)?,
+            SizedBuffer::Two(buffers) => self.time_disinterleave::<2>(buffers, org, sym)?,
+            SizedBuffer::Three(buffers) => self.time_disinterleave::<3>(buffers, org, sym)?,
         };
 
-        // depuncture
-        let depunctured = match (sc.subchannel_type(), sc.protection()) {
-            (SubChannelType::Audio, Protection::EEP) => self.eep_depuncture(&dis, sc),
-            (SubChannelType::Audio, Protection::UEP) => self.uep_depuncture(&dis, sc),
-            (SubChannelType::Data, _) => self.eep_depuncture_data(&dis, sc),
-            (t, p) => panic!("unexpected subchannel configuration: {:?} {:?}", t, p),
+        self.decode_subchannel(&dis, &org.profile)
+    }
+
+    /// Decode the bits of a subchannel already time deinterleaved, audio or
+    /// data alike
+    pub fn decode_subchannel(&self, bits: &[u8], profile: &ProtectionProfile) -> Result<Vec<u8>> {
+        let depunctured = match profile {
+            ProtectionProfile::EEP(eep) => self.eep_depuncture(bits, eep)?,
+            ProtectionProfile::UEP(uep) => self.uep_depuncture(bits, uep)?,
         };
+        Ok(self.unprotect(&depunctured))
+    }
 
-        let vited = self.viterbi.viterbi(&depunctured);
+    // Viterbi decode and undo the energy dispersal
+    fn unprotect(&self, depunctured: &[Bit]) -> Vec<u8> {
+        let vited = self.viterbi.viterbi(depunctured);
         let scrambled = scramble(&vited);
         bits_to_bytes(&scrambled)
     }
@@ -67,51 +85,48 @@ impl MainServiceChannelDecoder {
     fn time_disinterleave<const N: usize>(
         &self,
         buffers: &Buffers<N>,
-        sc: &dyn SubChannel,
+        org: &SubChannelOrganisation,
         sym: &ChannelSymbols,
-    ) -> Vec<u8> {
-        const TD_MAP: [usize; 16] = [0, 8, 4, 12, 2, 10, 6, 14, 1, 9, 5, 13, 3, 11, 7, 15];
+    ) -> Result<Vec<u8>> {
         const BITSPERCU: u16 = 64;
-        let mut result = Vec::<u8>::with_capacity(sc.size() as usize * BITSPERCU as usize);
+        let mut result = Vec::<u8>::with_capacity(org.size as usize * BITSPERCU as usize);
 
-        for i in 0..(sc.size() * BITSPERCU) as usize {
+        for i in 0..(org.size * BITSPERCU) as usize {
             let cif = TD_MAP[i % 16];
             let offset = (BITSPERCU * sym.startcu) as usize + i;
             let n = floor(offset as f64 / 3072.0f64) as usize;
             let m = offset % 3072;
 
-            if let Some(buf) = buffers.symbols[(buffers.lframe + cif) % 16][n] {
-                let bit = buf.bits[m];
-                result.push(bit);
-            } else {
-                panic!("missing buffer!");
+            match buffers.symbols[(buffers.lframe + cif) % 16].get(n) {
+                Some(Some(buf)) => result.push(buf.bits[m]),
+                _ => return Err(Error::Msc("missing buffer")),
             }
         }
 
-        result
+        Ok(result)
     }
 
-    fn eep_depuncture(&self, _bits: &[u8], _sc: &dyn SubChannel) -> Vec<Bit> {
-        // println!("eep got bits of len {}", bits.len());
-        Vec::new()
+    fn eep_depuncture(&self, bits: &[u8], eep: &EepProf) -> Result<Vec<Bit>> {
+        self.depuncture(bits, &eep.l, &eep.pi)
     }
 
-    fn uep_depuncture(&self, bits: &[u8], sc: &dyn SubChannel) -> Vec<Bit> {
-        const BLKSIZE: usize = 128;
+    fn uep_depuncture(&self, bits: &[u8], uep: &UepProf) -> Result<Vec<Bit>> {
+        self.depuncture(bits, &uep.l, &uep.pi)
+    }
 
-        let uep = match sc.uep_profile() {
-            Some(p) => p,
-            None => panic!("no UEP profile while uep_depuncturing?"),
-        };
+    // Each region is l blocks of 128 bits punctured with PVEC[pi],
+    // followed by the 24 tail bits punctured with V_T (as PVEC[7]).
+    fn depuncture(&self, bits: &[u8], l: &[usize], pi: &[usize]) -> Result<Vec<Bit>> {
+        const BLKSIZE: usize = 128;
 
         let mut result: Vec<Bit> = Vec::with_capacity(4 * bits.len());
 
         let mut iter = bits.iter();
 
-        for indx in 0..4 {
-            for i in 0..(BLKSIZE * uep.l[indx]) {
-                if PVEC[uep.pi[indx]][i % 32] == 1 {
-                    result.push(Bit::from_u8(iter.next().unwrap()));
+        for indx in 0..l.len() {
+            for i in 0..(BLKSIZE * l[indx]) {
+                if PVEC[pi[indx]][i % 32] == 1 {
+                    result.push(Bit::from_u8(iter.next().ok_or(TOO_FEW_BITS)?));
                 } else {
                     result.push(Bit::Erased);
                 }
@@ -120,17 +135,74 @@ impl MainServiceChannelDecoder {
 
         for i in 0..24 {
             if PVEC[7][i % 32] == 1 {
-                result.push(Bit::from_u8(iter.next().unwrap()));
+                result.push(Bit::from_u8(iter.next().ok_or(TOO_FEW_BITS)?));
             } else {
                 result.push(Bit::Erased);
             }
         }
 
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::msc::tables::eep_profile;
+
+    // Puncture the mother code as a transmitter would
+    fn puncture(bits: &[u8], l: &[usize], pi: &[usize]) -> Vec<u8> {
+        let mut iter = bits.iter();
+        let mut result = vec![];
+        for (l, pi) in l.iter().zip(pi) {
+            for i in 0..128 * l {
+                let bit = iter.next().unwrap();
+                if PVEC[*pi][i % 32] == 1 {
+                    result.push(*bit);
+                }
+            }
+        }
+        for i in 0..24 {
+            let bit = iter.next().unwrap();
+            if PVEC[7][i % 32] == 1 {
+                result.push(*bit);
+            }
+        }
+        assert!(iter.next().is_none());
         result
     }
 
-    fn eep_depuncture_data(&self, _bits: &[u8], _sc: &dyn SubChannel) -> Vec<Bit> {
-        // println!("eep data got bits of len {}", bits.len());
-        Vec::new()
+    #[test]
+    fn eep_depuncture_round_trip() {
+        let decoder = new_decoder();
+        // 2-A at 64 kbit/s, 32 CUs, and 3-B at 128 kbit/s, 72 CUs
+        for (opt, lvl, size) in [(0, 1, 32), (1, 2, 72)] {
+            let eep = eep_profile(opt, lvl, size).unwrap();
+            // a logical frame: 24ms at the bitrate
+            let data = (0..eep.BitRate as usize * 24)
+                .map(|i| ((i * 7 + i / 3) % 5 % 2) as u8)
+                .collect::<Vec<u8>>();
+            let encoded = decoder.viterbi.encode(&data);
+            let punctured = puncture(&encoded, &eep.l, &eep.pi);
+            assert_eq!(punctured.len(), size as usize * 64);
+
+            let depunctured = decoder.depuncture(&punctured, &eep.l, &eep.pi).unwrap();
+            assert_eq!(depunctured.len(), encoded.len());
+            for (d, e) in depunctured.iter().zip(&encoded) {
+                match d {
+                    Bit::Erased => {}
+                    Bit::True => assert_eq!(*e, 1),
+                    Bit::False => assert_eq!(*e, 0),
+                }
+            }
+            assert_eq!(decoder.viterbi.viterbi(&depunctured), data);
+
+            // a subchannel too small for the profile
+            assert!(
+                decoder
+                    .depuncture(&punctured[..punctured.len() - 1], &eep.l, &eep.pi)
+                    .is_err()
+            );
+        }
     }
 }
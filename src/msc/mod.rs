@@ -1,12 +1,17 @@
+use crate::error::{Error, Result};
+use crate::fic::ensemble::{Service, SubChannelOrganisation};
 use crate::msc::decoder::{MainServiceChannelDecoder, new_decoder};
-use crate::{fic::ensemble::Service, wavefinder::Buffer};
+use crate::wavefinder::Buffer;
 use bitvec::prelude::*;
 use enum_dispatch::enum_dispatch;
 use std::fmt;
 use std::ops::Range;
 
 mod cif;
+pub mod datagroup;
 mod decoder;
+pub mod multiplex;
+pub mod packet;
 pub mod tables;
 
 #[enum_dispatch]
@@ -32,8 +37,9 @@ pub struct Buffers<const N: usize> {
 }
 
 #[derive(Debug)]
-pub struct MainServiceChannel<'a> {
-    service: &'a Service,
+pub struct MainServiceChannel {
+    service: Service,
+    org: SubChannelOrganisation,
     symbols: ChannelSymbols,
     cur_frame: u8,
     cur_sym: u8,
@@ -124,9 +130,23 @@ pub struct ChannelSymbols {
     pub count: u16,
 }
 
-pub fn new_channel(service: &Service) -> MainServiceChannel<'_> {
-    let decoder = new_decoder();
-    let symbols = cif::channel_symbols(service);
+/// A channel for the service's subchannel
+pub fn new_channel(service: &Service) -> Result<MainServiceChannel> {
+    let org = service
+        .subchannel()
+        .ok_or(Error::Msc("no subchannels"))?
+        .organisation()
+        .ok_or(Error::Msc("subchannel not organised"))?;
+    new_subchannel_channel(service, &org)
+}
+
+/// A channel for one of the subchannels in the CIF, as organised by FIG
+/// 0/1, carrying a component of the service
+pub fn new_subchannel_channel(
+    service: &Service,
+    org: &SubChannelOrganisation,
+) -> Result<MainServiceChannel> {
+    let symbols = cif::channel_symbols(org)?;
     let buffers = match symbols.count {
         1 => SizedBuffer::One(Buffers::<1> {
             symbols: [[None; 1]; 16],
@@ -146,17 +166,18 @@ pub fn new_channel(service: &Service) -> MainServiceChannel<'_> {
             lframe: 0,
             full: false,
         }),
-        _ => panic!("unexpected count"),
+        _ => return Err(Error::Msc("subchannel spans more than three symbols")),
     };
-    MainServiceChannel {
-        service,
+    Ok(MainServiceChannel {
+        service: service.clone(),
+        org: *org,
         symbols,
         cur_frame: 0,
         cur_sym: 0,
         cifcnt: 0,
         buffers,
-        decoder,
-    }
+        decoder: new_decoder(),
+    })
 }
 
 #[derive(Debug)]
@@ -166,16 +187,23 @@ pub struct MainServiceChannelFrame {
     pub bits: Vec<u8>,
 }
 
-impl<'a> MainServiceChannel<'a> {
-    pub fn try_buffer(&mut self, buffer: &Buffer) -> Option<MainServiceChannelFrame> {
+impl MainServiceChannel {
+    pub fn try_buffer(&mut self, buffer: &Buffer) -> Result<Option<MainServiceChannelFrame>> {
+        if let Some(eti) = &buffer.eti {
+            return match eti.stream(self.org.start) {
+                Some(stream) => self.try_stream(eti.fct, &stream.data).map(Some),
+                None => Err(Error::Msc("subchannel not in the ETI frame")),
+            };
+        }
+
         let symbol = buffer.bytes[2];
         let frame = buffer.bytes[3];
 
         if symbol <= 4 {
-            return None;
+            return Ok(None);
         }
         if symbol == self.cur_sym {
-            return None;
+            return Ok(None);
         }
         self.cur_sym = symbol;
 
@@ -213,22 +241,36 @@ impl<'a> MainServiceChannel<'a> {
         }
 
         if buffer_full {
-            Some(self.decode())
+            self.decode().map(Some)
         } else {
-            None
+            Ok(None)
+        }
+    }
+
+    /// A logical frame of the subchannel already decoded elsewhere, which
+    /// only has to be the right length for its bitrate
+    pub fn try_stream(&mut self, frame: u8, data: &[u8]) -> Result<MainServiceChannelFrame> {
+        if data.len() != self.org.bitrate as usize * 3 {
+            return Err(Error::Msc("stream length doesn't match the bitrate"));
         }
+        self.cur_frame = frame;
+        self.cifcnt += 1;
+        Ok(MainServiceChannelFrame {
+            frame,
+            bitrate: self.org.bitrate,
+            bits: data.to_vec(),
+        })
     }
 
-    fn decode(&self) -> MainServiceChannelFrame {
+    fn decode(&self) -> Result<MainServiceChannelFrame> {
         let bits = self
             .decoder
-            .decode(&self.buffers, self.service.subchannel(), &self.symbols);
-        let bitrate = self.service.subchannel().bitrate();
-        MainServiceChannelFrame {
+            .decode(&self.buffers, &self.org, &self.symbols)?;
+        Ok(MainServiceChannelFrame {
             frame: self.cur_frame,
-            bitrate,
+            bitrate: self.org.bitrate,
             bits,
-        }
+        })
     }
 
     fn deinterleave(&self, buffer: &Buffer) -> MainServiceChannelBuffer {
@@ -257,4 +299,59 @@ impl<'a> MainServiceChannel<'a> {
         // Safety: transmuting to a type with less strict alignment, u16 -> u8
         unsafe { std::mem::transmute::<[u16; 5], [u8; 10]>(words) }
     }
+
+    pub fn service(&self) -> &Service {
+        &self.service
+    }
+
+    pub fn organisation(&self) -> &SubChannelOrganisation {
+        &self.org
+    }
+}
+
+/// The symbols needed by all of the channels, so several subchannels can be
+/// decoded at once.
+pub fn merged_selstr(channels: &[&MainServiceChannel]) -> [u8; 10] {
+    channels.iter().fold([0; 10], |mut selstr, channel| {
+        for (byte, b) in selstr.iter_mut().zip(channel.selstr()) {
+            *byte |= b;
+        }
+        selstr
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fic::ensemble::{new_service, new_subchannel_organisation};
+    use crate::fic::fig::Information;
+
+    // 96kbit/s at protection level 3A, boxed as the buffers are large
+    fn channel(id: u8, start: u16) -> Box<MainServiceChannel> {
+        let org = new_subchannel_organisation(&Information::SubChannelLong {
+            SubChId: id,
+            StartAddr: start,
+            Opt: 0,
+            ProtLvl: 2,
+            SubChSz: 72,
+        })
+        .unwrap();
+        Box::new(new_subchannel_channel(&new_service(0xc221), &org).unwrap())
+    }
+
+    #[test]
+    fn merged_selstr_has_every_channels_symbols() {
+        // at the start and near the end of each CIF
+        let (first, second) = (channel(1, 0), channel(2, 720));
+        let (a, b) = (first.selstr(), second.selstr());
+
+        let merged = merged_selstr(&[&first, &second]);
+        for i in 0..10 {
+            assert_eq!(merged[i], a[i] | b[i]);
+        }
+        assert_ne!(merged, a);
+        assert_ne!(merged, b);
+        assert_eq!(merged_selstr(&[&first]), a);
+        assert_eq!(merged_selstr(&[]), [0; 10]);
+    }
 }
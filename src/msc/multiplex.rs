@@ -0,0 +1,121 @@
+use std::collections::VecDeque;
+use std::mem;
+
+use crate::error::{Error, Result};
+use crate::fic::ensemble::SubChannelOrganisation;
+use crate::msc::decoder::{MainServiceChannelDecoder, TD_MAP, new_decoder};
+use crate::wavefinder::Buffer;
+
+const MSCSTART: u8 = 5;
+const SYMSPERCIF: u8 = 18;
+const CIFSPERFRAME: u8 = 4;
+const BITSPERCU: usize = 64;
+const CIFBITS: usize = 864 * BITSPERCU;
+// a logical frame is time interleaved over this many CIFs
+const DEPTH: usize = 16;
+
+/// The whole MSC, kept for the last 16 CIFs so any subchannel in it can be
+/// time deinterleaved and decoded, not only a selected one.
+pub struct Multiplex {
+    // oldest first, each the frequency deinterleaved bits of its 18 symbols
+    cifs: VecDeque<Vec<u8>>,
+    current: Vec<u8>,
+    frame: u8,
+    next_symbol: u8,
+    // frame number and CIF of the last CIF completed
+    last: Option<(u8, u8)>,
+    decoder: MainServiceChannelDecoder,
+}
+
+/// A CIF with all of its symbols, and how many CIFs without a gap are held
+/// up to and including it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompleteCif {
+    pub frame: u8,
+    pub cif: u8,
+    pub contiguous: usize,
+}
+
+pub fn new_multiplex() -> Multiplex {
+    Multiplex {
+        cifs: VecDeque::with_capacity(DEPTH),
+        current: Vec::with_capacity(CIFBITS),
+        frame: 0,
+        next_symbol: 0,
+        last: None,
+        decoder: new_decoder(),
+    }
+}
+
+impl Multiplex {
+    pub fn try_buffer(&mut self, buffer: &Buffer) -> Option<CompleteCif> {
+        let symbol = buffer.bytes[2];
+        let frame = buffer.bytes[3];
+
+        if !(MSCSTART..MSCSTART + SYMSPERCIF * CIFSPERFRAME).contains(&symbol) {
+            return None;
+        }
+        // the same symbol again
+        if symbol + 1 == self.next_symbol && frame == self.frame {
+            return None;
+        }
+
+        let offset = symbol - MSCSTART;
+        if offset.is_multiple_of(SYMSPERCIF) {
+            self.current.clear();
+            self.frame = frame;
+        } else if symbol != self.next_symbol || frame != self.frame {
+            // println!("missed a symbol before {} in frame {}", symbol, frame);
+            self.next_symbol = 0;
+            return None;
+        }
+        self.current
+            .extend_from_slice(&self.decoder.deinterleave(buffer).bits);
+        self.next_symbol = symbol + 1;
+
+        if !(offset + 1).is_multiple_of(SYMSPERCIF) {
+            return None;
+        }
+
+        let cif = offset / SYMSPERCIF;
+        let follows = match self.last {
+            Some((f, c)) => {
+                (f == frame && c + 1 == cif) || (f != frame && c + 1 == CIFSPERFRAME && cif == 0)
+            }
+            None => false,
+        };
+        if !follows {
+            self.cifs.clear();
+        }
+        if self.cifs.len() == DEPTH {
+            self.cifs.pop_front();
+        }
+        self.cifs.push_back(mem::take(&mut self.current));
+        self.last = Some((frame, cif));
+        self.next_symbol = 0;
+
+        Some(CompleteCif {
+            frame,
+            cif,
+            contiguous: self.cifs.len(),
+        })
+    }
+
+    /// The subchannel's logical frame from the oldest CIF held, which is
+    /// complete once 16 CIFs are
+    pub fn decode(&self, org: &SubChannelOrganisation) -> Result<Vec<u8>> {
+        if self.cifs.len() < DEPTH {
+            return Err(Error::Msc("missing buffer"));
+        }
+        let start = org.start as usize * BITSPERCU;
+        let size = org.size as usize * BITSPERCU;
+        if start + size > CIFBITS {
+            return Err(Error::Msc("subchannel beyond the end of the CIF"));
+        }
+
+        let bits = (0..size)
+            .map(|i| self.cifs[TD_MAP[i % DEPTH]][start + i])
+            .collect::<Vec<u8>>();
+        self.decoder.decode_subchannel(&bits, &org.profile)
+    }
+}
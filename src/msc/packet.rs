@@ -0,0 +1,260 @@
+#![allow(non_snake_case)]
+
+use bitvec::prelude::*;
+
+use crate::decode::crc16_ccitt_check;
+use crate::error::{Error, Result};
+use crate::fic::ensemble::{DataSubChannel, Service, SubChannel};
+use crate::msc::datagroup::{DataGroup, RepetitionFilter, new_repetition_filter, parse_data_group};
+use crate::msc::{MainServiceChannel, MainServiceChannelFrame, new_subchannel_channel};
+use crate::wavefinder::Buffer;
+
+/* ETSI EN 300 401 V2.1.1 (2017-01), 5.3.2: packet lengths in bytes */
+const PACKET_LENGTHS: [usize; 4] = [24, 48, 72, 96];
+
+/* Address 0 is reserved for padding packets */
+const PADDING_ADDRESS: u16 = 0;
+
+#[derive(Debug)]
+struct PacketHeader {
+    length: usize,
+    continuity: u8,
+    first: bool,
+    last: bool,
+    address: u16,
+    _command: bool,
+    useful_length: usize,
+}
+
+impl PacketHeader {
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let bits = bytes[0..3].view_bits::<Msb0>();
+        let PacketLength: usize = bits[0..2].load_be();
+        Self {
+            length: PACKET_LENGTHS[PacketLength],
+            continuity: bits[2..4].load_be(),
+            first: bits[4],
+            last: bits[5],
+            address: bits[6..16].load_be(),
+            _command: bits[16],
+            useful_length: bits[17..24].load_be(),
+        }
+    }
+}
+
+/// Packet mode demultiplexer for one packet address in a data subchannel.
+/// Packets are reassembled into MSC data groups, which are passed on once
+/// each, however many times they are repeated.
+#[derive(Debug)]
+pub struct PacketDemultiplexer {
+    address: u16,
+    data_groups: bool,
+    continuity: Option<u8>,
+    group: Vec<u8>,
+    in_group: bool,
+    repetition: RepetitionFilter,
+    pub crc_errors: u32,
+}
+
+pub fn new_packet_demultiplexer(subchannel: &DataSubChannel) -> PacketDemultiplexer {
+    PacketDemultiplexer {
+        address: subchannel.packet_addr(),
+        data_groups: subchannel.data_groups(),
+        continuity: None,
+        group: Vec::new(),
+        in_group: false,
+        repetition: new_repetition_filter(),
+        crc_errors: 0,
+    }
+}
+
+impl PacketDemultiplexer {
+    pub fn reset(&mut self) {
+        self.continuity = None;
+        self.group.clear();
+        self.in_group = false;
+        self.repetition.reset();
+    }
+
+    /// Feed one logical frame of the subchannel, returning any data groups
+    /// completed by it. Services not using data groups (DG flag set) have
+    /// each complete packet sequence returned as the data of a bare group.
+    pub fn output(&mut self, frame: &MainServiceChannelFrame) -> Vec<DataGroup> {
+        let bytes = &frame.bits;
+        let mut groups = Vec::new();
+        let mut offset = 0;
+
+        while offset + 3 <= bytes.len() {
+            let header = PacketHeader::from_bytes(&bytes[offset..]);
+            if offset + header.length > bytes.len() {
+                break;
+            }
+            let packet = &bytes[offset..(offset + header.length)];
+            offset += header.length;
+
+            if !crc16_ccitt_check(packet) {
+                self.crc_errors += 1;
+                // the packet length may itself be corrupt, so drop the frame
+                self.in_group = false;
+                break;
+            }
+
+            if header.address == PADDING_ADDRESS || header.address != self.address {
+                continue;
+            }
+
+            if let Some(group) = self.push_packet(&header, packet) {
+                groups.push(group);
+            }
+        }
+
+        groups
+    }
+
+    fn push_packet(&mut self, header: &PacketHeader, packet: &[u8]) -> Option<DataGroup> {
+        let expected = self.continuity.map(|c| (c + 1) % 4);
+        self.continuity = Some(header.continuity);
+
+        if header.first {
+            self.group.clear();
+            self.in_group = true;
+        } else if expected != Some(header.continuity) {
+            // lost a packet part way through a data group
+            self.in_group = false;
+        }
+
+        if !self.in_group {
+            return None;
+        }
+
+        let useful_length = header.useful_length.min(packet.len() - 5);
+        self.group
+            .extend_from_slice(&packet[3..(3 + useful_length)]);
+
+        if !header.last {
+            return None;
+        }
+        self.in_group = false;
+
+        if !self.data_groups {
+            return Some(DataGroup {
+                dg_type: 0,
+                continuity: 0,
+                repetition: 0,
+                extension: None,
+                segment: None,
+                transport_id: None,
+                end_user_address: Vec::new(),
+                data: std::mem::take(&mut self.group),
+            });
+        }
+
+        match parse_data_group(&self.group) {
+            Ok(group) if self.repetition.is_new(&group) => Some(group),
+            Ok(_) => None,
+            Err(_) => {
+                self.crc_errors += 1;
+                None
+            }
+        }
+    }
+}
+
+/// A packet mode data subchannel, decoded into the data groups of one of
+/// the services it carries
+#[derive(Debug)]
+pub struct DataChannel {
+    channel: MainServiceChannel,
+    demultiplexer: PacketDemultiplexer,
+}
+
+pub fn new_data_channel(service: &Service, subchannel: &DataSubChannel) -> Result<DataChannel> {
+    let org = subchannel
+        .organisation()
+        .ok_or(Error::Msc("subchannel not organised"))?;
+    Ok(DataChannel {
+        channel: new_subchannel_channel(service, &org)?,
+        demultiplexer: new_packet_demultiplexer(subchannel),
+    })
+}
+
+impl DataChannel {
+    /// The data groups completed by the buffer, if it finished a logical
+    /// frame of the subchannel
+    pub fn try_buffer(&mut self, buffer: &Buffer) -> Result<Vec<DataGroup>> {
+        Ok(match self.channel.try_buffer(buffer)? {
+            Some(frame) => self.demultiplexer.output(&frame),
+            None => vec![],
+        })
+    }
+
+    pub fn channel(&self) -> &MainServiceChannel {
+        &self.channel
+    }
+
+    pub fn crc_errors(&self) -> u32 {
+        self.demultiplexer.crc_errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::crc16_ccitt;
+    use crate::fic::ensemble::new_data_subchannel;
+
+    // A 24 byte packet carrying part of a data group
+    fn packet(address: u16, continuity: u8, first: bool, last: bool, data: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0; 24];
+        let header = packet[0..3].view_bits_mut::<Msb0>();
+        header[2..4].store_be(continuity);
+        header.set(4, first);
+        header.set(5, last);
+        header[6..16].store_be(address);
+        header[17..24].store_be(data.len() as u8);
+        packet[3..3 + data.len()].copy_from_slice(data);
+        let crc = crc16_ccitt(&packet[..22]);
+        packet[22..24].copy_from_slice(&crc.to_be_bytes());
+        packet
+    }
+
+    #[test]
+    fn data_group_from_packets() {
+        let mut demultiplexer = new_packet_demultiplexer(&new_data_subchannel(1, true));
+        demultiplexer.address = 5;
+
+        // no extension or segment, CRC, type 3, continuity 5
+        let mut group = vec![0x43, 0x50];
+        group.extend(b"a data group in two packets");
+        let crc = crc16_ccitt(&group);
+        group.extend(crc.to_be_bytes());
+
+        let (head, tail) = group.split_at(19);
+        // padding first
+        let mut bits = packet(0, 0, true, true, &[]);
+        bits.extend(packet(5, 0, true, false, head));
+        bits.extend(packet(5, 1, false, true, tail));
+        let frame = MainServiceChannelFrame {
+            frame: 0,
+            bitrate: 8,
+            bits,
+        };
+
+        let groups = demultiplexer.output(&frame);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].dg_type, 3);
+        assert_eq!(groups[0].continuity, 5);
+        assert_eq!(groups[0].data, b"a data group in two packets");
+
+        // a repeat is dropped, and so is a group with a packet missing
+        assert!(demultiplexer.output(&frame).is_empty());
+        let bits = packet(5, 3, false, true, tail);
+        let frame = MainServiceChannelFrame {
+            frame: 1,
+            bitrate: 8,
+            bits,
+        };
+        assert!(demultiplexer.output(&frame).is_empty());
+        assert_eq!(demultiplexer.crc_errors, 0);
+    }
+}
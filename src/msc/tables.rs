@@ -525,6 +525,77 @@ pub const UEPTABLE: &[UepProf] = &[
     },
 ];
 
+/* Table 8 ETSI EN 300 401 V2.1.1 (2017-01), 11.3.1
+TableSw 0 selects the UEP table above, the other table is reserved. */
+pub fn uep_profile(TableSw: u8, TabIndx: u8) -> Option<UepProf> {
+    if TableSw != 0 {
+        return None;
+    }
+    UEPTABLE.get(TabIndx as usize).copied()
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ProtectionProfile {
+    UEP(UepProf),
+    EEP(EepProf),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct EepProf {
+    pub BitRate: u16,
+    pub SubChSz: u16,
+    pub ProtLvl: u8,
+    pub Opt: u8,
+    pub l: [usize; 2],
+    pub pi: [usize; 2],
+}
+
+/* Tables 9 and 10 ETSI EN 300 401 V2.1.1 (2017-01), 11.3.2, P.133-134
+Equal error protection profiles, as signalled in the long form of FIG 0/1.
+Opt 0 is the A profiles (n x 8kbit/s), Opt 1 the B profiles (n x 32kbit/s).
+ProtLvl 0 is protection level 1. As with UEPTABLE, pi indexes PVEC. */
+pub fn eep_profile(Opt: u8, ProtLvl: u8, SubChSz: u16) -> Option<EepProf> {
+    // (CUs per n, kbit/s per n)
+    let (cus, rate) = match (Opt, ProtLvl) {
+        (0, 0) => (12, 8),
+        (0, 1) => (8, 8),
+        (0, 2) => (6, 8),
+        (0, 3) => (4, 8),
+        (1, 0) => (27, 32),
+        (1, 1) => (21, 32),
+        (1, 2) => (18, 32),
+        (1, 3) => (15, 32),
+        _ => return None,
+    };
+
+    if SubChSz == 0 || !SubChSz.is_multiple_of(cus) {
+        return None;
+    }
+    let n = (SubChSz / cus) as usize;
+
+    let (l, pi) = match (Opt, ProtLvl) {
+        (0, 0) => ([6 * n - 3, 3], [23, 22]),
+        (0, 1) if n == 1 => ([5, 1], [12, 11]),
+        (0, 1) => ([2 * n - 3, 4 * n + 3], [13, 12]),
+        (0, 2) => ([6 * n - 3, 3], [7, 6]),
+        (0, 3) => ([4 * n - 3, 2 * n + 3], [2, 1]),
+        (1, 0) => ([24 * n - 3, 3], [9, 8]),
+        (1, 1) => ([24 * n - 3, 3], [5, 4]),
+        (1, 2) => ([24 * n - 3, 3], [3, 2]),
+        (1, 3) => ([24 * n - 3, 3], [1, 0]),
+        _ => return None,
+    };
+
+    Some(EepProf {
+        BitRate: rate * n as u16,
+        SubChSz,
+        ProtLvl,
+        Opt,
+        l,
+        pi,
+    })
+}
+
 pub const PVEC: &[[u8; 32]] = &[
     [
         1, 1, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1, 0,
@@ -623,3 +694,75 @@ pub const PVEC: &[[u8; 32]] = &[
         1, 1,
     ],
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Bits left after puncturing: PVEC[pi] keeps pi + 9 of each 32, and the
+    // tail keeps 12 of its 24
+    fn punctured_bits(l: &[usize], pi: &[usize]) -> usize {
+        l.iter()
+            .zip(pi)
+            .map(|(l, pi)| l * 4 * (pi + 9))
+            .sum::<usize>()
+            + 12
+    }
+
+    /* Tables 9 and 10 ETSI EN 300 401 V2.1.1 (2017-01), with the PIs as
+    printed there, 1 based */
+    #[test]
+    fn eep_a_profiles() {
+        // (ProtLvl, SubChSz, BitRate, L1, L2, PI1, PI2), n = 4
+        let profiles = [
+            (0, 48, 32, 21, 3, 24, 23),
+            (1, 32, 32, 5, 19, 14, 13),
+            (2, 24, 32, 21, 3, 8, 7),
+            (3, 16, 32, 13, 11, 3, 2),
+        ];
+        for (lvl, size, rate, l1, l2, pi1, pi2) in profiles {
+            let eep = eep_profile(0, lvl, size).unwrap();
+            assert_eq!(eep.BitRate, rate, "{}-A", lvl + 1);
+            assert_eq!(eep.l, [l1, l2], "{}-A", lvl + 1);
+            assert_eq!(eep.pi, [pi1 - 1, pi2 - 1], "{}-A", lvl + 1);
+            assert_eq!(punctured_bits(&eep.l, &eep.pi), size as usize * 64);
+        }
+
+        // 2-A at 8 kbit/s is the exception
+        let eep = eep_profile(0, 1, 8).unwrap();
+        assert_eq!((eep.l, eep.pi), ([5, 1], [12, 11]));
+        assert_eq!(punctured_bits(&eep.l, &eep.pi), 8 * 64);
+
+        // 3-A at 128 kbit/s, the usual for an MP2 service
+        let eep = eep_profile(0, 2, 96).unwrap();
+        assert_eq!((eep.BitRate, eep.l), (128, [93, 3]));
+    }
+
+    #[test]
+    fn eep_b_profiles() {
+        // (ProtLvl, SubChSz, BitRate, L1, L2, PI1, PI2), n = 2
+        let profiles = [
+            (0, 54, 64, 45, 3, 10, 9),
+            (1, 42, 64, 45, 3, 6, 5),
+            (2, 36, 64, 45, 3, 4, 3),
+            (3, 30, 64, 45, 3, 2, 1),
+        ];
+        for (lvl, size, rate, l1, l2, pi1, pi2) in profiles {
+            let eep = eep_profile(1, lvl, size).unwrap();
+            assert_eq!(eep.BitRate, rate, "{}-B", lvl + 1);
+            assert_eq!(eep.l, [l1, l2], "{}-B", lvl + 1);
+            assert_eq!(eep.pi, [pi1 - 1, pi2 - 1], "{}-B", lvl + 1);
+            assert_eq!(punctured_bits(&eep.l, &eep.pi), size as usize * 64);
+        }
+    }
+
+    #[test]
+    fn eep_invalid() {
+        // not a multiple of the CUs for each n
+        assert!(eep_profile(0, 0, 50).is_none());
+        assert!(eep_profile(1, 3, 31).is_none());
+        assert!(eep_profile(0, 2, 0).is_none());
+        assert!(eep_profile(2, 0, 48).is_none());
+        assert!(eep_profile(0, 4, 48).is_none());
+    }
+}
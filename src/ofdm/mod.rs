@@ -0,0 +1,392 @@
+use rustfft::num_complex::{Complex64, c64};
+use rustfft::{Fft, FftPlanner};
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use crate::decode::bit_reverse;
+use crate::prs::{PRS_POINTS, PhaseReferenceArray, reference::prs_carriers};
+use crate::stats::SyncValues;
+use crate::wavefinder::Buffer;
+
+/* ETSI EN 300 401 V1.3.3 Sect.14.2 Table 38, Transmission Mode I, with
+durations in samples at 2.048 Msps */
+pub const SAMPLE_RATE: f64 = 2_048_000.0;
+const T_U: usize = PRS_POINTS;
+const GUARD: usize = 504;
+const T_S: usize = T_U + GUARD;
+const T_NULL: usize = 2656;
+const T_F: usize = 196608;
+const L: usize = 76;
+const CARRIERS: i32 = 768;
+const SPACING: f64 = 1000.0;
+
+// The FFT window starts this far before the end of the guard interval, so
+// a timing error either way still keeps it within one symbol
+const BACKOFF: usize = GUARD / 4;
+
+// Largest frequency offset looked for, in carriers
+const MAX_OFFSET: i32 = 32;
+
+// The null symbol's power has to be below this fraction of the frame's
+const NULL_LEVEL: f64 = 0.5;
+
+// Impulse response peak power over its mean, below this the phase
+// reference symbol isn't there and synchronisation is lost
+const PEAK_LEVEL: f64 = 20.0;
+
+/// Transmission Mode I demodulator for complex baseband samples at
+/// 2.048 Msps. Frames are found by their null symbol, then followed by
+/// correlating against the phase reference symbol.
+pub struct OfdmDemodulator {
+    // samples from the start of the search, or the start of the next frame
+    samples: Vec<Complex64>,
+    locked: bool,
+    frame: u8,
+    values: SyncValues,
+    prs: PhaseReferenceArray,
+    fft: Arc<dyn Fft<f64>>,
+    ifft: Arc<dyn Fft<f64>>,
+}
+
+pub fn new_demodulator() -> OfdmDemodulator {
+    let mut planner = FftPlanner::<f64>::new();
+    OfdmDemodulator {
+        samples: vec![],
+        locked: false,
+        frame: 0,
+        values: SyncValues::default(),
+        prs: prs_carriers(),
+        fft: planner.plan_fft_forward(T_U),
+        ifft: planner.plan_fft_inverse(T_U),
+    }
+}
+
+// The FFT bin of each carrier, from k = -768 up to k = 768, in the order
+// the frequency deinterleaver expects
+fn carrier_bins() -> impl Iterator<Item = usize> {
+    (-CARRIERS..=CARRIERS)
+        .filter(|k| *k != 0)
+        .map(|k| k.rem_euclid(T_U as i32) as usize)
+}
+
+impl OfdmDemodulator {
+    pub fn values(&self) -> SyncValues {
+        self.values
+    }
+
+    pub fn reset(&mut self) {
+        self.samples.clear();
+        self.locked = false;
+        self.values = SyncValues::default();
+    }
+
+    /// Add samples, returning a buffer for every symbol after the phase
+    /// reference symbol of each frame completed by them. The bits are hard
+    /// decisions, packed like the Wavefinder's buffers so the FIC and MSC
+    /// decoders take them unchanged.
+    pub fn push(&mut self, samples: &[Complex64]) -> Vec<Buffer> {
+        self.samples.extend_from_slice(samples);
+
+        let mut buffers = vec![];
+        loop {
+            if !self.locked {
+                // a whole null symbol is somewhere in a frame's worth
+                if self.samples.len() < T_F + T_NULL {
+                    break;
+                }
+                if let Some(end) = find_null(&self.samples[..T_F + T_NULL]) {
+                    self.samples.drain(..end);
+                    self.locked = true;
+                } else {
+                    self.samples.drain(..T_F);
+                }
+            } else {
+                if self.samples.len() < T_F {
+                    break;
+                }
+                match self.demodulate_frame() {
+                    Some((frame, timing)) => {
+                        buffers.extend(frame);
+                        self.frame = (self.frame + 1) % 32;
+                        self.samples.drain(..(T_F as isize - timing) as usize);
+                    }
+                    None => {
+                        self.locked = false;
+                        self.values.locked = false;
+                    }
+                }
+            }
+        }
+        buffers
+    }
+
+    // The frame starting at the first sample, and how many samples late
+    // that start was
+    fn demodulate_frame(&mut self) -> Option<(Vec<Buffer>, isize)> {
+        let samples = &self.samples[..L * T_S];
+
+        // The guard interval repeats the end of the symbol, the phase
+        // between them is the offset within one carrier spacing
+        let mut cp = c64(0, 0);
+        for symbol in samples.chunks(T_S) {
+            for n in 0..GUARD {
+                cp += symbol[n].conj() * symbol[n + T_U];
+            }
+        }
+        let fine = cp.arg() * SPACING / (2.0 * PI);
+
+        // then the whole number of carriers, from the phase differences
+        // between neighbouring carriers of the phase reference symbol
+        let prs = self.window(samples, 0, fine);
+        let coarse = self.carrier_offset(&prs);
+        let frequency = coarse as f64 * SPACING + fine;
+
+        let symbols = (0..L)
+            .map(|l| self.window(samples, l, frequency))
+            .collect::<Vec<_>>();
+
+        // Impulse response of the channel, its peak is where the FFT
+        // window should have started
+        let mut h = [c64(0, 0); T_U];
+        for bin in carrier_bins() {
+            h[bin] = symbols[0][bin] * self.prs[bin].conj();
+        }
+        self.ifft.process(&mut h);
+        let power = h.map(|v| v.norm_sqr());
+        let (peak, max) =
+            power.iter().enumerate().fold(
+                (0, 0.0),
+                |best, (i, p)| if *p > best.1 { (i, *p) } else { best },
+            );
+        let mean = power.iter().sum::<f64>() / T_U as f64;
+        if mean == 0.0 || max / mean < PEAK_LEVEL {
+            return None;
+        }
+        let mut timing = BACKOFF as isize - peak as isize;
+        if timing > (T_U / 2) as isize {
+            timing -= T_U as isize;
+        } else if timing <= -((T_U / 2) as isize) {
+            timing += T_U as isize;
+        }
+
+        self.values = SyncValues {
+            c: frequency,
+            ir: timing as f64,
+            locked: true,
+        };
+
+        let buffers = symbols
+            .windows(2)
+            .enumerate()
+            .map(|(l, pair)| self.symbol_buffer(l as u8 + 2, &pair[0], &pair[1]))
+            .collect();
+        Some((buffers, timing))
+    }
+
+    // FFT of symbol l, after removing the frequency offset
+    fn window(&self, samples: &[Complex64], l: usize, frequency: f64) -> PhaseReferenceArray {
+        let start = l * T_S + GUARD - BACKOFF;
+        let step = Complex64::from_polar(1.0, -2.0 * PI * frequency / SAMPLE_RATE);
+        let mut rotation =
+            Complex64::from_polar(1.0, -2.0 * PI * frequency * start as f64 / SAMPLE_RATE);
+        let mut data = [c64(0, 0); T_U];
+        for (d, s) in data.iter_mut().zip(&samples[start..start + T_U]) {
+            *d = s * rotation;
+            rotation *= step;
+        }
+        self.fft.process(&mut data);
+        data
+    }
+
+    // The shift of the carriers in FFT bins, by correlating the phase
+    // differences between neighbouring carriers with the reference's, which
+    // a timing error doesn't change
+    fn carrier_offset(&self, prs: &PhaseReferenceArray) -> i32 {
+        let bin = |k: i32| k.rem_euclid(T_U as i32) as usize;
+        let reference = (-CARRIERS + 1..=CARRIERS)
+            .filter(|k| *k != 0 && *k != 1)
+            .map(|k| (k, self.prs[bin(k)] * self.prs[bin(k - 1)].conj()))
+            .collect::<Vec<_>>();
+
+        (-MAX_OFFSET..=MAX_OFFSET)
+            .map(|m| {
+                let corr: Complex64 = reference
+                    .iter()
+                    .map(|(k, r)| prs[bin(k + m)] * prs[bin(k + m - 1)].conj() * r.conj())
+                    .sum();
+                (m, corr.norm_sqr())
+            })
+            .fold(
+                (0, 0.0),
+                |best, (m, c)| if c > best.1 { (m, c) } else { best },
+            )
+            .0
+    }
+
+    /* ETSI EN 300 401 V1.3.3 Sect.14.5 differential modulation, a bit is set
+    where the real or imaginary part of the phase change is negative. Packed
+    like the Wavefinder's buffers, LSB first in reversed 16 bit words. */
+    fn symbol_buffer(
+        &self,
+        symbol: u8,
+        previous: &PhaseReferenceArray,
+        current: &PhaseReferenceArray,
+    ) -> Buffer {
+        let mut bits = Vec::with_capacity(2 * CARRIERS as usize * 2);
+        for bin in carrier_bins() {
+            let d = current[bin] * previous[bin].conj();
+            bits.push((d.re < 0.0) as u8);
+            bits.push((d.im < 0.0) as u8);
+        }
+        bit_reverse(&mut bits);
+
+        let mut bytes = [0; 524];
+        bytes[2] = symbol;
+        bytes[3] = self.frame;
+        for (byte, chunk) in bytes[12..396].iter_mut().zip(bits.chunks(8)) {
+            *byte = chunk
+                .iter()
+                .enumerate()
+                .fold(0, |b, (i, bit)| b | (bit << i));
+        }
+        Buffer {
+            bytes,
+            last: false,
+            eti: None,
+        }
+    }
+}
+
+// Where the quietest null symbol length of the samples ends, if it's quiet
+// enough to be a null symbol
+fn find_null(samples: &[Complex64]) -> Option<usize> {
+    let power = samples.iter().map(|s| s.norm_sqr()).collect::<Vec<_>>();
+    let total: f64 = power.iter().sum();
+
+    let mut sum: f64 = power[..T_NULL].iter().sum();
+    let (mut quietest, mut start) = (sum, 0);
+    for n in T_NULL..power.len() {
+        sum += power[n] - power[n - T_NULL];
+        if sum < quietest {
+            quietest = sum;
+            start = n + 1 - T_NULL;
+        }
+    }
+
+    let level = (quietest / T_NULL as f64) / (total / power.len() as f64);
+    if total > 0.0 && level < NULL_LEVEL {
+        Some(start + T_NULL)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Mode I frames of pseudo-random symbols, with the carriers of each
+    // symbol as sent
+    fn frames(count: usize) -> (Vec<Complex64>, Vec<Vec<PhaseReferenceArray>>) {
+        let ifft = FftPlanner::<f64>::new().plan_fft_inverse(T_U);
+        let mut seed: u32 = 1;
+        let mut bit = move || {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            if (seed >> 16) & 1 == 1 { -1.0 } else { 1.0 }
+        };
+
+        let mut signal = vec![];
+        let mut sent = vec![];
+        for _ in 0..count {
+            let mut z = prs_carriers();
+            let mut symbols = vec![z];
+            for _ in 1..L {
+                for bin in carrier_bins() {
+                    z[bin] *= c64(bit(), bit()) / 2f64.sqrt();
+                }
+                symbols.push(z);
+            }
+
+            signal.extend(std::iter::repeat_n(c64(0, 0), T_NULL));
+            for symbol in &symbols {
+                let mut t = *symbol;
+                ifft.process(&mut t);
+                signal.extend_from_slice(&t[T_U - GUARD..]);
+                signal.extend_from_slice(&t);
+            }
+            sent.push(symbols);
+        }
+        // the next frame's null symbol
+        signal.extend(std::iter::repeat_n(c64(0, 0), T_NULL));
+        (signal, sent)
+    }
+
+    fn data(buffer: &Buffer) -> &[u8] {
+        &buffer.bytes[12..396]
+    }
+
+    #[test]
+    fn demodulates_offset_frames() {
+        let (signal, sent) = frames(3);
+        // part way into the first frame, 3 carriers and a bit high
+        let offset = 3.0 * SPACING + 170.0;
+        let samples = signal[70_000..]
+            .iter()
+            .enumerate()
+            .map(|(n, s)| {
+                s * Complex64::from_polar(1.0, 2.0 * PI * offset * n as f64 / SAMPLE_RATE)
+            })
+            .collect::<Vec<_>>();
+
+        let mut demodulator = new_demodulator();
+        let mut buffers = vec![];
+        for chunk in samples.chunks(20480) {
+            buffers.extend(demodulator.push(chunk));
+        }
+
+        let values = demodulator.values();
+        assert!(values.locked);
+        assert!((values.c - offset).abs() < 5.0, "offset {}", values.c);
+        assert_eq!(values.ir, 0.0);
+
+        // the second and third frames
+        assert_eq!(buffers.len(), 2 * (L - 1));
+        for (f, frame) in buffers.chunks(L - 1).enumerate() {
+            let symbols = &sent[f + 1];
+            for (l, buffer) in frame.iter().enumerate() {
+                let expected = demodulator.symbol_buffer(0, &symbols[l], &symbols[l + 1]);
+                assert_eq!(buffer.bytes[2] as usize, l + 2);
+                assert_eq!(buffer.bytes[3] as usize, f);
+                assert_eq!(data(buffer), data(&expected), "frame {f} symbol {}", l + 2);
+            }
+        }
+    }
+
+    #[test]
+    fn timing_of_a_frame() {
+        let (signal, _) = frames(1);
+        let mut demodulator = new_demodulator();
+
+        // starting late, then early, in the null symbol
+        for late in [30, -20] {
+            demodulator.samples = signal[(T_NULL as isize + late) as usize..].to_vec();
+            let (buffers, timing) = demodulator.demodulate_frame().unwrap();
+            assert_eq!(buffers.len(), L - 1);
+            assert_eq!(timing, late);
+            assert_eq!(demodulator.values().ir, late as f64);
+        }
+    }
+
+    #[test]
+    fn no_null_without_a_signal() {
+        let mut demodulator = new_demodulator();
+        assert!(demodulator.push(&vec![c64(0, 0); 2 * T_F]).is_empty());
+        assert!(!demodulator.values().locked);
+
+        let (signal, _) = frames(2);
+        assert_eq!(
+            find_null(&signal[1000..1000 + T_F + T_NULL]),
+            Some(T_F + T_NULL - 1000)
+        );
+    }
+}
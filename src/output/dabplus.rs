@@ -0,0 +1,433 @@
+use crate::decode::{ReedSolomon, crc16_ccitt_check, fire_code_check, new_reed_solomon};
+use crate::error::{Error, Result};
+use crate::msc::MainServiceChannelFrame;
+use crate::output::AudioOutput;
+use crate::output::fdkaac::{AacDecoder, new_aac_decoder};
+use crate::output::pcm::PcmOutput;
+use crate::stats::AudioFrames;
+use bitvec::prelude::*;
+use itertools::Itertools;
+use std::mem;
+
+/* ETSI TS 102 563 V2.1.1 (2017-01), 5.1: five logical frames per audio super frame */
+const FRAMES_PER_SUPERFRAME: usize = 5;
+
+/* RS(120, 110) outer code, 6.1 */
+const RS_N: usize = 120;
+const RS_K: usize = 110;
+
+/* ISO/IEC 14496-3 raw_data_block element id of a data stream element */
+const ID_DSE: u8 = 4;
+
+/// The fixed part of the audio super frame header, 5.2
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SuperframeHeader {
+    pub dac_rate: bool,
+    pub sbr_flag: bool,
+    pub aac_channel_mode: bool,
+    pub ps_flag: bool,
+    pub mpeg_surround_config: u8,
+}
+
+impl SuperframeHeader {
+    pub fn from_u8(byte: u8) -> Self {
+        let bits = byte.view_bits::<Msb0>();
+        Self {
+            dac_rate: bits[1],
+            sbr_flag: bits[2],
+            aac_channel_mode: bits[3],
+            ps_flag: bits[4],
+            mpeg_surround_config: bits[5..8].load_be(),
+        }
+    }
+
+    pub fn num_aus(&self) -> usize {
+        match (self.dac_rate, self.sbr_flag) {
+            (false, true) => 2,
+            (true, true) => 3,
+            (false, false) => 4,
+            (true, false) => 6,
+        }
+    }
+
+    // Start of the first AU, just past the AU start table
+    fn first_au_start(&self) -> usize {
+        match self.num_aus() {
+            2 => 5,
+            3 => 6,
+            4 => 8,
+            _ => 11,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        if self.dac_rate { 48000 } else { 32000 }
+    }
+
+    pub fn core_sample_rate(&self) -> u32 {
+        if self.sbr_flag {
+            self.sample_rate() / 2
+        } else {
+            self.sample_rate()
+        }
+    }
+
+    pub fn channels(&self) -> u32 {
+        if self.aac_channel_mode || self.ps_flag {
+            2
+        } else {
+            1
+        }
+    }
+
+    // channelConfiguration: PS is carried on a mono core
+    fn channel_configuration(&self) -> u8 {
+        if self.aac_channel_mode && !self.ps_flag {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// The MPEG-4 AudioSpecificConfig for this stream, 5.3: AAC LC core with a
+    /// 960 sample frame length, and explicit SBR/PS signalling.
+    pub fn audio_specific_config(&self) -> Vec<u8> {
+        let mut bits = BitVec::<u8, Msb0>::new();
+        let mut push = |value: u32, n: usize| {
+            for i in (0..n).rev() {
+                bits.push((value >> i) & 1 == 1);
+            }
+        };
+
+        // audioObjectType: AAC LC
+        push(2, 5);
+        push(sampling_frequency_index(self.core_sample_rate()), 4);
+        push(self.channel_configuration() as u32, 4);
+        // GASpecificConfig: frameLengthFlag, dependsOnCoreCoder, extensionFlag
+        push(1, 1);
+        push(0, 1);
+        push(0, 1);
+
+        if self.sbr_flag {
+            push(0x2b7, 11);
+            // extensionAudioObjectType: SBR, sbrPresentFlag
+            push(5, 5);
+            push(1, 1);
+            push(sampling_frequency_index(self.sample_rate()), 4);
+            if self.ps_flag {
+                push(0x548, 11);
+                push(1, 1);
+            }
+        }
+
+        bits.into_vec()
+    }
+
+    /// An ADTS header for one access unit, so the AUs can be written out as a
+    /// playable stream. SBR and PS are left to be detected implicitly, as
+    /// ADTS can only signal the core.
+    pub fn adts_header(&self, au_len: usize) -> [u8; 7] {
+        let len = au_len + 7;
+        let sfi = sampling_frequency_index(self.core_sample_rate()) as u8;
+        let channels = self.channel_configuration();
+        [
+            0xff,
+            // MPEG-4, layer 0, no CRC
+            0xf1,
+            // profile AAC LC
+            (1 << 6) | (sfi << 2) | (channels >> 2),
+            ((channels & 0x03) << 6) | ((len >> 11) as u8 & 0x03),
+            (len >> 3) as u8,
+            ((len as u8 & 0x07) << 5) | 0x1f,
+            0xfc,
+        ]
+    }
+}
+
+fn sampling_frequency_index(rate: u32) -> u32 {
+    match rate {
+        48000 => 3,
+        32000 => 5,
+        24000 => 6,
+        _ => 8, // 16000
+    }
+}
+
+/// Collects logical frames into audio super frames, and splits them into
+/// their access units.
+pub struct Superframes {
+    frames: Vec<Vec<u8>>,
+    rs: ReedSolomon,
+}
+
+pub fn new_superframes() -> Superframes {
+    Superframes {
+        frames: Vec::with_capacity(FRAMES_PER_SUPERFRAME),
+        rs: new_reed_solomon(RS_N, RS_K).expect("RS(120, 110) is a shortened RS(255, 245)"),
+    }
+}
+
+impl Superframes {
+    /// Apply the RS(120, 110) code to a complete super frame in place. The
+    /// s codewords are interleaved byte by byte across the super frame, 6.2.
+    /// Returns the number of corrected bytes, or an error if any codeword
+    /// could not be corrected.
+    pub fn correct(&self, superframe: &mut [u8]) -> Result<usize> {
+        let s = superframe.len() / RS_N;
+        let mut codeword = [0u8; RS_N];
+        let mut corrected = 0;
+        let mut result = Ok(());
+
+        for column in 0..s {
+            for (j, byte) in codeword.iter_mut().enumerate() {
+                *byte = superframe[column + j * s];
+            }
+            match self.rs.decode(&mut codeword) {
+                Ok(n) => {
+                    corrected += n;
+                    for (j, byte) in codeword.iter().enumerate() {
+                        superframe[column + j * s] = *byte;
+                    }
+                }
+                Err(e) => result = Err(e),
+            }
+        }
+
+        result.map(|_| corrected)
+    }
+
+    /// Split the audio super frame (excluding RS parity) into the access
+    /// units listed in its AU start table, dropping any failing their CRC.
+    pub fn access_units<'a>(audio: &'a [u8], header: &SuperframeHeader) -> Vec<&'a [u8]> {
+        let num_aus = header.num_aus();
+        let mut starts = Vec::with_capacity(num_aus + 1);
+        starts.push(header.first_au_start());
+
+        let table = audio[3..].view_bits::<Msb0>();
+        for i in 0..(num_aus - 1) {
+            starts.push(table[(i * 12)..(i * 12 + 12)].load_be::<u16>() as usize);
+        }
+        starts.push(audio.len());
+
+        starts
+            .into_iter()
+            .tuple_windows()
+            .filter(|(start, end)| start < end && *end <= audio.len() && end - start > 2)
+            .map(|(start, end)| &audio[start..end])
+            .filter(|au| crc16_ccitt_check(au))
+            .map(|au| &au[..(au.len() - 2)])
+            .collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    /// Add a logical frame, returning the header and access units once a
+    /// whole super frame is in sync.
+    pub fn push(
+        &mut self,
+        frame: &MainServiceChannelFrame,
+    ) -> Option<(SuperframeHeader, Vec<Vec<u8>>)> {
+        let s = frame.bitrate as usize / 8;
+        if s == 0 || frame.bits.len() * FRAMES_PER_SUPERFRAME != s * RS_N {
+            self.frames.clear();
+            return None;
+        }
+
+        self.frames.push(frame.bits.clone());
+        if self.frames.len() < FRAMES_PER_SUPERFRAME {
+            return None;
+        }
+
+        let mut superframe = self.frames.concat();
+        let _ = self.correct(&mut superframe);
+
+        // Super frame sync: until the fire code checks out, slide along by one
+        // logical frame.
+        let sync: &[u8; 11] = superframe[0..11].try_into().expect("eleven bytes");
+        if superframe[0..11].iter().all(|b| *b == 0) || !fire_code_check(sync) {
+            self.frames.remove(0);
+            return None;
+        }
+        self.frames.clear();
+
+        let header = SuperframeHeader::from_u8(superframe[2]);
+        let audio = &superframe[0..(s * RS_K)];
+        let aus = Superframes::access_units(audio, &header)
+            .into_iter()
+            .map(|au| au.to_vec())
+            .collect();
+        Some((header, aus))
+    }
+}
+
+/* ETSI TS 102 563 V2.1.1 (2017-01), 5.4: the PAD of an access unit is in
+a data stream element at its start, if there is one */
+pub fn pad_field(au: &[u8]) -> Option<&[u8]> {
+    if au.len() < 2 || au[0] >> 5 != ID_DSE {
+        return None;
+    }
+    // element_instance_tag and data_byte_align_flag, then the count, which
+    // leaves the data byte aligned
+    let (count, start) = match au[1] {
+        255 => (255 + *au.get(2)? as usize, 3),
+        count => (count as usize, 2),
+    };
+    au.get(start..start + count)
+}
+
+pub struct DabPlus {
+    superframes: Superframes,
+    header: Option<SuperframeHeader>,
+    pcm: PcmOutput,
+    decoder: Option<AacDecoder>,
+    // PAD fields of the access units decoded since they were last taken
+    pad: Vec<Vec<u8>>,
+}
+
+pub fn new_dabplus(pcm: PcmOutput) -> DabPlus {
+    DabPlus {
+        superframes: new_superframes(),
+        header: None,
+        pcm,
+        decoder: None,
+        pad: vec![],
+    }
+}
+
+impl DabPlus {
+    fn init(&mut self, header: SuperframeHeader) -> Result<()> {
+        self.header = Some(header);
+        self.decoder = Some(new_aac_decoder(&header.audio_specific_config())?);
+        Ok(())
+    }
+
+    /// The PAD carried by the access units so far, in order
+    pub fn take_pad(&mut self) -> Vec<Vec<u8>> {
+        mem::take(&mut self.pad)
+    }
+
+    pub(super) fn take_pcm(&mut self) -> PcmOutput {
+        mem::take(&mut self.pcm)
+    }
+}
+
+impl AudioOutput for DabPlus {
+    fn deinit(&mut self) {
+        self.superframes.clear();
+        self.header = None;
+        self.decoder = None;
+        self.pad.clear();
+    }
+
+    fn output(&mut self, frame: &MainServiceChannelFrame) -> Result<AudioFrames> {
+        let Some((header, aus)) = self.superframes.push(frame) else {
+            return Ok(AudioFrames::default());
+        };
+        if self.header != Some(header) {
+            self.init(header)?;
+        }
+        let Some(decoder) = self.decoder.as_mut() else {
+            return Err(Error::Output("no AAC decoder"));
+        };
+
+        // access units failing their CRC have already been dropped
+        let mut frames = AudioFrames {
+            decoded: 0,
+            failed: header.num_aus().saturating_sub(aus.len()) as u32,
+        };
+        for au in aus {
+            if let Some(pad) = pad_field(&au) {
+                self.pad.push(pad.to_vec());
+            }
+            match decoder.decode(&au) {
+                Ok(pcm) => {
+                    self.pcm.init(pcm.channels, pcm.sample_rate)?;
+                    self.pcm.write_i16(&pcm.samples)?;
+                    frames.decoded += 1;
+                }
+                Err(_) => {
+                    // eprintln!("AU decode error: {:?}", e);
+                    frames.failed += 1;
+                }
+            }
+        }
+        Ok(frames)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::crc16_ccitt;
+
+    // An access unit of this many bytes, with its CRC
+    fn au(len: usize, fill: u8) -> Vec<u8> {
+        let mut au = vec![fill; len - 2];
+        au.extend(crc16_ccitt(&au).to_be_bytes());
+        au
+    }
+
+    #[test]
+    fn access_units_from_start_table() {
+        // 32kHz with SBR, two access units, the second starting at byte 20
+        let header = SuperframeHeader::from_u8(0x20);
+        assert_eq!(header.num_aus(), 2);
+        let mut audio = vec![0, 0, 0x20, 0x01, 0x40];
+        audio.extend(au(15, 1));
+        audio.extend(au(20, 2));
+
+        let aus = Superframes::access_units(&audio, &header);
+        assert_eq!(aus, [&[1; 13][..], &[2; 18][..]]);
+
+        // one failing its CRC is dropped
+        audio[30] ^= 0xff;
+        let aus = Superframes::access_units(&audio, &header);
+        assert_eq!(aus, [&[1; 13][..]]);
+
+        // a start beyond the super frame
+        audio[3] = 0xff;
+        let aus = Superframes::access_units(&audio, &header);
+        assert!(aus.is_empty());
+    }
+
+    #[test]
+    fn adts_header_fields() {
+        // 48kHz stereo AAC without SBR
+        let header = SuperframeHeader::from_u8(0x50);
+        let adts = header.adts_header(1000);
+        assert_eq!(adts[0..2], [0xff, 0xf1]);
+        // AAC LC, sampling frequency index 3, channel configuration 2
+        assert_eq!(adts[2] >> 6, 1);
+        assert_eq!((adts[2] >> 2) & 0x0f, 3);
+        assert_eq!(((adts[2] & 0x01) << 2) | (adts[3] >> 6), 2);
+        // the frame length includes the 7 header bytes
+        let len =
+            ((adts[3] as usize & 0x03) << 11) | ((adts[4] as usize) << 3) | (adts[5] as usize >> 5);
+        assert_eq!(len, 1007);
+
+        // with SBR only the 24kHz core is signalled
+        let adts = SuperframeHeader::from_u8(0x60).adts_header(0x1fff - 7);
+        assert_eq!((adts[2] >> 2) & 0x0f, 6);
+        assert_eq!(adts[3] & 0x03, 0x03);
+        assert_eq!(adts[4], 0xff);
+        assert_eq!(adts[5] >> 5, 0x07);
+    }
+
+    #[test]
+    fn pad_field_from_dse() {
+        // DSE with 3 bytes of PAD, then the rest of the access unit
+        let au = [0x80, 3, 1, 2, 3, 0x21, 0x00];
+        assert_eq!(pad_field(&au), Some(&[1, 2, 3][..]));
+
+        // the count escape for 255 bytes and more
+        let mut au = vec![0x80, 255, 2];
+        au.extend([0; 257]);
+        assert_eq!(pad_field(&au).map(|p| p.len()), Some(257));
+
+        // no DSE, or cut short
+        assert_eq!(pad_field(&[0x21, 0x00, 0x00]), None);
+        assert_eq!(pad_field(&[0x80, 4, 1, 2]), None);
+    }
+}
@@ -0,0 +1,122 @@
+use std::os::raw::{c_int, c_uint};
+use std::ptr;
+
+use crate::error::{Error, Result};
+
+/* The parts of libfdk-aac's aacdecoder_lib.h used here */
+#[repr(C)]
+struct AacDecoderInstance {
+    _private: [u8; 0],
+}
+
+// only the leading fields are read
+#[repr(C)]
+struct CStreamInfo {
+    sample_rate: c_int,
+    frame_size: c_int,
+    num_channels: c_int,
+}
+
+// TRANSPORT_TYPE: access units without any transport framing
+const TT_MP4_RAW: c_int = 0;
+const AAC_DEC_OK: c_int = 0;
+
+unsafe extern "C" {
+    fn aacDecoder_Open(transport_fmt: c_int, nr_of_layers: c_uint) -> *mut AacDecoderInstance;
+    fn aacDecoder_ConfigRaw(
+        decoder: *mut AacDecoderInstance,
+        conf: *mut *mut u8,
+        length: *const c_uint,
+    ) -> c_int;
+    fn aacDecoder_Fill(
+        decoder: *mut AacDecoderInstance,
+        buffer: *mut *mut u8,
+        buffer_size: *const c_uint,
+        bytes_valid: *mut c_uint,
+    ) -> c_int;
+    fn aacDecoder_DecodeFrame(
+        decoder: *mut AacDecoderInstance,
+        time_data: *mut i16,
+        time_data_size: c_int,
+        flags: c_uint,
+    ) -> c_int;
+    fn aacDecoder_GetStreamInfo(decoder: *mut AacDecoderInstance) -> *mut CStreamInfo;
+    fn aacDecoder_Close(decoder: *mut AacDecoderInstance);
+}
+
+// 2 channels of an HE-AAC frame, with room to spare
+const MAX_SAMPLES: usize = 8 * 1024;
+
+/// Interleaved 16 bit samples decoded from one access unit
+pub struct AacFrame {
+    pub channels: u32,
+    pub sample_rate: u32,
+    pub samples: Vec<i16>,
+}
+
+/// HE-AAC v2 decoder, AAC LC with SBR and PS, from libfdk-aac
+pub struct AacDecoder {
+    handle: *mut AacDecoderInstance,
+}
+
+/// A decoder for raw access units described by the AudioSpecificConfig
+pub fn new_aac_decoder(config: &[u8]) -> Result<AacDecoder> {
+    let handle = unsafe { aacDecoder_Open(TT_MP4_RAW, 1) };
+    if handle.is_null() {
+        return Err(Error::Output("opening the AAC decoder"));
+    }
+    // closed on drop from here on
+    let decoder = AacDecoder { handle };
+
+    let mut conf = config.to_vec();
+    let mut conf_ptr = conf.as_mut_ptr();
+    let length = conf.len() as c_uint;
+    if unsafe { aacDecoder_ConfigRaw(decoder.handle, &mut conf_ptr, &length) } != AAC_DEC_OK {
+        return Err(Error::Output(
+            "AAC decoder rejected the AudioSpecificConfig",
+        ));
+    }
+    Ok(decoder)
+}
+
+impl AacDecoder {
+    pub fn decode(&mut self, au: &[u8]) -> Result<AacFrame> {
+        let mut data = au.to_vec();
+        let mut data_ptr = data.as_mut_ptr();
+        let size = data.len() as c_uint;
+        let mut valid = size;
+        if unsafe { aacDecoder_Fill(self.handle, &mut data_ptr, &size, &mut valid) } != AAC_DEC_OK {
+            return Err(Error::Output("filling the AAC decoder"));
+        }
+
+        let mut samples = vec![0i16; MAX_SAMPLES];
+        let result = unsafe {
+            aacDecoder_DecodeFrame(self.handle, samples.as_mut_ptr(), MAX_SAMPLES as c_int, 0)
+        };
+        if result != AAC_DEC_OK {
+            return Err(Error::Output("AAC access unit failed to decode"));
+        }
+
+        let info = unsafe { aacDecoder_GetStreamInfo(self.handle) };
+        if info.is_null() {
+            return Err(Error::Output("no AAC stream info"));
+        }
+        let info = unsafe { ptr::read(info) };
+        let len = (info.frame_size * info.num_channels) as usize;
+        if info.num_channels <= 0 || len > MAX_SAMPLES {
+            return Err(Error::Output("unexpected AAC frame size"));
+        }
+        samples.truncate(len);
+        Ok(AacFrame {
+            channels: info.num_channels as u32,
+            sample_rate: info.sample_rate as u32,
+            samples,
+        })
+    }
+}
+
+impl Drop for AacDecoder {
+    fn drop(&mut self) {
+        unsafe { aacDecoder_Close(self.handle) }
+    }
+}
@@ -1,2 +1,64 @@
+use enum_dispatch::enum_dispatch;
+
+use crate::error::Result;
+use crate::fic::ensemble::{AudioCodec, Service};
+use crate::msc::MainServiceChannelFrame;
+use crate::stats::AudioFrames;
+
+pub mod dabplus;
+mod fdkaac;
 pub mod mp2header;
 pub mod mpeg;
+mod pcm;
+pub mod record;
+
+use dabplus::DabPlus;
+use mpeg::Mpeg;
+use pcm::{PcmOutput, new_pcm_output};
+
+#[enum_dispatch]
+pub trait AudioOutput {
+    fn output(&mut self, frame: &MainServiceChannelFrame) -> Result<AudioFrames>;
+    fn deinit(&mut self);
+}
+
+#[allow(clippy::large_enum_variant)]
+#[enum_dispatch(AudioOutput)]
+pub enum Audio {
+    Mpeg(Mpeg),
+    DabPlus(DabPlus),
+}
+
+// Pick the decoder for the service's audio: MP2, or DAB+ for ASCTy 63
+pub fn new_audio(service: &Service) -> Audio {
+    audio_for(service.subchannel().and_then(|s| s.audio_codec()), new_pcm_output())
+}
+
+fn audio_for(codec: Option<AudioCodec>, pcm: PcmOutput) -> Audio {
+    match codec {
+        Some(AudioCodec::AAC) => Audio::DabPlus(dabplus::new_dabplus(pcm)),
+        _ => Audio::Mpeg(mpeg::new_mpeg(pcm)),
+    }
+}
+
+impl Audio {
+    /// The decoder for another subchannel's audio, keeping the audio
+    /// device open
+    pub fn switch(&mut self, codec: Option<AudioCodec>) {
+        self.deinit();
+        let pcm = match self {
+            Audio::Mpeg(mpeg) => mpeg.take_pcm(),
+            Audio::DabPlus(dabplus) => dabplus.take_pcm(),
+        };
+        *self = audio_for(codec, pcm);
+    }
+
+    /// PAD from the access units decoded since last asked, for DAB+. MP2
+    /// PAD is at the end of each frame instead.
+    pub fn take_pad(&mut self) -> Vec<Vec<u8>> {
+        match self {
+            Audio::Mpeg(_) => vec![],
+            Audio::DabPlus(dabplus) => dabplus.take_pad(),
+        }
+    }
+}
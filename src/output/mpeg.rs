@@ -1,8 +1,10 @@
-use crate::{msc::MainServiceChannelFrame, output::mp2header::Mp2Header};
-use alsa::pcm::{Access, Format, HwParams, PCM};
-use alsa::{Direction, ValueOr};
+use crate::error::{Error, Result};
+use crate::msc::MainServiceChannelFrame;
+use crate::output::AudioOutput;
+use crate::output::mp2header::Mp2Header;
+use crate::output::pcm::PcmOutput;
+use crate::stats::AudioFrames;
 
-use symphonia::core::audio::{AudioBuffer, AudioBufferRef, Signal};
 use symphonia::core::codecs::{CODEC_TYPE_MP2, Decoder, DecoderOptions};
 use symphonia::core::formats::Packet;
 use symphonia::default::get_codecs;
@@ -24,14 +26,11 @@ const HXOR: u32 = 0xfff40400;
 pub struct Mpeg {
     header_expected: bool,
     header_valid: bool,
-    pcm: PCM,
+    pcm: PcmOutput,
     decoder: Box<dyn Decoder>,
 }
 
-pub fn new_mpeg() -> Mpeg {
-    // Open default playback device
-    let pcm = PCM::new("default", Direction::Playback, false).unwrap();
-
+pub fn new_mpeg(pcm: PcmOutput) -> Mpeg {
     let mut codec_params = symphonia::core::codecs::CodecParameters::new();
     codec_params.codec = CODEC_TYPE_MP2;
 
@@ -47,44 +46,24 @@ pub fn new_mpeg() -> Mpeg {
     }
 }
 
-/// Interleave a planar f32 AudioBuffer into Vec<f32> (frames x channels).
-fn interleave_planar_f32(buf: &AudioBuffer<f32>) -> Vec<f32> {
-    let channels = buf.spec().channels.count();
-    let frames = buf.frames();
-    let mut out = Vec::with_capacity(channels * frames);
-    for f in 0..frames {
-        for ch in 0..channels {
-            out.push(buf.chan(ch)[f]);
-        }
-    }
-    out
-}
-
 impl Mpeg {
-    pub fn init(&mut self, channels: u32, rate: u32) {
-        let hwp = HwParams::any(&self.pcm).unwrap();
-        hwp.set_channels(channels).unwrap();
-        hwp.set_rate(rate, ValueOr::Nearest).unwrap();
-        hwp.set_format(Format::FloatLE).unwrap();
-        hwp.set_access(Access::RWInterleaved).unwrap();
-        self.pcm.hw_params(&hwp).unwrap();
-
-        // Make sure we don't start the stream too early
-        let hwp = self.pcm.hw_params_current().unwrap();
-        let swp = self.pcm.sw_params_current().unwrap();
-        swp.set_start_threshold(hwp.get_buffer_size().unwrap())
-            .unwrap();
-        self.pcm.sw_params(&swp).unwrap();
+    pub(super) fn take_pcm(&mut self) -> PcmOutput {
+        std::mem::take(&mut self.pcm)
     }
+}
 
-    pub fn deinit(&mut self) {
+impl AudioOutput for Mpeg {
+    fn deinit(&mut self) {
         self.header_expected = true;
     }
 
-    pub fn output(&mut self, frame: &MainServiceChannelFrame) {
+    fn output(&mut self, frame: &MainServiceChannelFrame) -> Result<AudioFrames> {
         if self.header_expected {
-            let header_bytes = frame.bits[0..4].try_into().expect("four bytes");
-            let header_int = u32::from_be_bytes(header_bytes);
+            let header_bytes = frame
+                .bits
+                .get(0..4)
+                .ok_or(Error::Output("MP2 frame too short for a header"))?;
+            let header_int = u32::from_be_bytes(header_bytes.try_into().unwrap());
             let header = Mp2Header::from_u32(header_int);
             if ((header_int & HMASK) ^ HXOR) != 0 {
                 // eprintln!("header mask check failed: {:x}", header_int);
@@ -111,16 +90,17 @@ impl Mpeg {
                     1 => 2, // joint_stereo
                     2 => 2, // dual_channel
                     3 => 1, // single_channel
-                    _ => panic!("unexpected mp2 mode"),
+                    _ => return Err(Error::Output("unexpected MP2 mode")),
                 };
                 let rate = match header.id {
                     false => 24000,
                     true  => 48000,
                 };
-                self.init(channels, rate);
+                self.pcm.init(channels, rate)?;
             }
         }
 
+        let mut frames = AudioFrames::default();
         if self.header_valid {
 
             // Wrap your frame bytes in a Packet
@@ -129,24 +109,18 @@ impl Mpeg {
             // Decode the packet.
             match self.decoder.decode(&packet) {
                 Ok(audio_ref) => {
-                    match audio_ref {
-                        AudioBufferRef::F32(buf) => {
-                            // Write to ALSA (interleave first).
-                            if let Ok(io) = &self.pcm.io_f32() {
-                                let interleaved = interleave_planar_f32(&buf);
-                                io.writei(&interleaved).expect("writing to audio device");
-                            } else {
-                                panic!("getting io from pcm");
-                            }
-                        }
-                        _ => panic!("unexpected audio format"),
-                    }
+                    self.pcm.write(audio_ref)?;
+                    frames.decoded += 1;
                 }
                 Err(_) => {
                     // eprintln!("Frame decode error: {:?}", e);
                     // Continue on decode errors (or break depending on your use case).
+                    frames.failed += 1;
                 }
             }
+        } else {
+            frames.failed += 1;
         }
+        Ok(frames)
     }
 }
@@ -0,0 +1,104 @@
+use alsa::pcm::{Access, Format, HwParams, PCM};
+use alsa::{Direction, ValueOr};
+
+use symphonia::core::audio::{AudioBuffer, AudioBufferRef, Signal};
+
+use crate::error::{Error, Result};
+
+/// The default playback device, opened once the audio format is known and
+/// kept open while it stays the same
+#[derive(Default)]
+pub struct PcmOutput {
+    pcm: Option<PCM>,
+    // channels and sample rate the device is configured for
+    format: Option<(u32, u32)>,
+}
+
+pub fn new_pcm_output() -> PcmOutput {
+    PcmOutput::default()
+}
+
+/// Interleave a planar f32 AudioBuffer into Vec<f32> (frames x channels).
+fn interleave_planar_f32(buf: &AudioBuffer<f32>) -> Vec<f32> {
+    let channels = buf.spec().channels.count();
+    let frames = buf.frames();
+    let mut out = Vec::with_capacity(channels * frames);
+    for f in 0..frames {
+        for ch in 0..channels {
+            out.push(buf.chan(ch)[f]);
+        }
+    }
+    out
+}
+
+impl PcmOutput {
+    pub fn init(&mut self, channels: u32, rate: u32) -> Result<()> {
+        if self.pcm.is_some() && self.format == Some((channels, rate)) {
+            return Ok(());
+        }
+
+        // close before opening again, the device may not be shared
+        self.pcm = None;
+        self.format = None;
+        let pcm = PCM::new("default", Direction::Playback, false)
+            .map_err(|_| Error::Output("opening audio device"))?;
+        configure(&pcm, channels, rate).map_err(|_| Error::Output("configuring audio device"))?;
+        self.pcm = Some(pcm);
+        self.format = Some((channels, rate));
+        Ok(())
+    }
+
+    /// Decoded audio in any sample format
+    pub fn write(&mut self, audio_ref: AudioBufferRef) -> Result<()> {
+        let interleaved = match audio_ref {
+            AudioBufferRef::F32(buf) => interleave_planar_f32(&buf),
+            audio_ref => {
+                let mut buf = audio_ref.make_equivalent::<f32>();
+                audio_ref.convert(&mut buf);
+                interleave_planar_f32(&buf)
+            }
+        };
+        self.write_interleaved(&interleaved)
+    }
+
+    /// Interleaved 16 bit samples
+    pub fn write_i16(&mut self, samples: &[i16]) -> Result<()> {
+        let interleaved = samples
+            .iter()
+            .map(|s| *s as f32 / 32768.0)
+            .collect::<Vec<f32>>();
+        self.write_interleaved(&interleaved)
+    }
+
+    fn write_interleaved(&mut self, interleaved: &[f32]) -> Result<()> {
+        let pcm = self
+            .pcm
+            .as_ref()
+            .ok_or(Error::Output("audio device not configured"))?;
+        let io = pcm
+            .io_f32()
+            .map_err(|_| Error::Output("getting io from pcm"))?;
+        match io.writei(interleaved) {
+            Ok(_) => Ok(()),
+            // an underrun after a gap in the audio, restart the stream
+            Err(e) => pcm
+                .try_recover(e, true)
+                .map_err(|_| Error::Output("writing to audio device")),
+        }
+    }
+}
+
+fn configure(pcm: &PCM, channels: u32, rate: u32) -> alsa::Result<()> {
+    let hwp = HwParams::any(pcm)?;
+    hwp.set_channels(channels)?;
+    hwp.set_rate(rate, ValueOr::Nearest)?;
+    hwp.set_format(Format::FloatLE)?;
+    hwp.set_access(Access::RWInterleaved)?;
+    pcm.hw_params(&hwp)?;
+
+    // Make sure we don't start the stream too early
+    let hwp = pcm.hw_params_current()?;
+    let swp = pcm.sw_params_current()?;
+    swp.set_start_threshold(hwp.get_buffer_size()?)?;
+    pcm.sw_params(&swp)
+}
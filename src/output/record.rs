@@ -0,0 +1,84 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Result};
+use crate::fic::ensemble::{AudioCodec, Service};
+use crate::msc::MainServiceChannelFrame;
+use crate::output::AudioOutput;
+use crate::output::dabplus::{Superframes, new_superframes};
+use crate::stats::AudioFrames;
+
+/// Writes a service's audio to a file rather than playing it: MP2 frames as
+/// they are, DAB+ access units with ADTS headers.
+pub struct Recorder {
+    path: PathBuf,
+    file: BufWriter<File>,
+    superframes: Option<Superframes>,
+}
+
+/// Start recording the service into the directory, named after its SId
+/// and label.
+pub fn new_recorder(service: &Service, dir: &Path) -> io::Result<Recorder> {
+    let (superframes, extension) = match service.subchannel().and_then(|s| s.audio_codec()) {
+        Some(AudioCodec::AAC) => (Some(new_superframes()), "aac"),
+        _ => (None, "mp2"),
+    };
+    let label: String = service
+        .label()
+        .trim()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect();
+    let path = dir.join(format!("{:04x}-{}.{}", service.id, label, extension));
+    let file = BufWriter::new(File::create(&path)?);
+    Ok(Recorder {
+        path,
+        file,
+        superframes,
+    })
+}
+
+impl Recorder {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn write(&mut self, frame: &MainServiceChannelFrame) -> io::Result<AudioFrames> {
+        match self.superframes.as_mut() {
+            Some(superframes) => {
+                let mut frames = AudioFrames::default();
+                if let Some((header, aus)) = superframes.push(frame) {
+                    frames.failed = header.num_aus().saturating_sub(aus.len()) as u32;
+                    for au in aus {
+                        self.file.write_all(&header.adts_header(au.len()))?;
+                        self.file.write_all(&au)?;
+                        frames.decoded += 1;
+                    }
+                }
+                Ok(frames)
+            }
+            None => {
+                self.file.write_all(&frame.bits)?;
+                Ok(AudioFrames {
+                    decoded: 1,
+                    failed: 0,
+                })
+            }
+        }
+    }
+}
+
+impl AudioOutput for Recorder {
+    fn deinit(&mut self) {
+        if let Some(superframes) = self.superframes.as_mut() {
+            superframes.clear();
+        }
+        let _ = self.file.flush();
+    }
+
+    fn output(&mut self, frame: &MainServiceChannelFrame) -> Result<AudioFrames> {
+        self.write(frame)
+            .map_err(|_| Error::Output("failed to write recording"))
+    }
+}
@@ -0,0 +1,260 @@
+#![allow(non_snake_case)]
+
+use bitvec::prelude::*;
+use core::fmt;
+
+/* ETSI TS 102 980 V2.1.2 (2012-10), 7.4: DL Plus tags command */
+const CID_TAGS: u8 = 0;
+
+/// DL Plus content types, ETSI TS 102 980 Annex A
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContentType {
+    Dummy,
+    ItemTitle,
+    ItemAlbum,
+    ItemTrackNumber,
+    ItemArtist,
+    ItemComposition,
+    ItemMovement,
+    ItemConductor,
+    ItemComposer,
+    ItemBand,
+    ItemComment,
+    ItemGenre,
+    InfoNews,
+    InfoNewsLocal,
+    InfoStockmarket,
+    InfoSport,
+    InfoLottery,
+    InfoHoroscope,
+    InfoDailyDiversion,
+    InfoHealth,
+    InfoEvent,
+    InfoScene,
+    InfoCinema,
+    InfoStupidityMachine,
+    InfoDateTime,
+    InfoWeather,
+    InfoTraffic,
+    InfoAlarm,
+    InfoAdvertisement,
+    InfoUrl,
+    InfoOther,
+    StationNameShort,
+    StationNameLong,
+    ProgrammeNow,
+    ProgrammeNext,
+    ProgrammePart,
+    ProgrammeHost,
+    ProgrammeEditorialStaff,
+    ProgrammeFrequency,
+    ProgrammeHomepage,
+    ProgrammeSubchannel,
+    PhoneHotline,
+    PhoneStudio,
+    PhoneOther,
+    SmsStudio,
+    SmsOther,
+    EmailHotline,
+    EmailStudio,
+    EmailOther,
+    MmsOther,
+    Chat,
+    ChatCenter,
+    VoteQuestion,
+    VoteCentre,
+    // 54 and 55 are reserved for future use
+    Private1,
+    Private2,
+    Private3,
+    DescriptorPlace,
+    DescriptorAppointment,
+    DescriptorIdentifier,
+    DescriptorPurchase,
+    DescriptorGetData,
+    Other(u8),
+}
+
+impl ContentType {
+    pub fn from_u8(code: u8) -> Self {
+        match code {
+            0 => Self::Dummy,
+            1 => Self::ItemTitle,
+            2 => Self::ItemAlbum,
+            3 => Self::ItemTrackNumber,
+            4 => Self::ItemArtist,
+            5 => Self::ItemComposition,
+            6 => Self::ItemMovement,
+            7 => Self::ItemConductor,
+            8 => Self::ItemComposer,
+            9 => Self::ItemBand,
+            10 => Self::ItemComment,
+            11 => Self::ItemGenre,
+            12 => Self::InfoNews,
+            13 => Self::InfoNewsLocal,
+            14 => Self::InfoStockmarket,
+            15 => Self::InfoSport,
+            16 => Self::InfoLottery,
+            17 => Self::InfoHoroscope,
+            18 => Self::InfoDailyDiversion,
+            19 => Self::InfoHealth,
+            20 => Self::InfoEvent,
+            21 => Self::InfoScene,
+            22 => Self::InfoCinema,
+            23 => Self::InfoStupidityMachine,
+            24 => Self::InfoDateTime,
+            25 => Self::InfoWeather,
+            26 => Self::InfoTraffic,
+            27 => Self::InfoAlarm,
+            28 => Self::InfoAdvertisement,
+            29 => Self::InfoUrl,
+            30 => Self::InfoOther,
+            31 => Self::StationNameShort,
+            32 => Self::StationNameLong,
+            33 => Self::ProgrammeNow,
+            34 => Self::ProgrammeNext,
+            35 => Self::ProgrammePart,
+            36 => Self::ProgrammeHost,
+            37 => Self::ProgrammeEditorialStaff,
+            38 => Self::ProgrammeFrequency,
+            39 => Self::ProgrammeHomepage,
+            40 => Self::ProgrammeSubchannel,
+            41 => Self::PhoneHotline,
+            42 => Self::PhoneStudio,
+            43 => Self::PhoneOther,
+            44 => Self::SmsStudio,
+            45 => Self::SmsOther,
+            46 => Self::EmailHotline,
+            47 => Self::EmailStudio,
+            48 => Self::EmailOther,
+            49 => Self::MmsOther,
+            50 => Self::Chat,
+            51 => Self::ChatCenter,
+            52 => Self::VoteQuestion,
+            53 => Self::VoteCentre,
+            56 => Self::Private1,
+            57 => Self::Private2,
+            58 => Self::Private3,
+            59 => Self::DescriptorPlace,
+            60 => Self::DescriptorAppointment,
+            61 => Self::DescriptorIdentifier,
+            62 => Self::DescriptorPurchase,
+            63 => Self::DescriptorGetData,
+            u => Self::Other(u),
+        }
+    }
+
+    // ITEM.* tags only describe the current item while it is running
+    pub fn is_item(&self) -> bool {
+        matches!(
+            self,
+            Self::ItemTitle
+                | Self::ItemAlbum
+                | Self::ItemTrackNumber
+                | Self::ItemArtist
+                | Self::ItemComposition
+                | Self::ItemMovement
+                | Self::ItemConductor
+                | Self::ItemComposer
+                | Self::ItemBand
+                | Self::ItemComment
+                | Self::ItemGenre
+        )
+    }
+}
+
+/// A DL Plus tag applied to the text of its label
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tag {
+    pub content_type: ContentType,
+    pub text: String,
+}
+
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}: {}", self.content_type, self.text)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct RawTag {
+    ContentType: u8,
+    StartMarker: usize,
+    LengthMarker: usize,
+}
+
+/// A DL Plus tags command, 7.4.1
+#[derive(Debug, Clone, PartialEq)]
+pub struct DlPlusCommand {
+    pub item_toggle: bool,
+    pub item_running: bool,
+    tags: Vec<RawTag>,
+}
+
+impl DlPlusCommand {
+    /// Parse the DL Plus command field following the DLS prefix.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let header = bytes.first()?.view_bits::<Msb0>();
+        let CId: u8 = header[0..4].load_be();
+        if CId != CID_TAGS {
+            // other commands are reserved
+            return None;
+        }
+        let IT = header[4];
+        let IR = header[5];
+        let NT: usize = header[6..8].load_be();
+
+        let mut tags = Vec::with_capacity(NT + 1);
+        for tag in bytes[1..].chunks_exact(3).take(NT + 1) {
+            tags.push(RawTag {
+                ContentType: tag[0] & 0x7f,
+                StartMarker: (tag[1] & 0x7f) as usize,
+                LengthMarker: (tag[2] & 0x7f) as usize,
+            });
+        }
+        if tags.len() != NT + 1 {
+            return None;
+        }
+
+        Some(Self {
+            item_toggle: IT,
+            item_running: IR,
+            tags,
+        })
+    }
+
+    /// The tags applied to the label they were sent with. Markers count
+    /// characters, not bytes.
+    pub fn tags(&self, label: &str) -> Vec<Tag> {
+        let chars: Vec<char> = label.chars().collect();
+        self.tags
+            .iter()
+            .filter(|t| t.ContentType != 0)
+            .filter(|t| t.StartMarker + t.LengthMarker < chars.len())
+            .map(|t| Tag {
+                content_type: ContentType::from_u8(t.ContentType),
+                text: chars[t.StartMarker..=(t.StartMarker + t.LengthMarker)]
+                    .iter()
+                    .collect::<String>()
+                    .trim()
+                    .to_string(),
+            })
+            .filter(|t| self.item_running || !t.content_type.is_item())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_types_at_the_end_of_the_table() {
+        assert_eq!(ContentType::from_u8(53), ContentType::VoteCentre);
+        assert_eq!(ContentType::from_u8(55), ContentType::Other(55));
+        assert_eq!(ContentType::from_u8(56), ContentType::Private1);
+        assert_eq!(ContentType::from_u8(58), ContentType::Private3);
+        assert_eq!(ContentType::from_u8(59), ContentType::DescriptorPlace);
+        assert_eq!(ContentType::from_u8(63), ContentType::DescriptorGetData);
+    }
+}
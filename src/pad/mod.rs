@@ -1,60 +1,101 @@
 #![allow(non_snake_case)]
 
+use crate::charset;
+use crate::decode::crc16_ccitt_check;
+use crate::error::{Error, Result};
 use crate::msc::MainServiceChannelFrame;
+use crate::msc::datagroup::parse_data_group;
+use crate::output::mp2header::Mp2Header;
 use bitvec::prelude::*;
 
+pub mod dlplus;
+pub mod mot;
+
+use dlplus::{ContentType, DlPlusCommand, Tag};
+use mot::{MotDecoder, Slide, new_mot_decoder};
+
+#[derive(Debug, Clone)]
 pub struct Label {
     pub label: String,
     pub is_new: bool,
+    pub item_toggle: Option<bool>,
+    pub item_running: bool,
+    pub artist: Option<String>,
+    pub title: Option<String>,
+    pub tags: Vec<Tag>,
 }
 
-pub struct Error {}
+impl Label {
+    pub fn tag(&self, content_type: ContentType) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|t| t.content_type == content_type)
+            .map(|t| t.text.as_str())
+    }
+}
+
+pub enum PadData {
+    Label(Label),
+    Slide(Slide),
+}
 
 const LABEL_MAX: usize = 128;
 const SEGMENT_MAX: usize = 16;
+const SEGMENTS: usize = LABEL_MAX / SEGMENT_MAX;
 
-#[derive(Debug, Clone)]
+/* ETSI EN 300 401 V2.1.1 (2017-01), 7.4.4.2 Table 12: variable size X-PAD sub-field lengths */
+const XPAD_LENGTHS: [usize; 8] = [4, 6, 8, 12, 16, 24, 32, 48];
+
+/* 7.4.3 Table 11: X-PAD application types */
+const APP_END_MARKER: u8 = 0;
+const APP_DG_LENGTH: u8 = 1;
+const APP_DLS_START: u8 = 2;
+const APP_DLS_CONT: u8 = 3;
+const APP_MOT_START: u8 = 12;
+const APP_MOT_CONT: u8 = 13;
+
+/* 7.4.5.2: DLS special commands */
+const DLS_CMD_DLPLUS: u8 = 2;
+
+#[derive(Debug)]
 pub struct PadState {
     bitrate: i32,
     sampling_freq: i32,
-    seglen: usize,
-    ci: u8,
-    toggle: bool,
-    firstlast: FirstLast,
-    label: [u8; LABEL_MAX],
-    label_offset: usize,
-    offset: usize,
-    crc: [u8; 2],
-    crc_offset: usize,
-    segment: [u8; SEGMENT_MAX],
-    segnum: u8,
-    is_new: bool,
+    last_app: u8,
+    last_len: usize,
+    dls_dg: XPadDataGroup,
+    mot_dg: XPadDataGroup,
+    dg_length: Option<usize>,
+    toggle: Option<bool>,
+    charset: u8,
+    segments: [Option<Vec<u8>>; SEGMENTS],
+    last_segment: Option<u8>,
+    emitted_toggle: Option<bool>,
+    label: Option<String>,
+    dlplus: Option<DlPlusCommand>,
+    mot: MotDecoder,
 }
 
 pub fn new_padstate() -> PadState {
     PadState {
         bitrate: 0,
         sampling_freq: 0,
-        seglen: 0,
-        ci: 0,
-        toggle: false,
-        firstlast: FirstLast::First,
-        label: [0; LABEL_MAX],
-        label_offset: 0,
-        offset: 0,
-        crc: [0; 2],
-        crc_offset: 0,
-        segment: [0; SEGMENT_MAX],
-        segnum: 0,
-        is_new: true, // assume first DLS label received is new
+        last_app: APP_END_MARKER,
+        last_len: 0,
+        dls_dg: XPadDataGroup::default(),
+        mot_dg: XPadDataGroup::default(),
+        dg_length: None,
+        toggle: None,
+        charset: 0,
+        segments: Default::default(),
+        last_segment: None,
+        emitted_toggle: None,
+        label: None,
+        dlplus: None,
+        mot: new_mot_decoder(),
     }
 }
 
-/// Dummy placeholder for `crc16check`
-fn _crc16check(_data: &[u8], _len: i32) {
-    // In real code, implement CRC16 validation here
-}
-
 #[derive(Debug)]
 struct FPad {
     _Z: bool,
@@ -85,13 +126,13 @@ struct FPad00 {
 }
 
 impl FPad00 {
-    pub fn from_u8(bits: u8) -> Self {
+    pub fn from_u8(bits: u8) -> Result<Self> {
         let bits = bits.view_bits::<Lsb0>();
-        Self {
+        Ok(Self {
             _ByteLInd: bits[0..4].load_be(),
-            XPadInd: XPadInd::from_u8(bits[4..6].load_be()),
+            XPadInd: XPadInd::from_u8(bits[4..6].load_be())?,
             _FType: bits[6..8].load_be(),
-        }
+        })
     }
 }
 
@@ -104,38 +145,53 @@ enum XPadInd {
 }
 
 impl XPadInd {
-    pub fn from_u8(bits: u8) -> Self {
+    pub fn from_u8(bits: u8) -> Result<Self> {
         match bits {
-            0 => Self::NoXPad,
-            1 => Self::ShortXPad,
-            2 => Self::VariableXPad,
-            3 => Self::Reserved,
-            u => panic!("really unexpected XPadInd: {}", u),
+            0 => Ok(Self::NoXPad),
+            1 => Ok(Self::ShortXPad),
+            2 => Ok(Self::VariableXPad),
+            3 => Ok(Self::Reserved),
+            _ => Err(Error::Pad("unexpected XPadInd")),
         }
     }
 }
 
 #[derive(Debug)]
 struct DlsPad {
-    _rfa: u8,
-    f2: u8,
-    f1: u8,
-    cmd: u8,
-    firstlast: FirstLast,
     toggle: bool,
+    firstlast: FirstLast,
+    cmd: u8,
+    f1: u8,
+    f2: u8,
 }
 
 impl DlsPad {
-    pub fn from_u16(bits: u16) -> Self {
-        let bits = bits.view_bits::<Lsb0>();
-        Self {
-            _rfa: bits[0..4].load_be(),
-            f2: bits[4..8].load_be(),
-            f1: bits[8..12].load_be(),
-            cmd: if bits[12] { 1 } else { 0 },
-            firstlast: FirstLast::from_u8(bits[13..14].load_be()),
-            toggle: bits[15],
-        }
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let bits = bytes
+            .get(0..2)
+            .ok_or(Error::Pad("short DLS prefix"))?
+            .view_bits::<Msb0>();
+        Ok(Self {
+            toggle: bits[0],
+            firstlast: FirstLast::from_u8(bits[1..3].load_be())?,
+            cmd: if bits[3] { 1 } else { 0 },
+            f1: bits[4..8].load_be(),
+            f2: bits[8..12].load_be(),
+        })
+    }
+
+    // Length of the whole DLS data group, including prefix and CRC
+    pub fn dg_length(bytes: &[u8]) -> Option<usize> {
+        let dls = DlsPad::from_bytes(bytes).ok()?;
+        let field = match (dls.cmd, dls.f1) {
+            (0, len) => len as usize + 1,
+            // clear display
+            (1, 1) => 0,
+            // DL Plus command, with its own length in the second byte
+            (1, DLS_CMD_DLPLUS) => (bytes[1] & 0x0f) as usize + 1,
+            _ => return None,
+        };
+        Some(2 + field + 2)
     }
 }
 
@@ -148,34 +204,109 @@ enum FirstLast {
 }
 
 impl FirstLast {
-    pub fn from_u8(bits: u8) -> Self {
+    pub fn from_u8(bits: u8) -> Result<Self> {
         match bits {
-            0 => Self::Intermediate,
-            1 => Self::Last,
-            2 => Self::First,
-            3 => Self::OneAndOnly,
-            u => panic!("unexpected FirstLast: {}", u),
+            0 => Ok(Self::Intermediate),
+            1 => Ok(Self::Last),
+            2 => Ok(Self::First),
+            3 => Ok(Self::OneAndOnly),
+            _ => Err(Error::Pad("unexpected FirstLast")),
+        }
+    }
+
+    fn first(&self) -> bool {
+        *self == FirstLast::First || *self == FirstLast::OneAndOnly
+    }
+
+    fn last(&self) -> bool {
+        *self == FirstLast::Last || *self == FirstLast::OneAndOnly
+    }
+}
+
+// One application's bytes from a single X-PAD field
+#[derive(Debug)]
+struct SubField {
+    app: u8,
+    bytes: Vec<u8>,
+}
+
+// An X-PAD data group being collected across frames
+#[derive(Debug, Default)]
+struct XPadDataGroup {
+    bytes: Vec<u8>,
+    active: bool,
+}
+
+impl XPadDataGroup {
+    fn start(&mut self, bytes: &[u8]) {
+        self.bytes = bytes.to_vec();
+        self.active = true;
+    }
+
+    fn append(&mut self, bytes: &[u8]) {
+        if self.active {
+            self.bytes.extend_from_slice(bytes);
+        }
+    }
+
+    // The data group, once `len` bytes have arrived
+    fn take(&mut self, len: usize) -> Option<Vec<u8>> {
+        if !self.active || self.bytes.len() < len {
+            return None;
         }
+        self.active = false;
+        self.bytes.truncate(len);
+        Some(std::mem::take(&mut self.bytes))
+    }
+}
+
+// Only DLS and MOT data groups continue into following X-PAD fields
+fn continuation(app: u8) -> u8 {
+    match app {
+        APP_DLS_START | APP_DLS_CONT => APP_DLS_CONT,
+        APP_MOT_START | APP_MOT_CONT => APP_MOT_CONT,
+        _ => APP_END_MARKER,
     }
 }
 
 impl PadState {
-    pub fn output(&mut self, frame: &MainServiceChannelFrame) -> Result<Label, Error> {
+    pub fn output(&mut self, frame: &MainServiceChannelFrame) -> Result<Vec<PadData>> {
         let bits = &frame.bits;
         let bytes = bits.len();
-        if bytes < 2 {
-            return Err(Error {});
+        if bytes < 4 {
+            return Err(Error::Pad("frame too short for F-PAD"));
         }
 
-        let fpad = u16::from_be_bytes([bits[bytes - 2], bits[bytes - 1]]);
-        let p = FPad::from_u16(fpad);
+        self.bitrate = frame.bitrate as i32;
+        let header = Mp2Header::from_u32(u32::from_be_bytes([bits[0], bits[1], bits[2], bits[3]]));
+        self.sampling_freq = if header.id { 48 } else { 24 };
+
+        // X-PAD ends before the scale factor CRC
+        let xpadoff = bytes.saturating_sub(self.scf_words() + 2);
+        self.decode(&bits[..xpadoff], [bits[bytes - 2], bits[bytes - 1]])
+    }
+
+    /* ETSI TS 102 563 V2.1.1 (2017-01), 5.4.3: a DAB+ access unit carries
+    its PAD in a data stream element, laid out as at the end of an MP2
+    frame, X-PAD then the two F-PAD bytes */
+    pub fn output_dse(&mut self, field: &[u8]) -> Result<Vec<PadData>> {
+        let Some(xpadoff) = field.len().checked_sub(2) else {
+            return Err(Error::Pad("data stream element too short for F-PAD"));
+        };
+        self.decode(&field[..xpadoff], [field[xpadoff], field[xpadoff + 1]])
+    }
 
+    fn decode(&mut self, xpad: &[u8], fpad: [u8; 2]) -> Result<Vec<PadData>> {
+        let p = FPad::from_u16(u16::from_be_bytes(fpad));
+
+        let mut output = vec![];
         if p.FType == 0 {
-            let p00 = FPad00::from_u8(p.ByteL1);
-            return self.fpad00(bits, p, p00);
+            let p00 = FPad00::from_u8(p.ByteL1)?;
+            for subfield in self.fpad00(xpad, p, p00) {
+                self.subfield(subfield, &mut output);
+            }
         }
-
-        Err(Error {})
+        Ok(output)
     }
 
     fn scf_words(&self) -> usize {
@@ -186,84 +317,186 @@ impl PadState {
         }
     }
 
-    fn fpad00(&mut self, bits: &[u8], p: FPad, p00: FPad00) -> Result<Label, Error> {
-        if p00.XPadInd == XPadInd::ShortXPad {
-            let xpadoff = bits.len() - (self.scf_words() + 2);
-            let xpad = &bits[(xpadoff - 4)..xpadoff];
-
-            if p.CIFlag {
-                self.ci = xpad[3];
-                if self.ci == 2 {
-                    // DLS, start of X-PAD data group
-                    let prefix = u16::from_be_bytes([xpad[2], xpad[1]]);
-                    let dls = DlsPad::from_u16(prefix);
-
-                    if dls.firstlast == FirstLast::First && dls.toggle != self.toggle {
-                        self.toggle = dls.toggle;
-                        self.is_new = true;
-                    }
+    // Split the X-PAD field into its application sub-fields
+    fn fpad00(&mut self, field: &[u8], p: FPad, p00: FPad00) -> Vec<SubField> {
+        // X-PAD is transmitted backwards, ending next to the F-PAD
+        let xpad: Vec<u8> = field.iter().rev().copied().collect();
 
-                    if dls.cmd == 0 {
-                        // f1 is segment length
-                        self.seglen = dls.f1 as usize + 1;
-                        self.offset = 0;
-                        self.crc_offset = 0;
-                    }
-                    if dls.cmd == 1 {
-                        // f1 is "special command"
-                        eprintln!("special command: {}", dls.f1);
-                    }
-                    if dls.firstlast == FirstLast::First || dls.firstlast == FirstLast::OneAndOnly {
-                        eprintln!("charset: {}", dls.f2);
+        let mut subfields = vec![];
+        match p00.XPadInd {
+            XPadInd::ShortXPad if xpad.len() >= 4 => {
+                if p.CIFlag {
+                    let app = xpad[0] & 0x1f;
+                    subfields.push(SubField {
+                        app,
+                        bytes: xpad[1..4].to_vec(),
+                    });
+                    self.last_app = app;
+                } else if continuation(self.last_app) != APP_END_MARKER {
+                    subfields.push(SubField {
+                        app: continuation(self.last_app),
+                        bytes: xpad[0..4].to_vec(),
+                    });
+                }
+                self.last_len = 4;
+            }
+            XPadInd::VariableXPad => {
+                if p.CIFlag {
+                    let mut cis = vec![];
+                    let mut offset = 0;
+                    while offset < 4 && offset < xpad.len() {
+                        let ci = xpad[offset];
+                        offset += 1;
+                        if ci & 0x1f == APP_END_MARKER {
+                            break;
+                        }
+                        cis.push((XPAD_LENGTHS[(ci >> 5) as usize], ci & 0x1f));
                     }
-                    if dls.firstlast == FirstLast::Intermediate || dls.firstlast == FirstLast::Last
-                    {
-                        self.segnum = dls.f2;
-
-                        // Catch first-time issue: coming in part way through a label
-                        if self.segnum > 0 && self.label_offset == 0 {
-                            self.ci = 0;
-                            return Err(Error {});
+                    for (len, app) in cis {
+                        if offset + len > xpad.len() {
+                            break;
                         }
+                        subfields.push(SubField {
+                            app,
+                            bytes: xpad[offset..(offset + len)].to_vec(),
+                        });
+                        offset += len;
+                        self.last_app = app;
+                        self.last_len = len;
                     }
+                } else if continuation(self.last_app) != APP_END_MARKER
+                    && self.last_len <= xpad.len()
+                {
+                    subfields.push(SubField {
+                        app: continuation(self.last_app),
+                        bytes: xpad[0..self.last_len].to_vec(),
+                    });
+                }
+            }
+            _ => self.last_app = APP_END_MARKER,
+        }
+        subfields
+    }
 
-                    self.segment = [0; 16];
-                    self.segment[self.offset] = xpad[0];
-                    self.offset += 1;
-                    self.firstlast = dls.firstlast;
-
-                    if self.segnum == 0 {
-                        self.label = [0; LABEL_MAX];
-                        self.label_offset = 0;
+    fn subfield(&mut self, subfield: SubField, output: &mut Vec<PadData>) {
+        match subfield.app {
+            APP_DG_LENGTH => {
+                let b = &subfield.bytes;
+                if b.len() >= 4 && crc16_ccitt_check(&b[0..4]) {
+                    self.dg_length = Some((u16::from_be_bytes([b[0], b[1]]) & 0x3fff) as usize);
+                }
+            }
+            APP_DLS_START | APP_DLS_CONT => {
+                if subfield.app == APP_DLS_START {
+                    self.dls_dg.start(&subfield.bytes);
+                } else {
+                    self.dls_dg.append(&subfield.bytes);
+                }
+                if let Some(len) = DlsPad::dg_length(&self.dls_dg.bytes)
+                    && let Some(dg) = self.dls_dg.take(len)
+                    && let Some(label) = self.dls(&dg)
+                {
+                    output.push(PadData::Label(label));
+                }
+            }
+            APP_MOT_START | APP_MOT_CONT => {
+                if subfield.app == APP_MOT_START {
+                    // a MOT data group is always preceded by its length
+                    if self.dg_length.is_none() {
+                        return;
                     }
+                    self.mot_dg.start(&subfield.bytes);
+                } else {
+                    self.mot_dg.append(&subfield.bytes);
                 }
-            } else if self.ci == 2 {
-                // previously set CI == 2
-                for i in 0..4 {
-                    if self.offset < self.seglen {
-                        self.segment[self.offset] = xpad[3 - i];
-                        self.offset += 1;
-                    } else if self.crc_offset < 2 {
-                        self.crc[self.crc_offset] = xpad[3 - i];
-                        self.crc_offset += 1;
+                if let Some(len) = self.dg_length
+                    && let Some(bytes) = self.mot_dg.take(len)
+                {
+                    self.dg_length = None;
+                    if let Ok(dg) = parse_data_group(&bytes)
+                        && let Some(slide) = self.mot.push(&dg)
+                    {
+                        output.push(PadData::Slide(slide));
                     }
                 }
+            }
+            _ => {}
+        }
+    }
 
-                if self.offset == self.seglen && self.crc_offset == 2 {
-                    self.label[self.label_offset..(self.label_offset + self.seglen)]
-                        .copy_from_slice(&self.segment[0..self.seglen]);
-                    self.label_offset += self.seglen;
+    // A complete DLS data group: one segment of a label, or a command
+    fn dls(&mut self, dg: &[u8]) -> Option<Label> {
+        if !crc16_ccitt_check(dg) {
+            return None;
+        }
+        let dls = DlsPad::from_bytes(dg).ok()?;
 
-                    if self.firstlast == FirstLast::Last {
-                        let label_string = String::from_utf8_lossy(&self.label[0..self.label_offset]).to_string();
-                        return Ok(Label {
-                            is_new: self.is_new,
-                            label: label_string,
-                        });
-                    }
-                }
+        if self.toggle != Some(dls.toggle) {
+            // a new label
+            self.toggle = Some(dls.toggle);
+            self.segments = Default::default();
+            self.last_segment = None;
+            self.label = None;
+            self.dlplus = None;
+        }
+
+        if dls.cmd == 1 {
+            if dls.f1 != DLS_CMD_DLPLUS {
+                return None;
+            }
+            // DL Plus tags for the label with the same toggle, which may
+            // still be on its way
+            let command = DlPlusCommand::from_bytes(&dg[2..(dg.len() - 2)])?;
+            if self.dlplus.as_ref() == Some(&command) {
+                return None;
             }
+            self.dlplus = Some(command);
+            return self.label();
+        }
+
+        let segnum = if dls.firstlast.first() {
+            self.charset = dls.f2;
+            0
+        } else {
+            dls.f2 & 0x07
+        };
+        if dls.firstlast.last() {
+            self.last_segment = Some(segnum);
         }
-        Err(Error {})
+        self.segments[segnum as usize] = Some(dg[2..(dg.len() - 2)].to_vec());
+
+        let last = self.last_segment? as usize;
+        if self.segments[0..=last].iter().any(|s| s.is_none()) {
+            return None;
+        }
+        let label: Vec<u8> = self.segments[0..=last].iter().flatten().flatten().copied().collect();
+        self.label = Some(charset::decode(self.charset, &label));
+        self.label()
+    }
+
+    // The current label, with any DL Plus tags applied
+    fn label(&mut self) -> Option<Label> {
+        let label = self.label.clone()?;
+        let tags = self
+            .dlplus
+            .as_ref()
+            .map(|c| c.tags(&label))
+            .unwrap_or_default();
+
+        let is_new = self.emitted_toggle != self.toggle;
+        self.emitted_toggle = self.toggle;
+
+        let mut label = Label {
+            label,
+            is_new,
+            item_toggle: self.dlplus.as_ref().map(|c| c.item_toggle),
+            item_running: self.dlplus.as_ref().is_some_and(|c| c.item_running),
+            artist: None,
+            title: None,
+            tags,
+        };
+        label.artist = label.tag(ContentType::ItemArtist).map(str::to_string);
+        label.title = label.tag(ContentType::ItemTitle).map(str::to_string);
+        Some(label)
     }
 }
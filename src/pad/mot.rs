@@ -0,0 +1,261 @@
+#![allow(non_snake_case)]
+
+use bitvec::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::charset;
+use crate::msc::datagroup::DataGroup;
+
+/* ETSI EN 301 234 V2.1.1 (2006-05), 5.1.1: data group types */
+const DG_MOT_HEADER: u8 = 3;
+const DG_MOT_BODY: u8 = 4;
+
+/* 6.1 ContentType "Image", and its subtypes */
+const CONTENT_TYPE_IMAGE: u8 = 2;
+const CONTENT_SUBTYPE_GIF: u16 = 0;
+const CONTENT_SUBTYPE_JFIF: u16 = 1;
+const CONTENT_SUBTYPE_BMP: u16 = 2;
+const CONTENT_SUBTYPE_PNG: u16 = 3;
+
+/* 6.2 header extension parameters */
+const PARAM_TRIGGER_TIME: u8 = 0x05;
+const PARAM_CONTENT_NAME: u8 = 0x0c;
+
+/* Objects in flight at once; slides are sent one after the other */
+const MAX_OBJECTS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TriggerTime {
+    Now,
+    At {
+        mjd: u32,
+        hours: u8,
+        minutes: u8,
+        seconds: u8,
+    },
+}
+
+/// A complete MOT SlideShow image
+#[derive(Debug, Clone)]
+pub struct Slide {
+    pub transport_id: u16,
+    pub content_name: String,
+    pub content_subtype: u16,
+    pub trigger_time: TriggerTime,
+    pub body: Vec<u8>,
+}
+
+impl Slide {
+    pub fn extension(&self) -> &str {
+        match self.content_subtype {
+            CONTENT_SUBTYPE_GIF => "gif",
+            CONTENT_SUBTYPE_JFIF => "jpg",
+            CONTENT_SUBTYPE_BMP => "bmp",
+            CONTENT_SUBTYPE_PNG => "png",
+            _ => "bin",
+        }
+    }
+
+    /// Write the image into the directory, named after its ContentName.
+    pub fn write_to_dir(&self, dir: &Path) -> io::Result<PathBuf> {
+        // ContentName is chosen by the broadcaster, so keep only the last component
+        let name = Path::new(&self.content_name)
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .filter(|n| !n.is_empty())
+            .unwrap_or(format!("slide-{:04x}", self.transport_id));
+
+        let mut path = dir.join(name);
+        if path.extension().is_none() {
+            path.set_extension(self.extension());
+        }
+        fs::write(&path, &self.body)?;
+        Ok(path)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Segments {
+    parts: BTreeMap<u16, Vec<u8>>,
+    last: Option<u16>,
+}
+
+impl Segments {
+    fn push(&mut self, number: u16, last: bool, data: &[u8]) {
+        self.parts.entry(number).or_insert_with(|| data.to_vec());
+        if last {
+            self.last = Some(number);
+        }
+    }
+
+    fn complete(&self) -> Option<Vec<u8>> {
+        let last = self.last?;
+        if self.parts.len() != last as usize + 1 || self.parts.keys().last() != Some(&last) {
+            return None;
+        }
+        Some(self.parts.values().flatten().copied().collect())
+    }
+}
+
+#[derive(Debug, Default)]
+struct MotObject {
+    header: Segments,
+    body: Segments,
+}
+
+#[derive(Debug)]
+struct MotHeader {
+    BodySize: usize,
+    ContentType: u8,
+    ContentSubType: u16,
+    content_name: String,
+    trigger_time: TriggerTime,
+}
+
+fn parse_header(bytes: &[u8]) -> Option<MotHeader> {
+    if bytes.len() < 7 {
+        return None;
+    }
+    let core = bytes[0..7].view_bits::<Msb0>();
+    let BodySize: usize = core[0..28].load_be();
+    let HeaderSize: usize = core[28..41].load_be();
+    let ContentType: u8 = core[41..47].load_be();
+    let ContentSubType: u16 = core[47..56].load_be();
+
+    let mut header = MotHeader {
+        BodySize,
+        ContentType,
+        ContentSubType,
+        content_name: String::new(),
+        trigger_time: TriggerTime::Now,
+    };
+
+    let end = HeaderSize.min(bytes.len());
+    let mut offset = 7;
+    while offset < end {
+        let PLI = bytes[offset] >> 6;
+        let ParamId = bytes[offset] & 0x3f;
+        offset += 1;
+
+        let len = match PLI {
+            0 => 0,
+            1 => 1,
+            2 => 4,
+            _ => {
+                let b = *bytes.get(offset)?;
+                if b & 0x80 != 0 {
+                    let len = (((b & 0x7f) as usize) << 8) | *bytes.get(offset + 1)? as usize;
+                    offset += 2;
+                    len
+                } else {
+                    offset += 1;
+                    b as usize
+                }
+            }
+        };
+        if offset + len > end {
+            return None;
+        }
+        let data = &bytes[offset..(offset + len)];
+        offset += len;
+
+        match ParamId {
+            PARAM_CONTENT_NAME if !data.is_empty() => {
+                // first byte is the character set indicator
+                header.content_name = charset::decode(data[0] >> 4, &data[1..]);
+            }
+            PARAM_TRIGGER_TIME if data.len() >= 4 => {
+                header.trigger_time = trigger_time(data);
+            }
+            _ => {}
+        }
+    }
+
+    Some(header)
+}
+
+// ETSI EN 301 234 V2.1.1 (2006-05), 6.2.4.1: the UTC time encoding of FIG 0/10
+fn trigger_time(data: &[u8]) -> TriggerTime {
+    let bits = data.view_bits::<Msb0>();
+    if !bits[0] {
+        return TriggerTime::Now;
+    }
+    let UTCFlag = bits[20];
+    TriggerTime::At {
+        mjd: bits[1..18].load_be(),
+        hours: bits[21..26].load_be(),
+        minutes: bits[26..32].load_be(),
+        seconds: if UTCFlag && bits.len() >= 38 {
+            bits[32..38].load_be()
+        } else {
+            0
+        },
+    }
+}
+
+/// Reassembles MOT objects sent in header mode from their data groups,
+/// and passes on the images.
+#[derive(Debug, Default)]
+pub struct MotDecoder {
+    objects: HashMap<u16, MotObject>,
+    last_complete: Option<u16>,
+}
+
+pub fn new_mot_decoder() -> MotDecoder {
+    MotDecoder {
+        objects: HashMap::new(),
+        last_complete: None,
+    }
+}
+
+impl MotDecoder {
+    pub fn push(&mut self, dg: &DataGroup) -> Option<Slide> {
+        if dg.dg_type != DG_MOT_HEADER && dg.dg_type != DG_MOT_BODY {
+            return None;
+        }
+        let transport_id = dg.transport_id?;
+        let segment = dg.segment?;
+
+        // Segmentation header: repetition count and segment size, 5.1.1
+        if dg.data.len() < 2 {
+            return None;
+        }
+        let SegmentSize = (u16::from_be_bytes([dg.data[0], dg.data[1]]) & 0x1fff) as usize;
+        let data = &dg.data[2..(2 + SegmentSize).min(dg.data.len())];
+
+        if self.last_complete == Some(transport_id) {
+            // repetition of a slide we already have
+            return None;
+        }
+
+        if !self.objects.contains_key(&transport_id) && self.objects.len() >= MAX_OBJECTS {
+            self.objects.clear();
+        }
+        let object = self.objects.entry(transport_id).or_default();
+        if dg.dg_type == DG_MOT_HEADER {
+            object.header.push(segment.number, segment.last, data);
+        } else {
+            object.body.push(segment.number, segment.last, data);
+        }
+
+        let header = parse_header(&object.header.complete()?)?;
+        let body = object.body.complete()?;
+        self.objects.remove(&transport_id);
+
+        if body.len() != header.BodySize || header.ContentType != CONTENT_TYPE_IMAGE {
+            return None;
+        }
+        self.last_complete = Some(transport_id);
+
+        Some(Slide {
+            transport_id,
+            content_name: header.content_name,
+            content_subtype: header.ContentSubType,
+            trigger_time: header.trigger_time,
+            body,
+        })
+    }
+}
@@ -4,7 +4,7 @@ use std::convert::TryFrom;
 
 mod fft;
 mod maths;
-mod reference;
+pub mod reference;
 pub mod sync;
 
 pub const PRS_POINTS: usize = 2048;
@@ -1,81 +1,67 @@
-use crate::prs::PhaseReferenceArray;
+use crate::prs::{PRS_POINTS, PhaseReferenceArray};
 use rustfft::num_complex::{Complex64, c64};
 use std::str::FromStr;
 
-// /* From ETSI EN 300 401 V1.3.3 Sect.14.3.2 Table 48 */
-// const h: [[usize; 32]; 4] = [
-//     [
-//         0, 2, 0, 0, 0, 0, 1, 1, 2, 0, 0, 0, 2, 2, 1, 1, 0, 2, 0, 0, 0, 0, 1, 1, 2, 0, 0, 0, 2, 2,
-//         1, 1,
-//     ],
-//     [
-//         0, 3, 2, 3, 0, 1, 3, 0, 2, 1, 2, 3, 2, 3, 3, 0, 0, 3, 2, 3, 0, 1, 3, 0, 2, 1, 2, 3, 2, 3,
-//         3, 0,
-//     ],
-//     [
-//         0, 0, 0, 2, 0, 2, 1, 3, 2, 2, 0, 2, 2, 0, 1, 3, 0, 0, 0, 2, 0, 2, 1, 3, 2, 2, 0, 2, 2, 0,
-//         1, 3,
-//     ],
-//     [
-//         0, 1, 2, 1, 0, 3, 3, 2, 2, 3, 2, 1, 2, 1, 3, 2, 0, 1, 2, 1, 0, 3, 3, 2, 2, 3, 2, 1, 2, 1,
-//         3, 2,
-//     ],
-// ];
+/* From ETSI EN 300 401 V1.3.3 Sect.14.3.2 Table 48 */
+const H: [[u8; 32]; 4] = [
+    [
+        0, 2, 0, 0, 0, 0, 1, 1, 2, 0, 0, 0, 2, 2, 1, 1, 0, 2, 0, 0, 0, 0, 1, 1, 2, 0, 0, 0, 2, 2,
+        1, 1,
+    ],
+    [
+        0, 3, 2, 3, 0, 1, 3, 0, 2, 1, 2, 3, 2, 3, 3, 0, 0, 3, 2, 3, 0, 1, 3, 0, 2, 1, 2, 3, 2, 3,
+        3, 0,
+    ],
+    [
+        0, 0, 0, 2, 0, 2, 1, 3, 2, 2, 0, 2, 2, 0, 1, 3, 0, 0, 0, 2, 0, 2, 1, 3, 2, 2, 0, 2, 2, 0,
+        1, 3,
+    ],
+    [
+        0, 1, 2, 1, 0, 3, 3, 2, 2, 3, 2, 1, 2, 1, 3, 2, 0, 1, 2, 1, 0, 3, 3, 2, 2, 3, 2, 1, 2, 1,
+        3, 2,
+    ],
+];
 
-// /* From ETSI EN 300 401 V1.3.3 Sect.14.3.2 Table 44 */
-// const i: [usize; 48] = [
-//     0, 1, 2, 3, 0, 1, 2, 3, 0, 1, 2, 3, 0, 1, 2, 3, 0, 1, 2, 3, 0, 1, 2, 3, 0, 3, 2, 1, 0, 3, 2, 1,
-//     0, 3, 2, 1, 0, 3, 2, 1, 0, 3, 2, 1, 0, 3, 2, 1,
-// ];
+/* From ETSI EN 300 401 V1.3.3 Sect.14.3.2 Table 44, Transmission Mode I:
+i and n for each block of 32 carriers from k = -768 up to k = 768 */
+const I: [usize; 48] = [
+    0, 1, 2, 3, 0, 1, 2, 3, 0, 1, 2, 3, 0, 1, 2, 3, 0, 1, 2, 3, 0, 1, 2, 3, 0, 3, 2, 1, 0, 3, 2, 1,
+    0, 3, 2, 1, 0, 3, 2, 1, 0, 3, 2, 1, 0, 3, 2, 1,
+];
 
-// const n: [usize; 48] = [
-//     1, 2, 0, 1, 3, 2, 2, 3, 2, 1, 2, 3, 1, 2, 3, 3, 2, 2, 2, 1, 1, 3, 1, 2, 3, 1, 1, 1, 2, 2, 1, 0,
-//     2, 2, 3, 3, 0, 2, 1, 3, 3, 3, 3, 0, 3, 0, 1, 1,
-// ];
+const N: [u8; 48] = [
+    1, 2, 0, 1, 3, 2, 2, 3, 2, 1, 2, 3, 1, 2, 3, 3, 2, 2, 2, 1, 1, 3, 1, 2, 3, 1, 1, 1, 2, 2, 1, 0,
+    2, 2, 3, 3, 0, 2, 1, 3, 3, 3, 3, 0, 3, 0, 1, 1,
+];
 
-// const cospi2: [i32; 4] = [1, 0, -1, 0]; /* cos(0), cos(pi/2), cos(pi), cos(3*pi/2) */
-// const sinpi2: [i32; 4] = [0, 1, 0, -1]; /* sin(0), sin(pi/2), sin(pi), sin(3*pi/2) */
-// pub fn prs_reference() -> ([Complex64; 2048], [Complex64; 2048]) {
-//     let mut prs_syms = [c64(0, 0); 2048];
-//     let mut prs_conj = [c64(0, 0); 2048];
+const CARRIERS: i32 = 768;
 
-//     let mut j: usize = 0;
-//     let mut kpl: i32 = -768;
-//     let mut kp: i32;
-//     let mut k: i32 = -768;
-//     while k < 769 {
-//         if k < 0 {
-//             kp = if k % 32 == 0 { k } else { k - 32 - k % 32 };
-//         } else {
-//             kp = if k % 32 == 0 { k - 31 } else { k - k % 32 + 1 };
-//         }
-
-//         assert!(j < 48);
-//         assert!(k - kp >= 0);
-//         assert!(k - kp < 32);
-//         let h_offset: usize = (k - kp) as usize;
-//         let pi = h[i[j]][h_offset] + n[j];
-
-//         let re = cospi2[pi % 4];
-//         let im = sinpi2[pi % 4];
-
-//         assert!(k > -(768 + 255));
-//         let symbol_offset: usize = (k + 768 + 255) as usize;
-//         prs_syms[symbol_offset] = c64(re, im);
-//         prs_conj[symbol_offset] = c64(re, im).conj();
-
-//         if kp != kpl && k != 0 {
-//             kpl = kp;
-//             j += 1;
-//         }
-
-//         k += 1;
-//     }
-//     prs_syms[768 + 255] = c64(0, 0); /* explicitly set value for k = 0 */
-//     prs_conj[768 + 255] = c64(0, 0);
-
-//     (prs_syms, prs_conj)
-// }
+/// The Transmission Mode I phase reference symbol as it is transmitted,
+/// carrier k at FFT bin k (k > 0) or 2048 + k (k < 0), with unit magnitude.
+/// phi_k = pi/2 * (h[i][k - k'] + n), 14.3.2
+pub fn prs_carriers() -> PhaseReferenceArray {
+    let mut carriers = [c64(0, 0); PRS_POINTS];
+    for k in (-CARRIERS..=CARRIERS).filter(|k| *k != 0) {
+        // blocks of 32 start at k' = -768 below the centre, and k' = 1 above
+        let (block, kp) = if k < 0 {
+            let block = (k + CARRIERS) / 32;
+            (block, -CARRIERS + 32 * block)
+        } else {
+            let block = (k - 1) / 32;
+            (24 + block, 1 + 32 * block)
+        };
+        let block = block as usize;
+        let phase = (H[I[block]][(k - kp) as usize] + N[block]) % 4;
+        let carrier = match phase {
+            0 => c64(1, 0),
+            1 => c64(0, 1),
+            2 => c64(-1, 0),
+            _ => c64(0, -1),
+        };
+        carriers[k.rem_euclid(PRS_POINTS as i32) as usize] = carrier;
+    }
+    carriers
+}
 
 const PRS1_GPLOT: &str = include_str!("prs1.gplot");
 const PRS2_GPLOT: &str = include_str!("prs2.gplot");
@@ -1,13 +1,13 @@
 use rustfft::num_complex::Complex64;
 use rustfft::num_complex::c64;
 
-use crate::msc::MainServiceChannel;
 use crate::prs::PRS_POINTS;
 use crate::prs::PhaseReferenceArray;
 use crate::prs::PhaseReferenceSymbol;
 use crate::prs::fft::*;
 use crate::prs::maths::*;
 use crate::prs::reference::prs_reference_1_2;
+use crate::stats::SyncValues;
 use crate::wavefinder::Message;
 use crate::wavefinder::mem_write_msg;
 use crate::wavefinder::timing_msg;
@@ -27,6 +27,7 @@ pub struct PhaseReferenceSynchroniser {
     selstr: [u8; 10],
     count: i32,
     locked: &'static AtomicBool,
+    values: SyncValues,
 }
 
 pub fn new_synchroniser(locked: &'static AtomicBool) -> PhaseReferenceSynchroniser {
@@ -42,6 +43,7 @@ pub fn new_synchroniser(locked: &'static AtomicBool) -> PhaseReferenceSynchronis
         selstr: [0xff; 10],
         count: 0,
         locked,
+        values: SyncValues::default(),
     }
 }
 
@@ -65,15 +67,21 @@ fn align_reference_symbol(indx: i32, source: &PhaseReferenceArray) -> [Complex64
 }
 
 impl PhaseReferenceSynchroniser {
-    pub fn select_channel(&mut self, channel: &MainServiceChannel) {
+    // Symbols to ask for, from msc::merged_selstr
+    pub fn select(&mut self, selstr: [u8; 10]) {
         self.count = 6;
-        self.selstr = channel.selstr();
+        self.selstr = selstr;
     }
 
     pub fn count(&self) -> i32 {
         self.count
     }
 
+    // c and ir from the last symbol
+    pub fn values(&self) -> SyncValues {
+        self.values
+    }
+
     fn locked(&self) -> bool {
         self.locked.load(Ordering::Relaxed)
     }
@@ -94,6 +102,13 @@ impl PhaseReferenceSynchroniser {
         self.locked.store(false, Ordering::Relaxed);
     }
 
+    // After retuning, nothing measured so far applies
+    pub fn reset(&mut self) {
+        self.unlock();
+        self.ravg = new_raverage();
+        self.values = SyncValues::default();
+    }
+
     pub fn try_sync_prs(&mut self, prs: PhaseReferenceSymbol) -> Vec<Message> {
         let rdata = ifft(&prs.vector());
         let (c, prs2_offset) = self.calc_c(&rdata);
@@ -109,6 +124,11 @@ impl PhaseReferenceSynchroniser {
             // eprintln!("unlocked: {:12.10} {:.2}", c, ir);
             self.unlock();
         }
+        self.values = SyncValues {
+            c,
+            ir,
+            locked: self.locked(),
+        };
 
         let mut messages: Vec<Message> = Vec::new();
 
@@ -1,15 +1,34 @@
-use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
 use std::thread;
+use std::io;
+use std::path::Path;
 use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
-use crate::output::mpeg::{self};
+use crate::error::{Error, ErrorCounts, Result};
+use crate::fic::decoder::FastInformationChannelDecoder;
+use crate::fic::ensemble::{Acquisition, Announcement, AudioCodec, Ensemble, Service};
+use crate::output::record::{Recorder, new_recorder};
+use crate::output::{self, Audio, AudioOutput};
 use crate::{Cli, CliSource, ControlEvent, UiEvent};
+use crate::pad::PadData;
+use crate::source::Source;
+use crate::stats::ReceptionStats;
+use crate::wavefinder::Buffer;
 use crate::{ControlData, EventData, pad};
 use crate::{
     fic::{FastInformationChannelBuffer, ensemble::new_ensemble},
-    msc::new_channel,
+    msc::{MainServiceChannel, new_channel, new_subchannel_channel},
 };
 
+// Buffers only arrive while the source is synchronised, so a gap this long
+// means the ensemble has been lost
+const SYNC_TIMEOUT: Duration = Duration::from_secs(1);
+
+// How often the app is told about reception quality and data skipped as
+// corrupt
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
 pub struct DABReceiver {
     args: Cli,
 }
@@ -18,46 +37,71 @@ pub fn new_receiver(args: Cli) -> DABReceiver {
     DABReceiver { args }
 }
 
+// The source chosen on the command line, or why it can't be opened
+pub(crate) fn new_source(args: &Cli) -> io::Result<Box<dyn Source + Send + Sync>> {
+    Ok(match args.source {
+        CliSource::Wavefinder => {
+            crate::source::wavefinder::new_wavefinder_source(args.file.clone(), args.frequency)
+        }
+        CliSource::File => crate::source::file::new_file_source(args.file.clone())?,
+        CliSource::Iq => crate::source::iq::new_iq_source(args.file.clone(), args.iq_format)?,
+        CliSource::RtlTcp => crate::source::rtltcp::new_rtltcp_source(
+            args.address.clone(),
+            args.frequency,
+            args.gain,
+        )?,
+        CliSource::Eti => crate::source::eti::new_eti_source(args.file.clone())?,
+    })
+}
+
 impl DABReceiver {
-    pub fn run(&mut self) -> (Receiver<UiEvent>, Sender<ControlEvent>, JoinHandle<()>) {
-        let mut source = match self.args.source {
-            CliSource::Wavefinder => crate::source::wavefinder::new_wavefinder_source(
-                self.args.file.clone(),
-                self.args.frequency.clone(),
-            ),
-            CliSource::File => crate::source::file::new_file_source(self.args.file.clone()),
-        };
+    /// Start receiving, unless the source can't be opened
+    pub fn run(
+        &mut self,
+    ) -> io::Result<(Receiver<UiEvent>, Sender<ControlEvent>, JoinHandle<()>)> {
+        let mut source = new_source(&self.args)?;
 
         let (source_rx, source_t) = source.run();
 
         let (ui_tx, ui_rx) = mpsc::channel();
         let (control_tx, control_rx) = mpsc::channel();
+        if let Some(frequency) = source.frequency() {
+            ui_tx
+                .send(UiEvent {
+                    data: EventData::Frequency(frequency),
+                })
+                .expect("sending frequency to app");
+        }
 
         let mut fic_decoder = crate::fic::new_decoder();
         let mut ens = new_ensemble();
-        let service_id = self.args.service.clone();
+        let mut service_id = self.args.service.clone().unwrap_or_default();
+        let slides = self.args.slides.clone();
+        let record = self.args.record.clone();
+        let record_dir = self.args.record_dir.clone();
+        let fic_timeout = Duration::from_secs(self.args.fic_timeout);
+        let enabled = self
+            .args
+            .announcements
+            .iter()
+            .fold(0, |flags, a| flags | a.flag());
 
         let receiver_t = thread::spawn(move || {
-            // FIC
+            let mut errors = ErrorCounts::default();
+            let mut stats = ReceptionStats::default();
 
+            // FIC, until the configuration is complete or we give up waiting
+            let start = Instant::now();
             while let Ok(buffer) = source_rx.recv() {
                 if buffer.last {
                     break;
                 }
-                if let Ok(fic_buffer) = TryInto::<FastInformationChannelBuffer>::try_into(&buffer)
-                    && let Some(fibs) = fic_decoder.try_buffer(fic_buffer)
-                {
-                    for fib in fibs {
-                        let figs = fic_decoder.extract_figs(&fib);
-                        for fig in figs {
-                            ens.add_fig(fig);
-                        }
-                    }
-                    if ens.is_complete() {
-                        break;
-                    }
+                update_ensemble(&mut fic_decoder, &mut ens, &buffer, &mut stats, &mut errors);
+                if ens.acquisition() == Acquisition::Complete || start.elapsed() > fic_timeout {
+                    break;
                 }
             }
+            ens.take_changed();
 
             ui_tx
                 .send(UiEvent {
@@ -65,25 +109,68 @@ impl DABReceiver {
                 })
                 .expect("sending ensemble to app");
 
+            // The service may not have been signalled yet, or not its
+            // subchannel, keep following the FIC
+            let mut tuned = tune(&ens, &service_id, &mut errors);
+            while tuned.is_none() {
+                let Ok(buffer) = source_rx.recv() else {
+                    break;
+                };
+                if buffer.last {
+                    break;
+                }
+                match control_rx.try_recv() {
+                    Ok(ControlEvent {
+                        data: ControlData::Stop(),
+                    }) => {
+                        source.exit();
+                        break;
+                    }
+                    Ok(ControlEvent {
+                        data: ControlData::Select(id),
+                    }) => {
+                        service_id = format!("{:x}", id);
+                        tuned = tune(&ens, &service_id, &mut errors);
+                    }
+                    _ => {}
+                }
+                if update_ensemble(&mut fic_decoder, &mut ens, &buffer, &mut stats, &mut errors) {
+                    ui_tx
+                        .send(UiEvent {
+                            data: EventData::Ensemble(ens.clone()),
+                        })
+                        .expect("sending ensemble to app");
+                    tuned = tune(&ens, &service_id, &mut errors);
+                }
+            }
+
             // If service, MSC
-            if let Some(service) = ens.find_service_by_id_str(&service_id) {
-                let mut msc = new_channel(service);
-                source.as_mut().select_channel(&msc);
+            if let Some((mut selected, mut msc)) = tuned {
+                let mut recordings = recordings(&ens, &record, &record_dir, &mut errors);
+                source
+                    .as_mut()
+                    .select_channels(&channels(&msc, &recordings));
 
                 ui_tx
                     .send(UiEvent {
-                        data: EventData::Service(service.clone()),
+                        data: EventData::Service(selected.clone()),
                     })
                     .expect("sending service to app");
 
                 let mut pad = pad::new_padstate();
-                let mut mpeg = mpeg::new_mpeg();
-
-                'msc: while let Ok(buffer) = source_rx.recv() {
-                    if buffer.last {
-                        break;
-                    }
+                let mut audio = output::new_audio(&selected);
+                let mut codec = selected.subchannel().and_then(|s| s.audio_codec());
+                // set while listening to an announcement instead of the selected service
+                let mut announcement: Option<Announcement> = None;
+                let mut lost = false;
+                // set after tuning elsewhere, until the service is found there
+                let mut retuned = false;
+                let mut reported = ErrorCounts::default();
+                let mut last_report = Instant::now();
 
+                'msc: loop {
+                    // before waiting for a buffer, as the user may tune
+                    // elsewhere once reception is lost
                     if let Ok(msg) = control_rx.try_recv() {
                         match msg {
                             ControlEvent {
@@ -96,37 +183,450 @@ impl DABReceiver {
                                 data: ControlData::Select(service_id),
                             } => {
                                 if let Some(service) = ens.find_service_by_id(service_id) {
-                                    msc = new_channel(service);
-                                    source.as_mut().select_channel(&msc);
-                                    mpeg.deinit();
+                                    match retune(service, &mut msc, &mut audio, &mut codec) {
+                                        Ok(()) => {
+                                            selected = service.clone();
+                                            announcement = None;
+                                            source
+                                                .as_mut()
+                                                .select_channels(&channels(&msc, &recordings));
+                                        }
+                                        Err(e) => errors.count(&e),
+                                    }
                                 }
                             }
-                            _ => todo!(),
+                            ControlEvent {
+                                data: ControlData::Tune(frequency),
+                            } => {
+                                // another ensemble: acquire it afresh, then
+                                // follow the service there by its SId
+                                source.tune(frequency);
+                                fic_decoder = crate::fic::new_decoder();
+                                ens = new_ensemble();
+                                announcement = None;
+                                retuned = true;
+                            }
                         }
                     }
 
-                    if !source.as_ref().ready() {
-                        continue;
+                    let buffer = match source_rx.recv_timeout(SYNC_TIMEOUT) {
+                        Ok(buffer) => buffer,
+                        Err(RecvTimeoutError::Timeout) => {
+                            if !lost {
+                                lost = true;
+                                ui_tx
+                                    .send(UiEvent {
+                                        data: EventData::Alternatives(Some(ens.alternatives(&selected))),
+                                    })
+                                    .expect("sending alternatives to app");
+                            }
+                            continue;
+                        }
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    };
+                    if lost {
+                        lost = false;
+                        ui_tx
+                            .send(UiEvent {
+                                data: EventData::Alternatives(None),
+                            })
+                            .expect("sending alternatives to app");
+                    }
+                    if buffer.last {
+                        break;
                     }
 
-                    if let Some(main) = msc.try_buffer(&buffer) {
-                        if let Ok(dls) = pad.output(&main) {
-                            // && dls.is_new {
+                    // The FIC is still received alongside the MSC; follow it
+                    // for changes and announcement switching
+                    if buffer.eti.is_some()
+                        || TryInto::<FastInformationChannelBuffer>::try_into(&buffer).is_ok()
+                    {
+                        if update_ensemble(&mut fic_decoder, &mut ens, &buffer, &mut stats, &mut errors) {
                             ui_tx
                                 .send(UiEvent {
-                                    data: EventData::Label(dls.label),
+                                    data: EventData::Ensemble(ens.clone()),
                                 })
-                                .expect("sending DLS to app");
-                                // eprintln!("DLS: {}", label.label);
+                                .expect("sending ensemble to app");
+                        }
+
+                        // subchannels may have moved in a reconfiguration, or
+                        // the service be found after retuning, follow them
+                        let found = retuned
+                            && ens
+                                .find_service_by_id(selected.id)
+                                .is_some_and(|s| new_channel(s).is_ok());
+                        if ens.take_reconfigured() || found {
+                            retuned = false;
+                            if let Some(service) = ens.find_service_by_id(selected.id) {
+                                selected = service.clone();
                             }
-                        mpeg.output(&main);
+                            let current = match announcement {
+                                Some(a) => announcement_channel(&ens, &a),
+                                None => new_channel(&selected),
+                            };
+                            for (channel, _) in recordings.iter_mut() {
+                                if let Some(service) = ens.find_service_by_id(channel.service().id)
+                                    && service.is_audio()
+                                {
+                                    match new_channel(service) {
+                                        Ok(c) => *channel = c,
+                                        Err(e) => errors.count(&e),
+                                    }
+                                }
+                            }
+                            match current {
+                                Ok(channel) => {
+                                    switch_channel(channel, &mut msc, &mut audio, &mut codec)
+                                }
+                                Err(e) => errors.count(&e),
+                            }
+                            source
+                                .as_mut()
+                                .select_channels(&channels(&msc, &recordings));
+
+                            ui_tx
+                                .send(UiEvent {
+                                    data: EventData::Service(selected.clone()),
+                                })
+                                .expect("sending service to app");
+                        }
+
+                        let active = ens.announcement_for(&selected, enabled).copied();
+                        if active.map(|a| a.subchid) != announcement.map(|a| a.subchid) {
+                            let target = match active {
+                                Some(a) => announcement_channel(&ens, &a),
+                                None => new_channel(&selected),
+                            };
+                            // not retried until the announcement changes
+                            announcement = active;
+                            match target {
+                                Ok(channel) => {
+                                    switch_channel(channel, &mut msc, &mut audio, &mut codec);
+                                    source
+                                        .as_mut()
+                                        .select_channels(&channels(&msc, &recordings));
+                                    pad = pad::new_padstate();
+
+                                    ui_tx
+                                        .send(UiEvent {
+                                            data: EventData::Announcement(active),
+                                        })
+                                        .expect("sending announcement to app");
+                                }
+                                Err(e) => errors.count(&e),
+                            }
+                        }
+                    }
+
+                    if retuned || !source.as_ref().ready() {
+                        continue;
+                    }
+
+                    match msc.try_buffer(&buffer) {
+                        Ok(Some(main)) => {
+                            match audio.output(&main) {
+                                Ok(frames) => stats.audio(frames),
+                                Err(e) => errors.count(&e),
+                            }
+                            // PAD is at the end of an MP2 frame, and in the
+                            // access units of DAB+
+                            let pads = if codec == Some(AudioCodec::MP2) {
+                                vec![pad.output(&main)]
+                            } else {
+                                audio
+                                    .take_pad()
+                                    .iter()
+                                    .map(|field| pad.output_dse(field))
+                                    .collect()
+                            };
+                            for data in pads.into_iter().flat_map(|p| {
+                                p.unwrap_or_else(|e| {
+                                    errors.count(&e);
+                                    vec![]
+                                })
+                            }) {
+                                match data {
+                                    PadData::Label(dls) => {
+                                        ui_tx
+                                            .send(UiEvent {
+                                                data: EventData::Label(dls),
+                                            })
+                                            .expect("sending DLS to app");
+                                    }
+                                    PadData::Slide(slide) => {
+                                        if let Some(dir) = &slides
+                                            && let Err(e) = slide.write_to_dir(dir)
+                                        {
+                                            eprintln!("failed to write slide: {}", e);
+                                        }
+                                        ui_tx
+                                            .send(UiEvent {
+                                                data: EventData::Slide(slide),
+                                            })
+                                            .expect("sending slide to app");
+                                    }
+                                }
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(e) => errors.count(&e),
+                    }
+
+                    for (channel, recorder) in recordings.iter_mut() {
+                        if let Err(e) = channel
+                            .try_buffer(&buffer)
+                            .and_then(|main| main.map(|main| recorder.output(&main)).transpose())
+                        {
+                            errors.count(&e);
+                        }
+                    }
+
+                    if last_report.elapsed() >= REPORT_INTERVAL {
+                        last_report = Instant::now();
+                        stats.sync = source.as_ref().sync();
+                        ui_tx
+                            .send(UiEvent {
+                                data: EventData::Stats(std::mem::take(&mut stats)),
+                            })
+                            .expect("sending stats to app");
+                        if errors != reported {
+                            reported = errors;
+                            ui_tx
+                                .send(UiEvent {
+                                    data: EventData::Errors(errors),
+                                })
+                                .expect("sending errors to app");
+                        }
                     }
                 }
 
+                for (_, recorder) in recordings.iter_mut() {
+                    recorder.deinit();
+                }
                 source_t.join().unwrap();
             }
         });
 
-        (ui_rx, control_tx, receiver_t)
+        Ok((ui_rx, control_tx, receiver_t))
+    }
+}
+
+// Decode a FIC buffer into the ensemble, returning whether it changed.
+// Corrupt FIGs are counted and skipped.
+pub(crate) fn update_ensemble(
+    decoder: &mut FastInformationChannelDecoder,
+    ens: &mut Ensemble,
+    buffer: &Buffer,
+    stats: &mut ReceptionStats,
+    errors: &mut ErrorCounts,
+) -> bool {
+    let fibs = match &buffer.eti {
+        Some(eti) => Some(decoder.try_fibs(&eti.fic, eti.fct, stats)),
+        None => TryInto::<FastInformationChannelBuffer>::try_into(buffer)
+            .ok()
+            .and_then(|fic_buffer| decoder.try_buffer(fic_buffer, stats)),
+    };
+    if let Some(fibs) = fibs {
+        for fib in fibs {
+            for fig in decoder.extract_figs(&fib) {
+                match fig {
+                    Ok(fig) => ens.add_fig(fig),
+                    Err(e) => errors.count(&e),
+                }
+            }
+        }
+    }
+    ens.take_changed()
+}
+
+// The service to play and a channel for it, once its subchannel is known
+fn tune(
+    ens: &Ensemble,
+    service_id: &str,
+    errors: &mut ErrorCounts,
+) -> Option<(Service, MainServiceChannel)> {
+    let service = ens.find_service_by_id_str(service_id)?;
+    match new_channel(service) {
+        Ok(msc) => Some((service.clone(), msc)),
+        Err(e) => {
+            errors.count(&e);
+            None
+        }
+    }
+}
+
+// Switch decoding over to the service, leaving the current one playing if
+// its subchannel can't be decoded
+fn retune(
+    service: &Service,
+    msc: &mut MainServiceChannel,
+    audio: &mut Audio,
+    codec: &mut Option<AudioCodec>,
+) -> Result<()> {
+    switch_channel(new_channel(service)?, msc, audio, codec);
+    Ok(())
+}
+
+fn switch_channel(
+    channel: MainServiceChannel,
+    msc: &mut MainServiceChannel,
+    audio: &mut Audio,
+    codec: &mut Option<AudioCodec>,
+) {
+    *codec = channel
+        .service()
+        .audio_codec(channel.organisation().SubChId);
+    audio.switch(*codec);
+    *msc = channel;
+}
+
+// A channel for the subchannel FIG 0/19 switches to, which needn't be the
+// one the service carrying the announcement is usually heard on
+fn announcement_channel(ens: &Ensemble, announcement: &Announcement) -> Result<MainServiceChannel> {
+    let org = ens
+        .subchannels()
+        .into_iter()
+        .find(|org| org.SubChId == announcement.subchid)
+        .ok_or(Error::Fig("announcement subchannel not organised"))?;
+    let service = ens
+        .find_service_for_subchannel(org.SubChId)
+        .and_then(|id| ens.find_service_by_id(id))
+        .ok_or(Error::Fig("no service for the announcement subchannel"))?;
+    new_subchannel_channel(service, &org)
+}
+
+// A channel per service to record, each with its own file
+fn recordings(
+    ens: &Ensemble,
+    record: &[String],
+    dir: &Path,
+    errors: &mut ErrorCounts,
+) -> Vec<(MainServiceChannel, Recorder)> {
+    ens.services()
+        .into_iter()
+        .filter(|s| s.is_audio())
+        .filter(|s| {
+            record
+                .iter()
+                .any(|r| r == "all" || u32::from_str_radix(r, 16) == Ok(s.id))
+        })
+        .filter_map(|s| {
+            let channel = new_channel(s).map_err(|e| errors.count(&e)).ok()?;
+            match new_recorder(s, dir) {
+                Ok(recorder) => Some((channel, recorder)),
+                Err(e) => {
+                    eprintln!("failed to record {:04x}: {}", s.id, e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+// The channel playing and those being recorded
+fn channels<'a>(
+    msc: &'a MainServiceChannel,
+    recordings: &'a [(MainServiceChannel, Recorder)],
+) -> Vec<&'a MainServiceChannel> {
+    std::iter::once(msc)
+        .chain(recordings.iter().map(|(channel, _)| channel))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CliAnnouncement;
+    use crate::decode::crc16_ccitt;
+    use crate::eti::eti_frame;
+    use crate::fic::ensemble::{SubChannelOrganisation, new_subchannel_organisation};
+    use crate::fic::fig::Information;
+    use crate::source::eti::new_eti_source;
+    use std::fs;
+
+    const SERVICE: u32 = 0xc221;
+    const TRAFFIC: u32 = 0xc222;
+
+    // Three FIBs: the ensemble and its two services, their subchannels, and
+    // a traffic announcement in cluster 1 on the second service's subchannel
+    fn fic() -> Vec<u8> {
+        let figs: [&[u8]; 3] = [
+            &[
+                0x05, 0x00, 0xce, 0x15, 0x00, 0x00, // 0/0
+                0x0b, 0x02, 0xc2, 0x21, 0x01, 0x00, 0x06, 0xc2, 0x22, 0x01, 0x00, 0x0a, // 0/2
+            ],
+            &[
+                0x09, 0x01, 0x04, 0x00, 0x88, 0x48, 0x08, 0x48, 0x88, 0x48, // 0/1
+                0x07, 0x12, 0xc2, 0x21, 0x00, 0x02, 0x01, 0x01, // 0/18
+                0x05, 0x13, 0x01, 0x00, 0x02, 0x82, // 0/19
+            ],
+            &[],
+        ];
+        figs.iter()
+            .flat_map(|figs| {
+                let mut fib = [0xff; 32];
+                fib[..figs.len()].copy_from_slice(figs);
+                let crc = crc16_ccitt(&fib[..30]);
+                fib[30..].copy_from_slice(&crc.to_be_bytes());
+                fib
+            })
+            .collect()
+    }
+
+    // 3-A subchannels of 72 CUs, one after the other
+    fn subchannel(id: u8) -> SubChannelOrganisation {
+        new_subchannel_organisation(&Information::SubChannelLong {
+            SubChId: id,
+            StartAddr: (id as u16 - 1) * 72,
+            Opt: 0,
+            ProtLvl: 2,
+            SubChSz: 72,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn announcement_from_eti_capture() {
+        let path = std::env::temp_dir().join(format!("announcement-{}.eti", std::process::id()));
+        let fic = fic();
+        let streams = [1, 2].map(|id| (subchannel(id), vec![id; 288]));
+        let capture: Vec<u8> = (0..4)
+            .flat_map(|fct| eti_frame(fct, Some(&fic), &streams))
+            .collect();
+        fs::write(&path, capture).unwrap();
+
+        let mut source = new_eti_source(Some(path.clone())).unwrap();
+        let (source_rx, source_t) = source.run();
+        let mut decoder = crate::fic::new_decoder();
+        let mut ens = new_ensemble();
+        let mut stats = ReceptionStats::default();
+        let mut errors = ErrorCounts::default();
+        let mut buffers = vec![];
+        for buffer in source_rx {
+            if buffer.last {
+                break;
+            }
+            update_ensemble(&mut decoder, &mut ens, &buffer, &mut stats, &mut errors);
+            buffers.push(buffer);
+        }
+        source_t.join().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(buffers.len(), 4);
+        assert_eq!(errors.total(), 0);
+
+        let service = ens.find_service_by_id(SERVICE).unwrap();
+        assert_eq!(new_channel(service).unwrap().organisation().SubChId, 1);
+        let enabled = CliAnnouncement::Traffic.flag();
+        let announcement = ens.announcement_for(service, enabled).copied().unwrap();
+        assert_eq!(announcement.subchid, 2);
+        let news = CliAnnouncement::News.flag();
+        assert!(ens.announcement_for(service, news).is_none());
+
+        // the channel is for the signalled subchannel, and decodes its stream
+        let mut msc = announcement_channel(&ens, &announcement).unwrap();
+        assert_eq!(msc.service().id, TRAFFIC);
+        assert_eq!(msc.organisation().SubChId, 2);
+        let frame = msc.try_buffer(&buffers[0]).unwrap().unwrap();
+        assert!(frame.bits.iter().all(|b| *b == 2));
     }
 }
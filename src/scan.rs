@@ -0,0 +1,138 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::Cli;
+use crate::channels::{BAND_III, Channel};
+use crate::error::ErrorCounts;
+use crate::fic::ensemble::{Acquisition, Ensemble, new_ensemble};
+use crate::fic::new_decoder;
+use crate::receiver::update_ensemble;
+use crate::source::wavefinder::new_wavefinder_source;
+use crate::stats::ReceptionStats;
+use crate::wavefinder::Buffer;
+
+// Time for the synthesiser to settle after retuning, before buffers can be
+// trusted to come from the new channel
+const SETTLE: Duration = Duration::from_millis(200);
+
+// Buffers only arrive while synchronised, a gap this long means it was lost
+const SYNC_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// An ensemble found by a scan, and the services it carries
+pub struct Station {
+    pub channel: Channel,
+    pub eid: u16,
+    pub label: String,
+    pub services: Vec<(u32, String)>,
+}
+
+fn new_station(channel: Channel, ens: &Ensemble) -> Station {
+    Station {
+        channel,
+        eid: ens.id(),
+        label: ens.label().trim().to_owned(),
+        services: ens
+            .services()
+            .into_iter()
+            .map(|s| (s.id, s.label().trim().to_owned()))
+            .collect(),
+    }
+}
+
+pub struct Scanner {
+    args: Cli,
+}
+
+pub fn new_scanner(args: Cli) -> Scanner {
+    Scanner { args }
+}
+
+impl Scanner {
+    /// Step through the Band III channels, collecting the ensemble on each
+    /// one the Wavefinder synchronises to. Each channel's result is passed
+    /// to progress as it's scanned.
+    pub fn run<F>(&mut self, mut progress: F) -> Vec<Station>
+    where
+        F: FnMut(&Channel, Option<&Station>),
+    {
+        let mut source = new_wavefinder_source(None, Some(BAND_III[0].frequency));
+        let (source_rx, source_t) = source.run();
+        let lock_timeout = Duration::from_secs(self.args.lock_timeout);
+        let fic_timeout = Duration::from_secs(self.args.fic_timeout);
+
+        let mut stations = vec![];
+        for channel in BAND_III {
+            source.tune(channel.frequency);
+            thread::sleep(SETTLE);
+            while source_rx.try_recv().is_ok() {}
+
+            // Nothing arrives until the phase reference symbol is locked
+            let station = match source_rx.recv_timeout(lock_timeout) {
+                Ok(buffer) => {
+                    collect(&source_rx, buffer, fic_timeout).map(|ens| new_station(channel, &ens))
+                }
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            progress(&channel, station.as_ref());
+            stations.extend(station);
+        }
+
+        source.exit();
+        let _ = source_t.join();
+        stations
+    }
+}
+
+// Follow the FIC until the configuration is complete, giving up on the
+// timeout, or early if synchronisation is lost
+fn collect(source_rx: &Receiver<Buffer>, first: Buffer, fic_timeout: Duration) -> Option<Ensemble> {
+    let mut decoder = new_decoder();
+    let mut ens = new_ensemble();
+    let mut stats = ReceptionStats::default();
+    let mut errors = ErrorCounts::default();
+
+    let start = Instant::now();
+    let mut buffer = first;
+    loop {
+        update_ensemble(&mut decoder, &mut ens, &buffer, &mut stats, &mut errors);
+        if ens.acquisition() == Acquisition::Complete || start.elapsed() > fic_timeout {
+            break;
+        }
+        match source_rx.recv_timeout(SYNC_TIMEOUT) {
+            Ok(b) => buffer = b,
+            Err(_) => break,
+        }
+    }
+
+    if ens.acquisition() > Acquisition::Searching {
+        Some(ens)
+    } else {
+        None
+    }
+}
+
+/// Write the stations as tab separated lines, one per service
+pub fn write_stations(path: &Path, stations: &[Station]) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(file, "# channel\tMHz\tEId\tensemble\tSId\tservice")?;
+    for station in stations {
+        for (sid, label) in &station.services {
+            writeln!(
+                file,
+                "{}\t{:.3}\t{:04x}\t{}\t{:04x}\t{}",
+                station.channel.name,
+                station.channel.frequency,
+                station.eid,
+                station.label,
+                sid,
+                label
+            )?;
+        }
+    }
+    file.flush()
+}
@@ -0,0 +1,289 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::wavefinder::Buffer;
+
+/* Capture files start with a header:
+magic "DABC", version, header length, source, 3 reserved bytes, frequency
+in kHz, start time in microseconds since the Unix epoch, all big endian.
+Each buffer follows the microseconds since the start it arrived at.
+Captures written before there was a header are the buffers alone.
+Fields can be added to the end of the header without a new version, the
+version only changes with the records. */
+pub const MAGIC: &[u8; 4] = b"DABC";
+pub const VERSION: u16 = 1;
+const HEADER_SIZE: usize = 24;
+const TIMESTAMP_SIZE: usize = 8;
+
+/// What received the buffers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CaptureSource {
+    Wavefinder,
+    // demodulated from I/Q samples, from a recording or rtl_tcp
+    Iq,
+    // read from an ETI(NI) recording
+    Eti,
+    // written by a later version
+    Unknown(u8),
+}
+
+impl CaptureSource {
+    fn to_u8(self) -> u8 {
+        match self {
+            CaptureSource::Wavefinder => 1,
+            CaptureSource::Iq => 2,
+            CaptureSource::Eti => 3,
+            CaptureSource::Unknown(s) => s,
+        }
+    }
+
+    fn from_u8(s: u8) -> CaptureSource {
+        match s {
+            1 => CaptureSource::Wavefinder,
+            2 => CaptureSource::Iq,
+            3 => CaptureSource::Eti,
+            s => CaptureSource::Unknown(s),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CaptureHeader {
+    pub version: u16,
+    pub source: CaptureSource,
+    // MHz, as tuned when the capture started
+    pub frequency: f64,
+    pub start: SystemTime,
+}
+
+pub fn new_capture_header(source: CaptureSource, frequency: f64) -> CaptureHeader {
+    CaptureHeader {
+        version: VERSION,
+        source,
+        frequency,
+        start: SystemTime::now(),
+    }
+}
+
+impl CaptureHeader {
+    fn to_bytes(self) -> [u8; HEADER_SIZE] {
+        let start = self
+            .start
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+        let mut bytes = [0; HEADER_SIZE];
+        bytes[0..4].copy_from_slice(MAGIC);
+        bytes[4..6].copy_from_slice(&self.version.to_be_bytes());
+        bytes[6..8].copy_from_slice(&(HEADER_SIZE as u16).to_be_bytes());
+        bytes[8] = self.source.to_u8();
+        bytes[12..16].copy_from_slice(&((self.frequency * 1000.0).round() as u32).to_be_bytes());
+        bytes[16..24].copy_from_slice(&start.to_be_bytes());
+        bytes
+    }
+
+    // any fields after these are skipped
+    fn read_from(reader: &mut impl Read) -> io::Result<CaptureHeader> {
+        let mut bytes = [0; HEADER_SIZE];
+        reader.read_exact(&mut bytes)?;
+        let version = u16::from_be_bytes([bytes[4], bytes[5]]);
+        if version == 0 || version > VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("capture version {} isn't supported", version),
+            ));
+        }
+        let length = u16::from_be_bytes([bytes[6], bytes[7]]) as usize;
+        if length < HEADER_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "capture header too short",
+            ));
+        }
+        io::copy(
+            &mut reader.take((length - HEADER_SIZE) as u64),
+            &mut io::sink(),
+        )?;
+
+        let frequency = u32::from_be_bytes(bytes[12..16].try_into().unwrap());
+        let start = u64::from_be_bytes(bytes[16..24].try_into().unwrap());
+        Ok(CaptureHeader {
+            version,
+            source: CaptureSource::from_u8(bytes[8]),
+            frequency: frequency as f64 / 1000.0,
+            start: UNIX_EPOCH + Duration::from_micros(start),
+        })
+    }
+}
+
+/// Reads buffers back from a capture, or from a raw file of buffers
+pub struct CaptureReader {
+    reader: BufReader<File>,
+    // None for a raw file
+    header: Option<CaptureHeader>,
+}
+
+pub fn new_capture_reader(mut reader: BufReader<File>) -> io::Result<CaptureReader> {
+    // a raw file starts straight away with a buffer, whose fourth byte is
+    // a frame number below 32, never the 'C' of the magic
+    let header = if reader.fill_buf()?.starts_with(MAGIC) {
+        Some(CaptureHeader::read_from(&mut reader)?)
+    } else {
+        None
+    };
+    Ok(CaptureReader { reader, header })
+}
+
+impl CaptureReader {
+    pub fn header(&self) -> Option<&CaptureHeader> {
+        self.header.as_ref()
+    }
+
+    /// The next buffer, and when it arrived if the capture recorded that
+    pub fn read_buffer(&mut self) -> io::Result<(Option<Duration>, Buffer)> {
+        let time = match self.header {
+            Some(_) => {
+                let mut bytes = [0; TIMESTAMP_SIZE];
+                self.reader.read_exact(&mut bytes)?;
+                Some(Duration::from_micros(u64::from_be_bytes(bytes)))
+            }
+            None => None,
+        };
+        Ok((time, Buffer::read_from_file(&mut self.reader)?))
+    }
+}
+
+/// Writes buffers to a capture, timed from when it was created
+pub struct CaptureWriter {
+    writer: BufWriter<File>,
+    start: Instant,
+}
+
+pub fn new_capture_writer(
+    mut writer: BufWriter<File>,
+    header: &CaptureHeader,
+) -> io::Result<CaptureWriter> {
+    writer.write_all(&header.to_bytes())?;
+    Ok(CaptureWriter {
+        writer,
+        start: Instant::now(),
+    })
+}
+
+impl CaptureWriter {
+    pub fn write_buffer(&mut self, buffer: &Buffer) -> io::Result<()> {
+        let time = self.start.elapsed().as_micros() as u64;
+        self.writer.write_all(&time.to_be_bytes())?;
+        self.writer.write_all(&buffer.bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::file::new_file_source;
+    use std::{fs, path::PathBuf};
+
+    fn buffer(i: u8) -> Buffer {
+        let mut bytes = [0; 524];
+        bytes[2] = 2 + i % 75;
+        bytes[3] = i % 32;
+        bytes[100] = i;
+        Buffer {
+            bytes,
+            last: false,
+            eti: None,
+        }
+    }
+
+    fn path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("{}-{}.wf", name, std::process::id()))
+    }
+
+    fn reader(path: &PathBuf) -> io::Result<CaptureReader> {
+        new_capture_reader(BufReader::new(File::open(path)?))
+    }
+
+    #[test]
+    fn header_round_trip() {
+        let path = path("capture");
+        let header = new_capture_header(CaptureSource::Iq, 225.648);
+        let mut writer =
+            new_capture_writer(BufWriter::new(File::create(&path).unwrap()), &header).unwrap();
+        for i in 0..10 {
+            writer.write_buffer(&buffer(i)).unwrap();
+        }
+        drop(writer);
+
+        let mut capture = reader(&path).unwrap();
+        let read = *capture.header().unwrap();
+        assert_eq!(read.version, VERSION);
+        assert_eq!(read.source, CaptureSource::Iq);
+        assert_eq!(read.frequency, 225.648);
+        // to the microsecond
+        assert!(header.start.duration_since(read.start).unwrap() < Duration::from_micros(1));
+        let mut last = Duration::ZERO;
+        for i in 0..10 {
+            let (time, b) = capture.read_buffer().unwrap();
+            assert!(time.unwrap() >= last);
+            last = time.unwrap();
+            assert_eq!(b.bytes, buffer(i).bytes);
+        }
+        assert!(capture.read_buffer().is_err());
+
+        let source = new_file_source(Some(path.clone())).unwrap();
+        assert_eq!(source.frequency(), Some(225.648));
+
+        // a longer header from a later release is skipped, a newer version refused
+        let bytes = fs::read(&path).unwrap();
+        let mut longer = bytes[..HEADER_SIZE].to_vec();
+        longer[6..8].copy_from_slice(&(HEADER_SIZE as u16 + 6).to_be_bytes());
+        longer.extend([9; 6]);
+        longer.extend(&bytes[HEADER_SIZE..]);
+        fs::write(&path, longer).unwrap();
+        let (_, b) = reader(&path).unwrap().read_buffer().unwrap();
+        assert_eq!(b.bytes, buffer(0).bytes);
+
+        let mut newer = bytes;
+        newer[4..6].copy_from_slice(&(VERSION + 1).to_be_bytes());
+        fs::write(&path, newer).unwrap();
+        let e = reader(&path).err().unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn sources_round_trip() {
+        for source in [
+            CaptureSource::Wavefinder,
+            CaptureSource::Iq,
+            CaptureSource::Eti,
+            CaptureSource::Unknown(200),
+        ] {
+            assert_eq!(CaptureSource::from_u8(source.to_u8()), source);
+        }
+    }
+
+    #[test]
+    fn legacy_capture_without_header() {
+        let path = path("legacy");
+        let bytes: Vec<u8> = (0..10).flat_map(|i| buffer(i).bytes).collect();
+        fs::write(&path, bytes).unwrap();
+
+        let mut capture = reader(&path).unwrap();
+        assert!(capture.header().is_none());
+        for i in 0..10 {
+            let (time, b) = capture.read_buffer().unwrap();
+            assert!(time.is_none());
+            assert_eq!(b.bytes, buffer(i).bytes);
+        }
+        assert!(capture.read_buffer().is_err());
+
+        let source = new_file_source(Some(path.clone())).unwrap();
+        assert_eq!(source.frequency(), None);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,110 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Read},
+    path::PathBuf,
+    sync::{
+        Arc, Mutex,
+        mpsc::{self, Receiver},
+    },
+    thread::{self, JoinHandle},
+};
+
+use crate::{
+    eti::{FRAME_SIZE, parse_eti_frame},
+    msc::MainServiceChannel,
+    stats::SyncValues,
+    wavefinder::Buffer,
+};
+
+use super::{Source, open_file};
+
+/// A raw ETI(NI) recording, 6144 bytes a frame. The FIBs and subchannels
+/// in it are already decoded, so each frame is sent as one buffer without
+/// a symbol.
+pub struct EtiSource {
+    exit: Arc<Mutex<bool>>,
+    // taken by the thread reading it
+    file: Option<BufReader<File>>,
+}
+
+pub fn new_eti_source(path: Option<PathBuf>) -> io::Result<Box<dyn Source + Send + Sync>> {
+    let exit = Arc::new(Mutex::new(false));
+    let file = Some(open_file(path)?);
+    Ok(Box::new(EtiSource { exit, file }))
+}
+
+impl Source for EtiSource {
+    fn run(&mut self) -> (Receiver<Buffer>, JoinHandle<()>) {
+        let (source_tx, source_rx) = mpsc::channel();
+        let file = self.file.take();
+        let exit = self.exit.clone();
+        let source_t = thread::spawn(move || {
+            let mut bytes = vec![0; FRAME_SIZE];
+            // already read if run before
+            let Some(mut buf) = file else {
+                let _ = source_tx.send(Buffer {
+                    bytes: [0; 524],
+                    last: true,
+                    eti: None,
+                });
+                return;
+            };
+            loop {
+                if let Ok(e) = exit.lock()
+                    && *e
+                {
+                    break;
+                }
+                if buf.read_exact(&mut bytes).is_err() {
+                    let _ = source_tx.send(Buffer {
+                        bytes: [0; 524],
+                        last: true,
+                        eti: None,
+                    });
+                    break;
+                }
+                // frames failing their CRCs are skipped
+                let Some(frame) = parse_eti_frame(&bytes) else {
+                    continue;
+                };
+                let buffer = Buffer {
+                    bytes: [0; 524],
+                    last: false,
+                    eti: Some(Arc::new(frame)),
+                };
+                if source_tx.send(buffer).is_err() {
+                    break;
+                }
+            }
+        });
+        (source_rx, source_t)
+    }
+
+    fn select_channels(&mut self, _channels: &[&MainServiceChannel]) {
+        // every subchannel is in each frame
+    }
+
+    fn ready(&self) -> bool {
+        true
+    }
+
+    fn sync(&self) -> Option<SyncValues> {
+        // nothing was demodulated
+        None
+    }
+
+    fn tune(&mut self, _frequency: f64) {
+        // no-op for ETI source
+    }
+
+    fn frequency(&self) -> Option<f64> {
+        // ETI doesn't carry it
+        None
+    }
+
+    fn exit(&mut self) {
+        if let Ok(mut e) = self.exit.lock() {
+            *e = true;
+        }
+    }
+}
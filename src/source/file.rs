@@ -1,6 +1,5 @@
 use std::{
-    fs::File,
-    io::BufReader,
+    io,
     path::PathBuf,
     sync::{
         Arc, Mutex,
@@ -9,37 +8,48 @@ use std::{
     thread::{self, JoinHandle},
 };
 
-use crate::{msc::MainServiceChannel, wavefinder::Buffer};
+use crate::{msc::MainServiceChannel, stats::SyncValues, wavefinder::Buffer};
 
-use super::Source;
+use super::{
+    Source,
+    capture::{CaptureReader, new_capture_reader},
+    open_file,
+};
 
 pub struct FileSource {
     exit: Arc<Mutex<bool>>,
-    path: Option<PathBuf>,
+    // taken by the thread reading it
+    reader: Option<CaptureReader>,
+    // from the capture's header, raw files don't record it
+    frequency: Option<f64>,
 }
 
-pub fn new_file_source(path: Option<PathBuf>) -> Box<dyn Source + Send + Sync> {
+pub fn new_file_source(path: Option<PathBuf>) -> io::Result<Box<dyn Source + Send + Sync>> {
     let exit = Arc::new(Mutex::new(false));
-    Box::new(FileSource { exit, path })
+    let reader = new_capture_reader(open_file(path)?)?;
+    let frequency = reader.header().map(|h| h.frequency);
+    Ok(Box::new(FileSource {
+        exit,
+        reader: Some(reader),
+        frequency,
+    }))
 }
 
 impl Source for FileSource {
     fn run(&mut self) -> (Receiver<Buffer>, JoinHandle<()>) {
         let (source_tx, source_rx) = mpsc::channel();
-        let path = self.path.clone();
+        let reader = self.reader.take();
         let exit = self.exit.clone();
         let source_t = thread::spawn(move || {
-            let mut buf;
-            if let Some(p) = path {
-                let file = File::open(&p);
-                if let Ok(f) = file {
-                    buf = BufReader::new(f);
-                } else {
-                    panic!("file couldn't be opened {:?}", p);
-                }
-            } else {
-                panic!("no file specified");
-            }
+            // already read if run before
+            let Some(mut reader) = reader else {
+                let _ = source_tx.send(Buffer {
+                    bytes: [0; 524],
+                    last: true,
+                    eti: None,
+                });
+                return;
+            };
 
             loop {
                 if let Ok(e) = exit.lock()
@@ -47,12 +57,13 @@ impl Source for FileSource {
                 {
                     break;
                 }
-                let result = Buffer::read_from_file(&mut buf);
-                let Ok(buffer) = result else {
+                let result = reader.read_buffer();
+                let Ok((_, buffer)) = result else {
                     source_tx
                         .send(Buffer {
                             bytes: [0; 524],
                             last: true,
+                            eti: None,
                         })
                         .unwrap();
                     break;
@@ -63,8 +74,7 @@ impl Source for FileSource {
         (source_rx, source_t)
     }
 
-    fn select_channel(&mut self, channel: &MainServiceChannel) {
-        dbg!(channel);
+    fn select_channels(&mut self, _channels: &[&MainServiceChannel]) {
         // no-op for file source
     }
 
@@ -73,6 +83,19 @@ impl Source for FileSource {
         true
     }
 
+    fn sync(&self) -> Option<SyncValues> {
+        // recorded buffers were already synchronised
+        None
+    }
+
+    fn tune(&mut self, _frequency: f64) {
+        // no-op for file source
+    }
+
+    fn frequency(&self) -> Option<f64> {
+        self.frequency
+    }
+
     fn exit(&mut self) {
         if let Ok(mut e) = self.exit.lock() {
             *e = true;
@@ -0,0 +1,169 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Read},
+    path::PathBuf,
+    sync::{
+        Arc, Mutex,
+        mpsc::{self, Receiver},
+    },
+    thread::{self, JoinHandle},
+};
+
+use rustfft::num_complex::{Complex64, c64};
+
+use crate::{
+    msc::MainServiceChannel,
+    ofdm::{SAMPLE_RATE, new_demodulator},
+    stats::SyncValues,
+    wavefinder::Buffer,
+};
+
+use super::{Source, open_file};
+
+/// How I/Q samples are stored, interleaved I then Q
+#[derive(Debug, Clone, Copy, clap::ValueEnum, PartialEq)]
+pub enum IqFormat {
+    // unsigned 8 bit, as from an RTL-SDR
+    Cu8,
+    // signed 16 bit little endian
+    Cs16,
+    // 32 bit float little endian
+    Cf32,
+}
+
+impl IqFormat {
+    pub fn sample_size(&self) -> usize {
+        match self {
+            IqFormat::Cu8 => 2,
+            IqFormat::Cs16 => 4,
+            IqFormat::Cf32 => 8,
+        }
+    }
+
+    /// The samples in bytes, scaled to about +/-1. Any incomplete sample at
+    /// the end is ignored.
+    pub fn samples(&self, bytes: &[u8]) -> Vec<Complex64> {
+        bytes
+            .chunks_exact(self.sample_size())
+            .map(|s| match self {
+                IqFormat::Cu8 => c64((s[0] as f64 - 127.5) / 127.5, (s[1] as f64 - 127.5) / 127.5),
+                IqFormat::Cs16 => c64(
+                    i16::from_le_bytes([s[0], s[1]]) as f64 / 32768.0,
+                    i16::from_le_bytes([s[2], s[3]]) as f64 / 32768.0,
+                ),
+                IqFormat::Cf32 => c64(
+                    f32::from_le_bytes([s[0], s[1], s[2], s[3]]) as f64,
+                    f32::from_le_bytes([s[4], s[5], s[6], s[7]]) as f64,
+                ),
+            })
+            .collect()
+    }
+}
+
+// Samples read at a time, 0.1s
+const CHUNK: usize = SAMPLE_RATE as usize / 10;
+
+/// A recording of complex baseband samples at 2.048 Msps, demodulated in
+/// software
+pub struct IqSource {
+    exit: Arc<Mutex<bool>>,
+    // taken by the thread reading it
+    file: Option<BufReader<File>>,
+    format: IqFormat,
+    values: Arc<Mutex<Option<SyncValues>>>,
+}
+
+pub fn new_iq_source(
+    path: Option<PathBuf>,
+    format: IqFormat,
+) -> io::Result<Box<dyn Source + Send + Sync>> {
+    let exit = Arc::new(Mutex::new(false));
+    Ok(Box::new(IqSource {
+        exit,
+        file: Some(open_file(path)?),
+        format,
+        values: Arc::new(Mutex::new(None)),
+    }))
+}
+
+impl Source for IqSource {
+    fn run(&mut self) -> (Receiver<Buffer>, JoinHandle<()>) {
+        let (source_tx, source_rx) = mpsc::channel();
+        let file = self.file.take();
+        let format = self.format;
+        let exit = self.exit.clone();
+        let values = self.values.clone();
+        let source_t = thread::spawn(move || {
+            // already read if run before
+            let Some(mut file) = file else {
+                let _ = source_tx.send(Buffer {
+                    bytes: [0; 524],
+                    last: true,
+                    eti: None,
+                });
+                return;
+            };
+
+            let mut demodulator = new_demodulator();
+            let mut bytes = Vec::with_capacity(CHUNK * format.sample_size());
+            loop {
+                if let Ok(e) = exit.lock()
+                    && *e
+                {
+                    break;
+                }
+                bytes.clear();
+                let result = (&mut file)
+                    .take((CHUNK * format.sample_size()) as u64)
+                    .read_to_end(&mut bytes);
+                if matches!(result, Ok(0) | Err(_)) {
+                    let _ = source_tx.send(Buffer {
+                        bytes: [0; 524],
+                        last: true,
+                        eti: None,
+                    });
+                    break;
+                }
+
+                let buffers = demodulator.push(&format.samples(&bytes));
+                if let Ok(mut v) = values.lock() {
+                    *v = Some(demodulator.values());
+                }
+                for buffer in buffers {
+                    if source_tx.send(buffer).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        (source_rx, source_t)
+    }
+
+    fn select_channels(&mut self, _channels: &[&MainServiceChannel]) {
+        // every symbol is demodulated
+    }
+
+    fn ready(&self) -> bool {
+        // symbols are only sent while synchronised
+        true
+    }
+
+    fn sync(&self) -> Option<SyncValues> {
+        self.values.lock().ok().and_then(|v| *v)
+    }
+
+    fn tune(&mut self, _frequency: f64) {
+        // no-op for a recording
+    }
+
+    fn frequency(&self) -> Option<f64> {
+        // not recorded with the samples
+        None
+    }
+
+    fn exit(&mut self) {
+        if let Ok(mut e) = self.exit.lock() {
+            *e = true;
+        }
+    }
+}
@@ -1,13 +1,57 @@
-use std::{sync::mpsc::Receiver, thread::JoinHandle};
+use std::{
+    fs::File,
+    io::{self, BufReader},
+    path::PathBuf,
+    sync::mpsc::Receiver,
+    thread::JoinHandle,
+};
 
-use crate::{msc::MainServiceChannel, wavefinder::Buffer};
+use crate::{msc::MainServiceChannel, stats::SyncValues, wavefinder::Buffer};
 
+pub mod capture;
+pub mod eti;
 pub mod file;
+pub mod iq;
+pub mod rtltcp;
 pub mod wavefinder;
 
 pub trait Source {
     fn exit(&mut self);
     fn run(&mut self) -> (Receiver<Buffer>, JoinHandle<()>);
     fn ready(&self) -> bool;
-    fn select_channel(&mut self, channel: &MainServiceChannel);
+    // every channel being decoded, their symbols are merged
+    fn select_channels(&mut self, channels: &[&MainServiceChannel]);
+    // the latest phase reference measurements, where the source has them
+    fn sync(&self) -> Option<SyncValues>;
+    // retune to another frequency in MHz, losing synchronisation
+    fn tune(&mut self, frequency: f64);
+    // the frequency in MHz being received, or a capture was made on, if known
+    fn frequency(&self) -> Option<f64>;
+}
+
+// The recording given with --file, opened up front so a missing or
+// unreadable one is reported before anything starts
+fn open_file(path: Option<PathBuf>) -> io::Result<BufReader<File>> {
+    let path =
+        path.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no --file given"))?;
+    File::open(&path)
+        .map(BufReader::new)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_recordings_are_reported() {
+        let path = std::env::temp_dir().join("no such recording.eti");
+        let e = eti::new_eti_source(Some(path.clone())).err().unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::NotFound);
+        assert!(e.to_string().contains("no such recording.eti"));
+
+        let e = file::new_file_source(None).err().unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+        assert!(iq::new_iq_source(Some(path), iq::IqFormat::Cu8).is_err());
+    }
 }
@@ -0,0 +1,240 @@
+use std::{
+    io::{self, Read, Write},
+    net::{Shutdown, TcpStream},
+    sync::{
+        Arc, Mutex,
+        mpsc::{self, Receiver, Sender},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    msc::MainServiceChannel,
+    ofdm::{SAMPLE_RATE, new_demodulator},
+    source::iq::IqFormat,
+    stats::SyncValues,
+    wavefinder::Buffer,
+};
+
+use super::{Source, wavefinder::DEFAULT_FREQUENCY};
+
+/* rtl_tcp commands, a byte then a big endian parameter */
+pub const SET_FREQUENCY: u8 = 0x01;
+pub const SET_SAMPLE_RATE: u8 = 0x02;
+// 0 automatic, 1 manual
+pub const SET_GAIN_MODE: u8 = 0x03;
+// in tenths of a dB
+pub const SET_GAIN: u8 = 0x04;
+
+/// rtl_tcp sends this first, then the tuner type and number of gains
+pub const MAGIC: &[u8; 4] = b"RTL0";
+
+// Bytes read at a time, 0.1s of unsigned 8 bit samples
+const CHUNK: usize = SAMPLE_RATE as usize / 10 * 2;
+
+// What replay claims to be, an R820T with its 29 gains
+const TUNER: u32 = 5;
+const GAINS: u32 = 29;
+
+// Bytes replayed at a time, 10ms
+const REPLAY_CHUNK: usize = SAMPLE_RATE as usize / 100 * 2;
+
+pub fn command(stream: &mut impl Write, cmd: u8, param: u32) -> io::Result<()> {
+    let mut bytes = [cmd, 0, 0, 0, 0];
+    bytes[1..].copy_from_slice(&param.to_be_bytes());
+    stream.write_all(&bytes)
+}
+
+fn set_frequency(stream: &mut impl Write, frequency: f64) -> io::Result<()> {
+    command(stream, SET_FREQUENCY, (frequency * 1e6).round() as u32)
+}
+
+/// A stand-in rtl_tcp server, for the rtl-tcp source without a dongle:
+/// sends unsigned 8 bit I/Q samples at 2.048 Msps to a connected client at
+/// the rate a dongle would, passing on each command and its parameter.
+/// Returns the number of samples sent before the samples or the client ran
+/// out.
+pub fn replay(
+    mut stream: TcpStream,
+    mut samples: impl Read,
+    commands: Sender<(u8, u32)>,
+) -> io::Result<usize> {
+    let mut header = [0; 12];
+    header[0..4].copy_from_slice(MAGIC);
+    header[4..8].copy_from_slice(&TUNER.to_be_bytes());
+    header[8..12].copy_from_slice(&GAINS.to_be_bytes());
+    stream.write_all(&header)?;
+
+    let mut control = stream.try_clone()?;
+    thread::spawn(move || {
+        let mut cmd = [0; 5];
+        while control.read_exact(&mut cmd).is_ok() {
+            let param = u32::from_be_bytes([cmd[1], cmd[2], cmd[3], cmd[4]]);
+            if commands.send((cmd[0], param)).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut bytes = vec![0; REPLAY_CHUNK];
+    let start = Instant::now();
+    let mut sent = 0;
+    loop {
+        let n = samples.read(&mut bytes)?;
+        if n == 0 || stream.write_all(&bytes[..n]).is_err() {
+            break;
+        }
+        sent += n;
+        let due = Duration::from_secs_f64(sent as f64 / 2.0 / SAMPLE_RATE);
+        if let Some(wait) = due.checked_sub(start.elapsed()) {
+            thread::sleep(wait);
+        }
+    }
+    // the command thread's clone would otherwise keep the connection open
+    let _ = stream.shutdown(Shutdown::Both);
+    Ok(sent / 2)
+}
+
+/// I/Q samples streamed by an rtl_tcp server, demodulated in software
+pub struct RtlTcpSource {
+    exit: Arc<Mutex<bool>>,
+    // taken by the thread reading it
+    stream: Option<TcpStream>,
+    freq: f64,
+    values: Arc<Mutex<Option<SyncValues>>>,
+    tune_tx: Option<Sender<f64>>,
+}
+
+/// Connect to the server and set it up, or say why it can't be used
+pub fn new_rtltcp_source(
+    address: String,
+    freq: Option<f64>,
+    gain: Option<f64>,
+) -> io::Result<Box<dyn Source + Send + Sync>> {
+    let freq = freq.unwrap_or(DEFAULT_FREQUENCY);
+    let mut stream = TcpStream::connect(&address).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("couldn't connect to rtl_tcp at {}: {}", address, e),
+        )
+    })?;
+    let mut header = [0; 12];
+    if stream.read_exact(&mut header).is_err() || &header[0..4] != MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} isn't an rtl_tcp server", address),
+        ));
+    }
+
+    command(&mut stream, SET_SAMPLE_RATE, SAMPLE_RATE as u32)
+        .and_then(|_| match gain {
+            Some(g) => command(&mut stream, SET_GAIN_MODE, 1)
+                .and_then(|_| command(&mut stream, SET_GAIN, (g * 10.0).round() as u32)),
+            None => command(&mut stream, SET_GAIN_MODE, 0),
+        })
+        .and_then(|_| set_frequency(&mut stream, freq))
+        .map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("couldn't configure rtl_tcp at {}: {}", address, e),
+            )
+        })?;
+
+    Ok(Box::new(RtlTcpSource {
+        exit: Arc::new(Mutex::new(false)),
+        stream: Some(stream),
+        freq,
+        values: Arc::new(Mutex::new(None)),
+        tune_tx: None,
+    }))
+}
+
+impl Source for RtlTcpSource {
+    fn run(&mut self) -> (Receiver<Buffer>, JoinHandle<()>) {
+        let (source_tx, source_rx) = mpsc::channel();
+        let (tune_tx, tune_rx) = mpsc::channel();
+        self.tune_tx = Some(tune_tx);
+
+        let stream = self.stream.take();
+        let exit = self.exit.clone();
+        let values = self.values.clone();
+        let source_t = thread::spawn(move || {
+            // already read if run before
+            let Some(mut stream) = stream else {
+                let _ = source_tx.send(Buffer {
+                    bytes: [0; 524],
+                    last: true,
+                    eti: None,
+                });
+                return;
+            };
+
+            let mut demodulator = new_demodulator();
+            let mut bytes = vec![0; CHUNK];
+            loop {
+                if let Ok(e) = exit.lock()
+                    && *e
+                {
+                    break;
+                }
+                while let Ok(f) = tune_rx.try_recv() {
+                    if set_frequency(&mut stream, f).is_ok() {
+                        // start synchronising again on the new ensemble
+                        demodulator.reset();
+                    }
+                }
+
+                if stream.read_exact(&mut bytes).is_err() {
+                    let _ = source_tx.send(Buffer {
+                        bytes: [0; 524],
+                        last: true,
+                        eti: None,
+                    });
+                    break;
+                }
+
+                let buffers = demodulator.push(&IqFormat::Cu8.samples(&bytes));
+                if let Ok(mut v) = values.lock() {
+                    *v = Some(demodulator.values());
+                }
+                for buffer in buffers {
+                    if source_tx.send(buffer).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        (source_rx, source_t)
+    }
+
+    fn select_channels(&mut self, _channels: &[&MainServiceChannel]) {
+        // every symbol is demodulated
+    }
+
+    fn ready(&self) -> bool {
+        // symbols are only sent while synchronised
+        true
+    }
+
+    fn sync(&self) -> Option<SyncValues> {
+        self.values.lock().ok().and_then(|v| *v)
+    }
+
+    fn tune(&mut self, frequency: f64) {
+        if let Some(tune_tx) = &self.tune_tx {
+            let _ = tune_tx.send(frequency);
+        }
+        self.freq = frequency;
+    }
+
+    fn frequency(&self) -> Option<f64> {
+        Some(self.freq)
+    }
+
+    fn exit(&mut self) {
+        if let Ok(mut e) = self.exit.lock() {
+            *e = true;
+        }
+    }
+}
@@ -3,37 +3,44 @@ use std::fs::File;
 use std::io::BufWriter;
 use std::path::PathBuf;
 use std::sync::atomic::AtomicBool;
-use std::sync::mpsc::{self, Receiver};
+use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::{Arc, Mutex};
 use std::thread::{self, JoinHandle};
 
-use crate::msc::MainServiceChannel;
+use crate::msc::{self, MainServiceChannel};
 use crate::prs;
 use crate::prs::sync::{PhaseReferenceSynchroniser, new_synchroniser};
+use crate::stats::SyncValues;
 use crate::wavefinder;
 use crate::wavefinder::{Buffer, Wavefinder};
 
 use super::Source;
+use super::capture::{CaptureSource, new_capture_header, new_capture_writer};
 
 static LOCKED: AtomicBool = AtomicBool::new(false);
 
+// 12B, BBC National DAB
+pub(crate) const DEFAULT_FREQUENCY: f64 = 225.648;
+
 pub struct WavefinderSource {
     exit: Arc<Mutex<bool>>,
     path: Option<PathBuf>,
-    freq: String,
+    freq: f64,
     sync: Option<Arc<Mutex<PhaseReferenceSynchroniser>>>,
+    tune_tx: Option<Sender<f64>>,
 }
 
 pub fn new_wavefinder_source(
     path: Option<PathBuf>,
-    freq: Option<String>,
+    freq: Option<f64>,
 ) -> Box<dyn Source + Send + Sync> {
     let exit = Arc::new(Mutex::new(false));
     Box::new(WavefinderSource {
         exit,
         path,
-        freq: freq.unwrap_or("225.648".to_owned()),
+        freq: freq.unwrap_or(DEFAULT_FREQUENCY),
         sync: None,
+        tune_tx: None,
     })
 }
 
@@ -47,29 +54,49 @@ impl Source for WavefinderSource {
         false
     }
 
+    fn sync(&self) -> Option<SyncValues> {
+        let sync = self.sync.as_ref()?;
+        sync.lock().ok().map(|s| s.values())
+    }
+
+    fn tune(&mut self, frequency: f64) {
+        if let Some(tune_tx) = &self.tune_tx {
+            let _ = tune_tx.send(frequency);
+        }
+        self.freq = frequency;
+    }
+
+    fn frequency(&self) -> Option<f64> {
+        Some(self.freq)
+    }
+
     fn exit(&mut self) {
         if let Ok(mut e) = self.exit.lock() {
             *e = true;
         }
     }
 
-    fn select_channel(&mut self, channel: &MainServiceChannel) {
-        // dbg!(channel);
+    fn select_channels(&mut self, channels: &[&MainServiceChannel]) {
+        // dbg!(channels);
 
         if let Some(sync) = &self.sync
             && let Ok(mut s) = sync.lock()
         {
-            s.select_channel(channel);
+            s.select(msc::merged_selstr(channels));
         }
     }
 
     fn run(&mut self) -> (Receiver<Buffer>, JoinHandle<()>) {
         let file_output = self.path.is_some();
         let path = self.path.clone();
-        let freq = self.freq.clone();
+        let freq = self.freq;
 
         let sync = Arc::new(Mutex::new(new_synchroniser(&LOCKED)));
         self.sync = Some(sync.clone());
+        let tune_sync = sync.clone();
+
+        let (tune_tx, tune_rx) = mpsc::channel();
+        self.tune_tx = Some(tune_tx);
 
         let exit = self.exit.clone();
 
@@ -111,13 +138,14 @@ impl Source for WavefinderSource {
                 thread::spawn(move || {
                     if let Some(p) = path {
                         let f = File::create(p).expect("Unable to create file");
-                        let mut buf = BufWriter::new(f);
-
-                        loop {
-                            let result = file_rx.recv();
-                            if let Ok(buffer) = result {
-                                buffer.write_to_file(&mut buf);
-                            }
+                        let header = new_capture_header(CaptureSource::Wavefinder, freq);
+                        let mut capture = new_capture_writer(BufWriter::new(f), &header)
+                            .expect("failed to write to file");
+
+                        while let Ok(buffer) = file_rx.recv() {
+                            capture
+                                .write_buffer(&buffer)
+                                .expect("failed to write to file");
                         }
                     }
                 });
@@ -132,7 +160,7 @@ impl Source for WavefinderSource {
                 }
 
                 if LOCKED.load(std::sync::atomic::Ordering::Relaxed) {
-                    source_tx.send(buffer).unwrap();
+                    source_tx.send(buffer.clone()).unwrap();
 
                     // File writer
                     if file_output {
@@ -143,11 +171,7 @@ impl Source for WavefinderSource {
 
             w.set_callback(cb);
 
-            if let Ok(f) = freq.parse::<f64>() {
-                w.init(f); // BBC National DAB
-            } else {
-                panic!("bad frequency: {}", freq);
-            }
+            w.init(freq);
 
             w.read();
 
@@ -162,6 +186,13 @@ impl Source for WavefinderSource {
                 while let Ok(m) = message_rx.try_recv() {
                     w.send_ctrl_message(&m);
                 }
+                while let Ok(f) = tune_rx.try_recv() {
+                    w.tune(f);
+                    // start synchronising again on the new ensemble
+                    if let Ok(mut s) = tune_sync.lock() {
+                        s.reset();
+                    }
+                }
             }
         });
 
@@ -0,0 +1,69 @@
+/// How well the ensemble is being received, counted by each stage of the
+/// decoder since the last report.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ReceptionStats {
+    // FIBs passing and failing their CRC
+    pub fibs_ok: u32,
+    pub fibs_failed: u32,
+    // FIC bits received, and those differing from the re-encoded Viterbi output
+    pub coded_bits: u32,
+    pub bit_errors: u32,
+    // MP2 frames or DAB+ access units, and those that couldn't be decoded
+    pub audio_frames: u32,
+    pub audio_errors: u32,
+    pub sync: Option<SyncValues>,
+}
+
+/// The phase reference symbol measurements behind synchronisation: the
+/// timing offset and impulse response peak offset. The software demodulator
+/// gives the frequency offset in Hz and its timing correction in samples.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct SyncValues {
+    pub c: f64,
+    pub ir: f64,
+    pub locked: bool,
+}
+
+impl ReceptionStats {
+    pub fn fib(&mut self, ok: bool) {
+        if ok {
+            self.fibs_ok += 1;
+        } else {
+            self.fibs_failed += 1;
+        }
+    }
+
+    pub fn audio(&mut self, frames: AudioFrames) {
+        self.audio_frames += frames.decoded + frames.failed;
+        self.audio_errors += frames.failed;
+    }
+
+    /// Fraction of FIBs failing their CRC
+    pub fn fib_error_rate(&self) -> Option<f64> {
+        rate(self.fibs_failed, self.fibs_ok + self.fibs_failed)
+    }
+
+    /// Channel bit error rate, before error correction
+    pub fn bit_error_rate(&self) -> Option<f64> {
+        rate(self.bit_errors, self.coded_bits)
+    }
+
+    pub fn audio_error_rate(&self) -> Option<f64> {
+        rate(self.audio_errors, self.audio_frames)
+    }
+}
+
+fn rate(count: u32, total: u32) -> Option<f64> {
+    if total == 0 {
+        None
+    } else {
+        Some(count as f64 / total as f64)
+    }
+}
+
+/// Audio frames handled by an output
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct AudioFrames {
+    pub decoded: u32,
+    pub failed: u32,
+}
@@ -8,23 +8,29 @@ mod bindings {
 
 pub use bindings::*;
 pub use message::*;
+pub use tune::tunable;
 
 use std::{
     fs::File,
     io::{self, BufReader, BufWriter, Read, Write},
+    sync::Arc,
     thread,
     time::Duration,
 };
 
+use crate::eti::EtiFrame;
+
 #[derive(Debug)]
 pub struct Wavefinder {
     device: *mut wf_device,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 pub struct Buffer {
     pub bytes: [u8; 524],
     pub last: bool,
+    // already decoded by the source, with no symbol in the bytes
+    pub eti: Option<Arc<EtiFrame>>,
 }
 
 impl Buffer {
@@ -39,6 +45,7 @@ impl Buffer {
         Ok(Buffer {
             bytes: buffer,
             last: false,
+            eti: None,
         })
     }
 }
@@ -67,7 +74,11 @@ unsafe extern "C" fn call_closure<F>(
     let callback = unsafe { &mut *callback_ptr };
     let slice = unsafe { std::slice::from_raw_parts(buf, len) };
     if let Ok(bytes) = slice.try_into() {
-        callback(Buffer { bytes, last: false });
+        callback(Buffer {
+            bytes,
+            last: false,
+            eti: None,
+        });
     } else {
         println!("short read? len = {:?}", len);
     }
@@ -1,5 +1,8 @@
 use super::Wavefinder;
 
+/* Minimum Band III frequency (MHz) */
+const MINFREQIII: f64 = 174.0;
+
 /* Maximum Band III frequency (MHz) */
 const MAXFREQIII: f64 = 240.0;
 
@@ -50,6 +53,19 @@ fn reverse_bits(op: u32, len: usize) -> u32 {
     j
 }
 
+/*
+** L Band is tuned by the Band III PLL at an offset, so either way the
+** frequency it's given has to be within Band III
+*/
+pub const fn tunable(freq: f64) -> bool {
+    let offset_freq = if freq > MAXFREQIII {
+        freq - LBANDOFFSET
+    } else {
+        freq
+    };
+    offset_freq >= MINFREQIII && offset_freq <= MAXFREQIII
+}
+
 impl Wavefinder {
     pub fn tune(&self, freq: f64) {
         let lband;
@@ -0,0 +1,120 @@
+use std::f64::consts::PI;
+use std::io::{ErrorKind, Write};
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use rustfft::FftPlanner;
+use rustfft::num_complex::{Complex64, c64};
+
+use dab::prs::reference::prs_carriers;
+use dab::source::rtltcp::{
+    SET_FREQUENCY, SET_GAIN, SET_GAIN_MODE, SET_SAMPLE_RATE, new_rtltcp_source, replay,
+};
+
+const T_U: usize = 2048;
+const GUARD: usize = 504;
+const T_NULL: usize = 2656;
+const L: usize = 76;
+
+// Transmission Mode I frames of pseudo-random symbols, as unsigned 8 bit
+// samples
+fn recording(frames: usize) -> Vec<u8> {
+    let ifft = FftPlanner::<f64>::new().plan_fft_inverse(T_U);
+    let bins = (-768..=768i32)
+        .filter(|k| *k != 0)
+        .map(|k| k.rem_euclid(T_U as i32) as usize)
+        .collect::<Vec<_>>();
+    let mut seed: u32 = 1;
+    let mut phase = move || {
+        seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+        PI / 4.0 + (seed >> 16) as f64 % 4.0 * PI / 2.0
+    };
+
+    let mut signal = vec![];
+    for _ in 0..frames {
+        signal.extend(std::iter::repeat_n(c64(0, 0), T_NULL));
+        let mut z = prs_carriers();
+        for l in 0..L {
+            if l > 0 {
+                for &bin in &bins {
+                    z[bin] *= Complex64::from_polar(1.0, phase());
+                }
+            }
+            let mut t = z;
+            ifft.process(&mut t);
+            signal.extend_from_slice(&t[T_U - GUARD..]);
+            signal.extend_from_slice(&t);
+        }
+    }
+
+    // about 4 standard deviations at full scale
+    let scale = 127.5 / 4.0 / (bins.len() as f64).sqrt();
+    signal
+        .iter()
+        .flat_map(|s| [s.re, s.im])
+        .map(|x| (127.5 + x * scale).round().clamp(0.0, 255.0) as u8)
+        .collect()
+}
+
+#[test]
+fn rtl_tcp_source_from_replay() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let (commands_tx, commands_rx) = mpsc::channel();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        replay(stream, &recording(4)[..], commands_tx).unwrap()
+    });
+
+    let mut source = new_rtltcp_source(address, Some(227.36), Some(30.0)).unwrap();
+    let (source_rx, source_t) = source.run();
+    let buffers = source_rx.iter().take_while(|b| !b.last).collect::<Vec<_>>();
+    source_t.join().unwrap();
+    assert!(server.join().unwrap() > 0);
+
+    let commands = (0..4)
+        .map(|_| commands_rx.recv_timeout(Duration::from_secs(1)).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        commands,
+        [
+            (SET_SAMPLE_RATE, 2_048_000),
+            (SET_GAIN_MODE, 1),
+            (SET_GAIN, 300),
+            (SET_FREQUENCY, 227_360_000),
+        ]
+    );
+
+    // every symbol after the phase reference symbol of whole frames
+    assert!(buffers.len() >= L - 1);
+    assert_eq!(buffers.len() % (L - 1), 0);
+    for (l, buffer) in buffers.iter().take(L - 1).enumerate() {
+        assert_eq!(buffer.bytes[2] as usize, l + 2);
+    }
+    assert!(source.sync().unwrap().locked);
+}
+
+#[test]
+fn rtl_tcp_errors_are_reported() {
+    // nothing listening
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    drop(listener);
+    let e = new_rtltcp_source(address.clone(), None, None)
+        .err()
+        .unwrap();
+    assert!(e.to_string().contains(&address));
+
+    // something else listening
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.write_all(b"HTTP/1.1 400\r\n\r\n").unwrap();
+    });
+    let e = new_rtltcp_source(address, None, None).err().unwrap();
+    assert_eq!(e.kind(), ErrorKind::InvalidData);
+    server.join().unwrap();
+}